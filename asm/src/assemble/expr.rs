@@ -0,0 +1,240 @@
+//! Tokens and constant expressions for operands and `.if`
+
+use std::collections::HashMap;
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Token {
+    Number(i64),
+    /// Names, registers and `.local` labels
+    Ident(String),
+    Punct(&'static str),
+}
+
+/// Longest first, so `<<` is not read as two `<`
+const PUNCTS: [&str; 24] = [
+    "<<", ">>", "<=", ">=", "==", "!=", "&&", "||", "+", "-", "*", "/", "%", "&", "|", "^", "~",
+    "!", "<", ">", "(", ")", "[", "]",
+];
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Token::Number(value) => write!(f, "{}", value),
+            Token::Ident(name) => write!(f, "{}", name),
+            Token::Punct(punct) => write!(f, "{}", punct),
+        }
+    }
+}
+
+pub fn is_ident_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '_' || c == '.'
+}
+
+pub fn tokenize(text: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    let mut rest = text.trim_start();
+    while let Some(c) = rest.chars().next() {
+        if is_ident_char(c) {
+            let end = rest.find(|c| !is_ident_char(c)).unwrap_or(rest.len());
+            let word = &rest[..end];
+            if c.is_ascii_digit() {
+                tokens.push(Token::Number(number(word)?));
+            } else {
+                tokens.push(Token::Ident(word.to_string()));
+            }
+            rest = &rest[end..];
+        } else {
+            let punct = PUNCTS
+                .iter()
+                .find(|punct| rest.starts_with(*punct))
+                .ok_or_else(|| format!("unexpected `{}`", c))?;
+            tokens.push(Token::Punct(punct));
+            rest = &rest[punct.len()..];
+        }
+        rest = rest.trim_start();
+    }
+    Ok(tokens)
+}
+
+/// Decimal, `0x` hex or `0b` binary, with optional `_` separators
+fn number(word: &str) -> Result<i64, String> {
+    let digits = word.replace('_', "");
+    let parsed = match digits.get(..2) {
+        Some("0x" | "0X") => i64::from_str_radix(&digits[2..], 16),
+        Some("0b" | "0B") => i64::from_str_radix(&digits[2..], 2),
+        _ => digits.parse(),
+    };
+    parsed.map_err(|_| format!("invalid number `{}`", word))
+}
+
+/// Evaluate with C precedence, comparisons and logic give 0 or 1
+pub fn evaluate(tokens: &[Token], constants: &HashMap<String, i64>) -> Result<i64, String> {
+    let mut parser = Parser {
+        tokens,
+        pos: 0,
+        constants,
+    };
+    let value = parser.binary(0)?;
+    match parser.tokens.get(parser.pos) {
+        Some(token) => Err(format!("unexpected `{}`", token)),
+        None => Ok(value),
+    }
+}
+
+struct Parser<'a> {
+    tokens: &'a [Token],
+    pos: usize,
+    constants: &'a HashMap<String, i64>,
+}
+
+fn precedence(op: &str) -> Option<u8> {
+    let precedence = match op {
+        "||" => 1,
+        "&&" => 2,
+        "|" => 3,
+        "^" => 4,
+        "&" => 5,
+        "==" | "!=" => 6,
+        "<" | "<=" | ">" | ">=" => 7,
+        "<<" | ">>" => 8,
+        "+" | "-" => 9,
+        "*" | "/" | "%" => 10,
+        _ => return None,
+    };
+    Some(precedence)
+}
+
+impl Parser<'_> {
+    fn next(&mut self) -> Option<&Token> {
+        let token = self.tokens.get(self.pos);
+        self.pos += 1;
+        token
+    }
+
+    fn binary(&mut self, min: u8) -> Result<i64, String> {
+        let mut lhs = self.unary()?;
+        while let Some(Token::Punct(op)) = self.tokens.get(self.pos) {
+            let Some(precedence) = precedence(op).filter(|&p| p >= min) else {
+                break;
+            };
+            self.pos += 1;
+            let rhs = self.binary(precedence + 1)?;
+            lhs = apply(op, lhs, rhs)?;
+        }
+        Ok(lhs)
+    }
+
+    fn unary(&mut self) -> Result<i64, String> {
+        match self.next().cloned() {
+            Some(Token::Number(value)) => Ok(value),
+            Some(Token::Ident(name)) => match self.constants.get(&name) {
+                Some(&value) => Ok(value),
+                None => Err(format!("`{}` is not a constant", name)),
+            },
+            Some(Token::Punct("-")) => Ok(self.unary()?.wrapping_neg()),
+            Some(Token::Punct("~")) => Ok(!self.unary()?),
+            Some(Token::Punct("!")) => Ok((self.unary()? == 0) as i64),
+            Some(Token::Punct("(")) => {
+                let value = self.binary(0)?;
+                match self.next() {
+                    Some(Token::Punct(")")) => Ok(value),
+                    _ => Err("expected `)`".to_string()),
+                }
+            }
+            Some(token) => Err(format!("unexpected `{}`", token)),
+            None => Err("expected a value".to_string()),
+        }
+    }
+}
+
+fn apply(op: &str, lhs: i64, rhs: i64) -> Result<i64, String> {
+    let value = match op {
+        "||" => (lhs != 0 || rhs != 0) as i64,
+        "&&" => (lhs != 0 && rhs != 0) as i64,
+        "|" => lhs | rhs,
+        "^" => lhs ^ rhs,
+        "&" => lhs & rhs,
+        "==" => (lhs == rhs) as i64,
+        "!=" => (lhs != rhs) as i64,
+        "<" => (lhs < rhs) as i64,
+        "<=" => (lhs <= rhs) as i64,
+        ">" => (lhs > rhs) as i64,
+        ">=" => (lhs >= rhs) as i64,
+        "<<" | ">>" => {
+            let shift = u32::try_from(rhs)
+                .ok()
+                .filter(|&shift| shift < 64)
+                .ok_or_else(|| format!("shift by {} out of range", rhs))?;
+            if op == "<<" {
+                lhs << shift
+            } else {
+                lhs >> shift
+            }
+        }
+        "+" => lhs.wrapping_add(rhs),
+        "-" => lhs.wrapping_sub(rhs),
+        "*" => lhs.wrapping_mul(rhs),
+        "/" | "%" if rhs == 0 => return Err("division by zero".to_string()),
+        "/" => lhs.wrapping_div(rhs),
+        "%" => lhs.wrapping_rem(rhs),
+        _ => unreachable!("`{}` has a precedence", op),
+    };
+    Ok(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn eval(text: &str) -> Result<i64, String> {
+        let constants = HashMap::from([("SIZE".to_string(), 0x20)]);
+        evaluate(&tokenize(text)?, &constants)
+    }
+
+    #[test]
+    fn tokens() {
+        let tokens = tokenize("[0x10 + .loop]<<2").expect("valid tokens");
+        assert_eq!(
+            vec![
+                Token::Punct("["),
+                Token::Number(0x10),
+                Token::Punct("+"),
+                Token::Ident(".loop".to_string()),
+                Token::Punct("]"),
+                Token::Punct("<<"),
+                Token::Number(2),
+            ],
+            tokens
+        );
+        assert_eq!(Err("invalid number `0xzz`".to_string()), tokenize("0xzz"));
+        assert_eq!(Err("unexpected `$`".to_string()), tokenize("$10"));
+    }
+
+    #[test]
+    fn expressions() {
+        assert_eq!(Ok(7), eval("1 + 2 * 3"));
+        assert_eq!(Ok(9), eval("(1 + 2) * 3"));
+        assert_eq!(Ok(0x40), eval("SIZE << 1"));
+        assert_eq!(Ok(1), eval("SIZE == 0x20 && 0b11 > 2"));
+        assert_eq!(Ok(0), eval("!SIZE"));
+        assert_eq!(Ok(-1), eval("~0"));
+        assert_eq!(Ok(1_000), eval("1_000"));
+    }
+
+    #[test]
+    fn expression_errors() {
+        assert_eq!(
+            Err("`OTHER` is not a constant".to_string()),
+            eval("OTHER + 1")
+        );
+        assert_eq!(
+            Err("division by zero".to_string()),
+            eval("1 / (SIZE - 0x20)")
+        );
+        assert_eq!(Err("shift by 64 out of range".to_string()), eval("1 << 64"));
+        assert_eq!(Err("expected `)`".to_string()), eval("(1 + 2"));
+        assert_eq!(Err("unexpected `2`".to_string()), eval("1 2 3"));
+        assert_eq!(Err("expected a value".to_string()), eval(""));
+    }
+}
@@ -0,0 +1,925 @@
+//! Text assembler front end
+//!
+//! Source is line based, `;` starts a comment. A line holds any number of
+//! `label:` definitions, then an instruction, a macro use or a directive.
+//! Instructions are encoded with [`encode`], labels are offsets from the start
+//! of the program. A label starting with `.` is local to the global label
+//! before it, so `.loop` may be reused under each routine.
+//!
+//! | Directive            | Effect                                          |
+//! |----------------------|-------------------------------------------------|
+//! | `.equ NAME, expr`    | define a constant                               |
+//! | `.include "path"`    | assemble a file, relative to the including file |
+//! | `.if expr`           | assemble the lines up to `.else` or `.endif`    |
+//! |                      | if the constant expression is not zero          |
+//! | `.macro name a, b`   | define a macro up to `.endm`, its body uses     |
+//! |                      | `\a` for parameters and `\@` for a number       |
+//! |                      | unique to each expansion                        |
+
+use crate::encode::{encode, Operand, Part, Value};
+use crate::Reg;
+use expr::{evaluate, is_ident_char, tokenize, Token};
+use std::collections::HashMap;
+use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+mod expr;
+
+/// Includes and macro expansions deeper than this are assumed to recurse
+const MAX_DEPTH: usize = 64;
+
+/// Line in a source file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Position {
+    pub file: PathBuf,
+    /// Starting from 1
+    pub line: usize,
+}
+
+/// How a line was reached from the position after it in a [`Location`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Via {
+    Include,
+    /// Body of the named macro
+    Macro(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Location {
+    pub at: Position,
+    /// The `.include` lines and macro uses leading to `at`, innermost first
+    pub via: Vec<(Via, Position)>,
+}
+
+/// Assembled bytes, with where each statement came from
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Program {
+    pub bytes: Vec<u8>,
+    /// Address of each statement and its location, in address order
+    pub lines: Vec<(u16, Location)>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AsmError {
+    /// `None` if the main file could not be read
+    pub location: Option<Location>,
+    pub message: String,
+}
+
+impl fmt::Display for Position {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.file.display(), self.line)
+    }
+}
+
+impl fmt::Display for AsmError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let Some(location) = &self.location else {
+            return write!(f, "{}", self.message);
+        };
+        write!(f, "{}: {}", location.at, self.message)?;
+        for (via, position) in &location.via {
+            match via {
+                Via::Include => write!(f, "\n  included from {}", position)?,
+                Via::Macro(name) => write!(f, "\n  in macro `{}` used at {}", name, position)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for AsmError {}
+
+impl AsmError {
+    fn new(location: &Location, message: impl Into<String>) -> Self {
+        Self {
+            location: Some(location.clone()),
+            message: message.into(),
+        }
+    }
+}
+
+/// Assemble a file and everything it includes from disk
+pub fn assemble_file(path: impl AsRef<Path>) -> Result<Program, AsmError> {
+    Assembler::new(|path: &Path| std::fs::read_to_string(path)).assemble(path.as_ref())
+}
+
+/// Assembles one program, reading source files through `read`
+pub struct Assembler<F> {
+    read: F,
+    constants: HashMap<String, i64>,
+    macros: HashMap<String, Rc<Macro>>,
+    /// Offsets by full name, `global.local` for local labels
+    labels: HashMap<String, usize>,
+    /// Last global label, owning local labels
+    scope: Option<String>,
+    statements: Vec<Statement>,
+    len: usize,
+    expansions: usize,
+}
+
+struct Macro {
+    params: Vec<String>,
+    body: Vec<(Position, String)>,
+}
+
+/// Encoded line, labels resolved once all are defined
+struct Statement {
+    location: Location,
+    parts: Vec<Part<String>>,
+}
+
+/// Open `.if`
+struct Condition {
+    location: Location,
+    /// Lines are being assembled
+    active: bool,
+    /// A branch has been taken, or the enclosing lines are skipped
+    taken: bool,
+    has_else: bool,
+}
+
+impl<F: FnMut(&Path) -> io::Result<String>> Assembler<F> {
+    pub fn new(read: F) -> Self {
+        Self {
+            read,
+            constants: HashMap::new(),
+            macros: HashMap::new(),
+            labels: HashMap::new(),
+            scope: None,
+            statements: Vec::new(),
+            len: 0,
+            expansions: 0,
+        }
+    }
+
+    pub fn assemble(mut self, path: &Path) -> Result<Program, AsmError> {
+        let source = (self.read)(path).map_err(|err| AsmError {
+            location: None,
+            message: format!("cannot read {}: {}", path.display(), err),
+        })?;
+        self.source(path, &source, &[])?;
+        self.link()
+    }
+
+    fn source(
+        &mut self,
+        path: &Path,
+        source: &str,
+        via: &[(Via, Position)],
+    ) -> Result<(), AsmError> {
+        let lines: Vec<_> = source
+            .lines()
+            .enumerate()
+            .map(|(index, line)| {
+                let position = Position {
+                    file: path.to_path_buf(),
+                    line: index + 1,
+                };
+                (position, line.to_string())
+            })
+            .collect();
+        self.lines(&lines, via)
+    }
+
+    /// Conditionals must be closed within the same file or macro body
+    fn lines(
+        &mut self,
+        lines: &[(Position, String)],
+        via: &[(Via, Position)],
+    ) -> Result<(), AsmError> {
+        let mut conditions: Vec<Condition> = Vec::new();
+        let mut lines = lines.iter();
+        while let Some((position, line)) = lines.next() {
+            let location = Location {
+                at: position.clone(),
+                via: via.to_vec(),
+            };
+            let text = strip_comment(line);
+            let (word, rest) = split_word(text);
+            let active = conditions.last().is_none_or(|cond| cond.active);
+            match word {
+                ".if" => {
+                    let outer = active;
+                    let active = outer && self.condition(rest, &location)?;
+                    conditions.push(Condition {
+                        location,
+                        active,
+                        taken: active || !outer,
+                        has_else: false,
+                    });
+                }
+                ".else" => {
+                    let Some(cond) = conditions.last_mut() else {
+                        return Err(AsmError::new(&location, "`.else` without `.if`"));
+                    };
+                    if cond.has_else {
+                        return Err(AsmError::new(&location, "duplicate `.else`"));
+                    }
+                    cond.active = !cond.taken;
+                    cond.taken = true;
+                    cond.has_else = true;
+                }
+                ".endif" => {
+                    if conditions.pop().is_none() {
+                        return Err(AsmError::new(&location, "`.endif` without `.if`"));
+                    }
+                }
+                _ if !active => (),
+                ".macro" => {
+                    let mut body = Vec::new();
+                    loop {
+                        let Some((position, line)) = lines.next() else {
+                            return Err(AsmError::new(&location, "`.macro` without `.endm`"));
+                        };
+                        match split_word(strip_comment(line)).0 {
+                            ".endm" => break,
+                            ".macro" => {
+                                let location = Location {
+                                    at: position.clone(),
+                                    via: via.to_vec(),
+                                };
+                                return Err(AsmError::new(&location, "nested `.macro`"));
+                            }
+                            _ => body.push((position.clone(), line.clone())),
+                        }
+                    }
+                    self.define_macro(rest, body, &location)?;
+                }
+                ".endm" => return Err(AsmError::new(&location, "`.endm` without `.macro`")),
+                _ => self.statement(text, &location)?,
+            }
+        }
+        match conditions.last() {
+            Some(cond) => Err(AsmError::new(&cond.location, "`.if` without `.endif`")),
+            None => Ok(()),
+        }
+    }
+
+    fn condition(&self, expr: &str, location: &Location) -> Result<bool, AsmError> {
+        let value = tokenize(expr).and_then(|tokens| evaluate(&tokens, &self.constants));
+        value
+            .map(|value| value != 0)
+            .map_err(|message| AsmError::new(location, message))
+    }
+
+    fn define_macro(
+        &mut self,
+        header: &str,
+        body: Vec<(Position, String)>,
+        location: &Location,
+    ) -> Result<(), AsmError> {
+        let (name, params) = split_word(header);
+        let params: Vec<_> = split_operands(params)
+            .into_iter()
+            .map(str::to_string)
+            .collect();
+        for name in params.iter().map(String::as_str).chain([name]) {
+            if !is_name(name) {
+                return Err(AsmError::new(location, format!("invalid name `{}`", name)));
+            }
+        }
+        let name = name.to_string();
+        if self.macros.contains_key(&name) {
+            let message = format!("duplicate macro `{}`", name);
+            return Err(AsmError::new(location, message));
+        }
+        self.macros.insert(name, Rc::new(Macro { params, body }));
+        Ok(())
+    }
+
+    /// Labels, then an instruction, macro use or directive
+    fn statement(&mut self, mut text: &str, location: &Location) -> Result<(), AsmError> {
+        while let Some((label, rest)) = split_label(text) {
+            self.define_label(label, location)?;
+            text = rest.trim_start();
+        }
+        let (word, rest) = split_word(text);
+        match word {
+            "" => Ok(()),
+            ".equ" => self.define_constant(rest, location),
+            ".include" => self.include(rest, location),
+            ".if" | ".else" | ".endif" | ".macro" | ".endm" => {
+                let message = format!("`{}` must start a line", word);
+                Err(AsmError::new(location, message))
+            }
+            _ if word.starts_with('.') => {
+                let message = format!("unknown directive `{}`", word);
+                Err(AsmError::new(location, message))
+            }
+            _ => match self.macros.get(word) {
+                Some(body) => self.expand(word, &Rc::clone(body), rest, location),
+                None => self.instruction(word, rest, location),
+            },
+        }
+    }
+
+    fn define_label(&mut self, label: &str, location: &Location) -> Result<(), AsmError> {
+        let name = self.label_name(label, location)?;
+        if !label.starts_with('.') {
+            self.scope = Some(name.clone());
+        }
+        if self.labels.insert(name, self.len).is_some() {
+            let message = format!("duplicate label `{}`", label);
+            return Err(AsmError::new(location, message));
+        }
+        Ok(())
+    }
+
+    /// Local labels are qualified by the current global label
+    fn label_name(&self, label: &str, location: &Location) -> Result<String, AsmError> {
+        if !label.starts_with('.') {
+            return Ok(label.to_string());
+        }
+        match &self.scope {
+            Some(scope) => Ok(format!("{}{}", scope, label)),
+            None => {
+                let message = format!("local label `{}` before any global label", label);
+                Err(AsmError::new(location, message))
+            }
+        }
+    }
+
+    fn define_constant(&mut self, args: &str, location: &Location) -> Result<(), AsmError> {
+        let Some((name, expr)) = args.split_once(',') else {
+            return Err(AsmError::new(location, "expected a name and a value"));
+        };
+        let name = name.trim();
+        if !is_name(name) {
+            return Err(AsmError::new(location, format!("invalid name `{}`", name)));
+        }
+        let value = tokenize(expr)
+            .and_then(|tokens| evaluate(&tokens, &self.constants))
+            .map_err(|message| AsmError::new(location, message))?;
+        if self.constants.insert(name.to_string(), value).is_some() {
+            let message = format!("duplicate constant `{}`", name);
+            return Err(AsmError::new(location, message));
+        }
+        Ok(())
+    }
+
+    fn include(&mut self, args: &str, location: &Location) -> Result<(), AsmError> {
+        let Some(path) = args
+            .strip_prefix('"')
+            .and_then(|path| path.strip_suffix('"'))
+        else {
+            return Err(AsmError::new(location, "expected a quoted path"));
+        };
+        let path = match location.at.file.parent() {
+            Some(dir) => dir.join(path),
+            None => PathBuf::from(path),
+        };
+        let via = nested(Via::Include, location)?;
+        let source = (self.read)(&path).map_err(|err| {
+            let message = format!("cannot read {}: {}", path.display(), err);
+            AsmError::new(location, message)
+        })?;
+        self.source(&path, &source, &via)
+    }
+
+    fn expand(
+        &mut self,
+        name: &str,
+        body: &Macro,
+        args: &str,
+        location: &Location,
+    ) -> Result<(), AsmError> {
+        let args = split_operands(args);
+        if args.len() != body.params.len() {
+            let count = body.params.len();
+            let plural = if count == 1 { "" } else { "s" };
+            let message = format!("macro `{}` takes {} argument{}", name, count, plural);
+            return Err(AsmError::new(location, message));
+        }
+        let via = nested(Via::Macro(name.to_string()), location)?;
+        self.expansions += 1;
+        let unique = self.expansions.to_string();
+        let mut lines = Vec::new();
+        for (position, line) in &body.body {
+            let line = substitute(line, &body.params, &args, &unique).map_err(|message| {
+                let location = Location {
+                    at: position.clone(),
+                    via: via.clone(),
+                };
+                AsmError::new(&location, message)
+            })?;
+            lines.push((position.clone(), line));
+        }
+        self.lines(&lines, &via)
+    }
+
+    fn instruction(
+        &mut self,
+        mnemonic: &str,
+        args: &str,
+        location: &Location,
+    ) -> Result<(), AsmError> {
+        let operands = split_operands(args)
+            .into_iter()
+            .map(|operand| self.operand(operand, location))
+            .collect::<Result<Vec<_>, _>>()?;
+        let parts =
+            encode(mnemonic, &operands).map_err(|err| AsmError::new(location, err.message))?;
+        self.len += parts.iter().map(Part::size).sum::<usize>();
+        if self.len > u16::MAX as usize + 1 {
+            return Err(AsmError::new(location, "program is larger than memory"));
+        }
+        self.statements.push(Statement {
+            location: location.clone(),
+            parts,
+        });
+        Ok(())
+    }
+
+    fn operand(&self, text: &str, location: &Location) -> Result<Operand<String>, AsmError> {
+        let tokens = tokenize(text).map_err(|message| AsmError::new(location, message))?;
+        let operand = match tokens.as_slice() {
+            [Token::Punct("["), inner @ .., Token::Punct("]")] => match inner {
+                [value @ .., Token::Punct("+"), reg] if register(reg).is_some() => {
+                    let reg = register(reg).expect("checked");
+                    Operand::Offset(self.value(value, location)?, reg)
+                }
+                [reg] if register(reg).is_some() => Operand::Ptr(register(reg).expect("checked")),
+                _ => Operand::Mem(self.value(inner, location)?),
+            },
+            [reg] if register(reg).is_some() => Operand::Reg(register(reg).expect("checked")),
+            _ => Operand::Value(self.value(&tokens, location)?),
+        };
+        Ok(operand)
+    }
+
+    /// A lone name that is not a constant is a label
+    fn value(&self, tokens: &[Token], location: &Location) -> Result<Value<String>, AsmError> {
+        if let [Token::Ident(name)] = tokens {
+            if !self.constants.contains_key(name) {
+                return Ok(Value::Label(self.label_name(name, location)?));
+            }
+        }
+        let value = evaluate(tokens, &self.constants)
+            .map_err(|message| AsmError::new(location, message))?;
+        match u16::try_from(value) {
+            Ok(value) => Ok(Value::Lit { value, wide: false }),
+            Err(_) => {
+                let message = format!("{} does not fit in 16 bits", value);
+                Err(AsmError::new(location, message))
+            }
+        }
+    }
+
+    fn link(self) -> Result<Program, AsmError> {
+        let mut bytes = Vec::with_capacity(self.len);
+        let mut lines = Vec::with_capacity(self.statements.len());
+        for statement in self.statements {
+            lines.push((bytes.len() as u16, statement.location.clone()));
+            for part in &statement.parts {
+                match part {
+                    Part::Byte(byte) => bytes.push(*byte),
+                    Part::Label(name) => {
+                        let Some(&addr) = self.labels.get(name) else {
+                            let message = format!("undefined label `{}`", name);
+                            return Err(AsmError::new(&statement.location, message));
+                        };
+                        bytes.extend((addr as u16).to_be_bytes());
+                    }
+                }
+            }
+        }
+        Ok(Program { bytes, lines })
+    }
+}
+
+/// Location one include or macro level below `location`
+fn nested(via: Via, location: &Location) -> Result<Vec<(Via, Position)>, AsmError> {
+    if location.via.len() >= MAX_DEPTH {
+        return Err(AsmError::new(
+            location,
+            "includes or macros nested too deeply",
+        ));
+    }
+    let mut nested = vec![(via, location.at.clone())];
+    nested.extend(location.via.iter().cloned());
+    Ok(nested)
+}
+
+fn register(token: &Token) -> Option<Reg> {
+    match token {
+        Token::Ident(name) => Reg::from_name(name),
+        _ => None,
+    }
+}
+
+fn is_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    matches!(chars.next(), Some(c) if c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// Trimmed line without its comment, `;` may appear in a quoted path
+fn strip_comment(line: &str) -> &str {
+    let mut quoted = false;
+    for (index, c) in line.char_indices() {
+        match c {
+            '"' => quoted = !quoted,
+            ';' if !quoted => return line[..index].trim(),
+            _ => (),
+        }
+    }
+    line.trim()
+}
+
+/// First word and the trimmed rest
+fn split_word(text: &str) -> (&str, &str) {
+    match text.split_once(char::is_whitespace) {
+        Some((word, rest)) => (word, rest.trim()),
+        None => (text, ""),
+    }
+}
+
+/// `label:` at the start of `text`, and what follows it
+fn split_label(text: &str) -> Option<(&str, &str)> {
+    let end = text.find(|c| !is_ident_char(c)).unwrap_or(text.len());
+    let (label, rest) = text.split_at(end);
+    let rest = rest.strip_prefix(':')?;
+    let name = label.strip_prefix('.').unwrap_or(label);
+    is_name(name).then_some((label, rest))
+}
+
+/// Comma separated, ignoring commas in brackets or parentheses
+fn split_operands(text: &str) -> Vec<&str> {
+    if text.trim().is_empty() {
+        return Vec::new();
+    }
+    let mut operands = Vec::new();
+    let mut depth = 0usize;
+    let mut start = 0;
+    for (index, c) in text.char_indices() {
+        match c {
+            '[' | '(' => depth += 1,
+            ']' | ')' => depth = depth.saturating_sub(1),
+            ',' if depth == 0 => {
+                operands.push(text[start..index].trim());
+                start = index + 1;
+            }
+            _ => (),
+        }
+    }
+    operands.push(text[start..].trim());
+    operands
+}
+
+/// Replace `\param` with its argument and `\@` with `unique`
+fn substitute(
+    line: &str,
+    params: &[String],
+    args: &[&str],
+    unique: &str,
+) -> Result<String, String> {
+    let mut out = String::with_capacity(line.len());
+    let mut rest = line;
+    while let Some(index) = rest.find('\\') {
+        out.push_str(&rest[..index]);
+        rest = &rest[index + 1..];
+        if let Some(after) = rest.strip_prefix('@') {
+            out.push_str(unique);
+            rest = after;
+            continue;
+        }
+        let end = rest
+            .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+            .unwrap_or(rest.len());
+        let (name, after) = rest.split_at(end);
+        match params.iter().position(|param| param == name) {
+            Some(index) => out.push_str(args[index]),
+            None => return Err(format!("unknown macro parameter `\\{}`", name)),
+        }
+        rest = after;
+    }
+    out.push_str(rest);
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{opcode, register};
+
+    fn assemble_program(files: &[(&str, &str)], main: &str) -> Result<Program, AsmError> {
+        let files: HashMap<PathBuf, String> = files
+            .iter()
+            .map(|(path, source)| (PathBuf::from(path), source.to_string()))
+            .collect();
+        let read = |path: &Path| {
+            files
+                .get(path)
+                .cloned()
+                .ok_or_else(|| io::Error::from(io::ErrorKind::NotFound))
+        };
+        Assembler::new(read).assemble(Path::new(main))
+    }
+
+    fn assemble_files(files: &[(&str, &str)], main: &str) -> Result<Vec<u8>, AsmError> {
+        assemble_program(files, main).map(|program| program.bytes)
+    }
+
+    fn assemble(source: &str) -> Result<Vec<u8>, AsmError> {
+        assemble_files(&[("main.asm", source)], "main.asm")
+    }
+
+    fn error(source: &str) -> String {
+        assemble(source).expect_err("invalid program").to_string()
+    }
+
+    fn position(file: &str, line: usize) -> Position {
+        Position {
+            file: PathBuf::from(file),
+            line,
+        }
+    }
+
+    #[test]
+    fn assemble_instructions() {
+        let source = "
+            start:  mov 0x01f0, cd  ; load the stack
+                    mov [cd], a
+                    MOV [0x10 + ef], B
+                    dw start, end
+            end:    hlt
+        ";
+        let expected = vec![
+            opcode::MOV_LIT_REG_WIDE,
+            0x01,
+            0xf0,
+            register::CD,
+            opcode::MOV_REG_PTR_REG,
+            register::CD,
+            register::A,
+            opcode::MOV_LIT_OFF_REG,
+            0x00,
+            0x10,
+            register::EF,
+            register::B,
+            0x00,
+            0x00,
+            0x00,
+            0x10,
+            opcode::HLT,
+        ];
+        assert_eq!(Ok(expected), assemble(source));
+    }
+
+    #[test]
+    fn local_labels() {
+        let source = "
+            first:
+            .loop:  dw .loop
+            second: nop
+            .loop:  dw .loop, first.loop
+        ";
+        assert_eq!(
+            Ok(vec![0x00, 0x00, opcode::NOP, 0x00, 0x03, 0x00, 0x00]),
+            assemble(source)
+        );
+        assert_eq!(
+            "main.asm:1: local label `.loop` before any global label",
+            error(".loop: hlt")
+        );
+        assert_eq!(
+            "main.asm:3: duplicate label `.x`",
+            error("a:\n.x: nop\n.x: nop")
+        );
+    }
+
+    #[test]
+    fn constants_and_conditionals() {
+        let source = "
+            .equ DEBUG, 1
+            .equ BASE, 0x0100
+            .if DEBUG && BASE >= 0x100
+                mov BASE + 2, ab
+                .if !DEBUG
+                    nop
+                .else
+                    db BASE >> 8
+                .endif
+            .else
+                hlt
+            .endif
+            .if 0
+                .include \"missing.asm\"
+                .bogus
+            .endif
+        ";
+        assert_eq!(
+            Ok(vec![
+                opcode::MOV_LIT_REG_WIDE,
+                0x01,
+                0x02,
+                register::AB,
+                0x01
+            ]),
+            assemble(source)
+        );
+    }
+
+    #[test]
+    fn conditional_errors() {
+        assert_eq!(
+            "main.asm:2: `.if` without `.endif`",
+            error("nop\n.if 1\nnop")
+        );
+        assert_eq!("main.asm:1: `.else` without `.if`", error(".else"));
+        assert_eq!("main.asm:1: `.endif` without `.if`", error(".endif"));
+        assert_eq!(
+            "main.asm:3: duplicate `.else`",
+            error(".if 1\n.else\n.else\n.endif")
+        );
+        assert_eq!(
+            "main.asm:1: `UNSET` is not a constant",
+            error(".if UNSET\n.endif")
+        );
+        assert_eq!("main.asm:1: `.if` must start a line", error("a: .if 1"));
+        assert_eq!(
+            "main.asm:2: duplicate constant `A1`",
+            error(".equ A1, 1\n.equ A1, 2")
+        );
+    }
+
+    #[test]
+    fn macros() {
+        let source = "
+            .macro load value, reg
+                mov \\value, \\reg
+            .endm
+            .macro spin
+            spin\\@: dw spin\\@
+            .endm
+                load 0x12, a
+                load [0x10 + ab], b
+                spin
+                spin
+        ";
+        let expected = vec![
+            opcode::MOV_LIT_REG,
+            0x12,
+            register::A,
+            opcode::MOV_LIT_OFF_REG,
+            0x00,
+            0x10,
+            register::AB,
+            register::B,
+            0x00,
+            0x08,
+            0x00,
+            0x0a,
+        ];
+        assert_eq!(Ok(expected), assemble(source));
+    }
+
+    #[test]
+    fn macro_errors() {
+        let source = ".macro twice op\n\\op\n\\op\njmp\n.endm\ntwice nop";
+        let err = assemble(source).expect_err("unknown instruction");
+        assert_eq!(
+            Some(Location {
+                at: position("main.asm", 4),
+                via: vec![(Via::Macro("twice".to_string()), position("main.asm", 6))],
+            }),
+            err.location
+        );
+        assert_eq!(
+            "main.asm:4: unknown instruction `jmp`\n  in macro `twice` used at main.asm:6",
+            err.to_string()
+        );
+        assert_eq!(
+            "main.asm:3: macro `m` takes 1 argument",
+            error(".macro m a\n.endm\nm 1, 2")
+        );
+        assert_eq!(
+            "main.asm:2: unknown macro parameter `\\b`\n  in macro `m` used at main.asm:4",
+            error(".macro m a\nnop \\b\n.endm\nm 1")
+        );
+        assert_eq!(
+            "main.asm:1: `.macro` without `.endm`",
+            error(".macro m\nnop")
+        );
+        assert_eq!(
+            "main.asm:2: nested `.macro`",
+            error(".macro m\n.macro n\n.endm")
+        );
+        assert_eq!("main.asm:1: `.endm` without `.macro`", error(".endm"));
+        let recursive = error(".macro m\nm\n.endm\nm");
+        assert!(recursive.starts_with("main.asm:2: includes or macros nested too deeply"));
+    }
+
+    #[test]
+    fn includes() {
+        let files = [
+            ("src/main.asm", ".include \"lib/util.asm\"\nhlt"),
+            ("src/lib/util.asm", ".equ VALUE, 0x12\nmov VALUE, a"),
+        ];
+        assert_eq!(
+            Ok(vec![opcode::MOV_LIT_REG, 0x12, register::A, opcode::HLT]),
+            assemble_files(&files, "src/main.asm")
+        );
+    }
+
+    #[test]
+    fn source_lines() {
+        let files = [
+            (
+                "main.asm",
+                ".macro pair\nnop\nnop\n.endm\nstart:\npair\n.include \"a.asm\"",
+            ),
+            ("a.asm", "mov 0x12, a\nhlt"),
+        ];
+        let program = assemble_program(&files, "main.asm").expect("valid program");
+        let pair = |line| Location {
+            at: position("main.asm", line),
+            via: vec![(Via::Macro("pair".to_string()), position("main.asm", 6))],
+        };
+        let included = |line| Location {
+            at: position("a.asm", line),
+            via: vec![(Via::Include, position("main.asm", 7))],
+        };
+        assert_eq!(
+            vec![
+                (0, pair(2)),
+                (1, pair(3)),
+                (2, included(1)),
+                (5, included(2))
+            ],
+            program.lines
+        );
+    }
+
+    #[test]
+    fn include_chain_errors() {
+        let files = [
+            ("main.asm", "nop\n.include \"lib/a.asm\""),
+            ("lib/a.asm", "; helpers\n.include \"b.asm\""),
+            ("lib/b.asm", "nop\nmov a, cd"),
+        ];
+        let err = assemble_files(&files, "main.asm").expect_err("register widths differ");
+        assert_eq!(
+            Some(Location {
+                at: position("lib/b.asm", 2),
+                via: vec![
+                    (Via::Include, position("lib/a.asm", 2)),
+                    (Via::Include, position("main.asm", 2)),
+                ],
+            }),
+            err.location
+        );
+        assert_eq!(
+            "lib/b.asm:2: register widths differ\n  \
+             included from lib/a.asm:2\n  \
+             included from main.asm:2",
+            err.to_string()
+        );
+
+        let files = [
+            ("main.asm", ".include \"a.asm\""),
+            ("a.asm", "mov [missing], a"),
+        ];
+        assert_eq!(
+            "a.asm:1: undefined label `missing`\n  included from main.asm:1",
+            assemble_files(&files, "main.asm")
+                .expect_err("undefined label")
+                .to_string()
+        );
+
+        let files = [("main.asm", "nop\n.include \"gone.asm\"")];
+        let err = assemble_files(&files, "main.asm").expect_err("missing include");
+        assert_eq!(position("main.asm", 2), err.location.expect("located").at);
+
+        let files = [("main.asm", ".include \"main.asm\"")];
+        let err = assemble_files(&files, "main.asm").expect_err("recursive include");
+        assert_eq!("includes or macros nested too deeply", err.message);
+        assert_eq!(MAX_DEPTH, err.location.expect("located").via.len());
+    }
+
+    #[test]
+    fn operand_errors() {
+        assert_eq!(
+            "main.asm:1: 65536 does not fit in 16 bits",
+            error("dw 0x10000")
+        );
+        assert_eq!("main.asm:1: unknown directive `.org`", error(".org 0x100"));
+        assert_eq!(
+            "main.asm:2: expected an 8 bit value",
+            error("nop\nmov 0x100, a")
+        );
+        assert_eq!(
+            "main.asm:1: expected a quoted path",
+            error(".include lib.asm")
+        );
+        assert_eq!(
+            "cannot read missing.asm: entity not found",
+            assemble_files(&[], "missing.asm")
+                .expect_err("no file")
+                .to_string()
+        );
+    }
+}
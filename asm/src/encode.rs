@@ -0,0 +1,430 @@
+use crate::{opcode, Reg};
+use std::fmt;
+
+/// Operand as written in assembly, with labels of type `L`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Operand<L> {
+    Value(Value<L>),
+    Reg(Reg),
+    /// `[value]`
+    Mem(Value<L>),
+    /// `[reg]`
+    Ptr(Reg),
+    /// `[value + reg]`
+    Offset(Value<L>, Reg),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Value<L> {
+    /// `wide` if written as a wide value, even when it fits in a byte
+    Lit {
+        value: u16,
+        wide: bool,
+    },
+    Label(L),
+}
+
+/// Encoded output, labels are resolved once every address is known
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Part<L> {
+    Byte(u8),
+    /// Address of a label, two bytes
+    Label(L),
+}
+
+/// Part of a statement an [`EncodeError`] refers to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorAt {
+    Mnemonic,
+    /// Operand by index, the value of `[value + reg]`
+    Operand(usize),
+    /// Register of an operand by index, the `reg` of `[value + reg]`
+    Register(usize),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EncodeError {
+    pub at: ErrorAt,
+    pub message: String,
+}
+
+impl<L> Part<L> {
+    pub fn size(&self) -> usize {
+        match self {
+            Part::Byte(_) => 1,
+            Part::Label(_) => 2,
+        }
+    }
+}
+
+impl<L> Value<L> {
+    /// Needs two bytes, labels are always addresses
+    pub fn is_wide(&self) -> bool {
+        match *self {
+            Value::Lit { value, wide } => wide || value > u8::MAX as u16,
+            Value::Label(_) => true,
+        }
+    }
+}
+
+impl EncodeError {
+    fn new(at: ErrorAt, message: impl Into<String>) -> Self {
+        Self {
+            at,
+            message: message.into(),
+        }
+    }
+}
+
+impl fmt::Display for EncodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl std::error::Error for EncodeError {}
+
+/// Encode one statement, choosing the operation from the operand kinds
+///
+/// Mnemonics ignore case. `db` and `dw` emit their values as bytes and
+/// wide values.
+pub fn encode<L: Clone>(
+    mnemonic: &str,
+    operands: &[Operand<L>],
+) -> Result<Vec<Part<L>>, EncodeError> {
+    let name = mnemonic.to_lowercase();
+    match (name.as_str(), operands) {
+        ("nop", []) => Ok(vec![Part::Byte(opcode::NOP)]),
+        ("hlt", []) => Ok(vec![Part::Byte(opcode::HLT)]),
+        ("nop" | "hlt", [_, ..]) => {
+            Err(EncodeError::new(ErrorAt::Operand(0), "unexpected operand"))
+        }
+        ("db", [_, ..]) => {
+            let mut parts = Vec::new();
+            for (index, operand) in operands.iter().enumerate() {
+                match operand {
+                    Operand::Value(value) => parts.push(byte(index, value)?),
+                    _ => {
+                        let at = ErrorAt::Operand(index);
+                        return Err(EncodeError::new(at, "expected a byte"));
+                    }
+                }
+            }
+            Ok(parts)
+        }
+        ("dw", [_, ..]) => {
+            let mut parts = Vec::new();
+            for (index, operand) in operands.iter().enumerate() {
+                match operand {
+                    Operand::Value(value) => parts.extend(wide(value)),
+                    _ => {
+                        let at = ErrorAt::Operand(index);
+                        return Err(EncodeError::new(at, "expected a wide value"));
+                    }
+                }
+            }
+            Ok(parts)
+        }
+        ("mov", [from, to]) => mov(from, to),
+        ("mul", [from, to]) => mul(from, to),
+        ("div", [from, to]) => divide(DIV, from, to),
+        ("mod", [from, to]) => divide(MOD, from, to),
+        ("cmp", [from, to]) => cmp(from, to),
+        ("mov" | "mul" | "div" | "mod" | "cmp", _) => Err(EncodeError::new(
+            ErrorAt::Mnemonic,
+            format!("{} takes a source and a destination", name),
+        )),
+        ("db" | "dw", []) => Err(EncodeError::new(ErrorAt::Mnemonic, "expected values")),
+        _ => Err(EncodeError::new(
+            ErrorAt::Mnemonic,
+            format!("unknown instruction `{}`", mnemonic),
+        )),
+    }
+}
+
+const FROM: usize = 0;
+const TO: usize = 1;
+
+/// `lit_reg`, `lit_reg_wide` and `reg_reg` operations
+const DIV: [u8; 3] = [
+    opcode::DIV_LIT_REG,
+    opcode::DIV_LIT_REG_WIDE,
+    opcode::DIV_REG_REG,
+];
+const MOD: [u8; 3] = [
+    opcode::MOD_LIT_REG,
+    opcode::MOD_LIT_REG_WIDE,
+    opcode::MOD_REG_REG,
+];
+
+fn mov<L: Clone>(from: &Operand<L>, to: &Operand<L>) -> Result<Vec<Part<L>>, EncodeError> {
+    let op = Part::Byte;
+    let parts = match (from, to) {
+        (Operand::Value(value), Operand::Reg(reg)) if reg.wide => {
+            let mut parts = vec![op(opcode::MOV_LIT_REG_WIDE)];
+            parts.extend(wide(value));
+            parts.push(register(reg));
+            parts
+        }
+        (Operand::Value(value), Operand::Reg(reg)) => {
+            vec![op(opcode::MOV_LIT_REG), byte(FROM, value)?, register(reg)]
+        }
+        (Operand::Reg(from), Operand::Reg(to)) => {
+            same_width(from, to)?;
+            vec![op(opcode::MOV_REG_REG), register(from), register(to)]
+        }
+        (Operand::Reg(reg), Operand::Mem(addr)) => {
+            let mut parts = vec![op(opcode::MOV_REG_MEM), register(reg)];
+            parts.extend(wide(addr));
+            parts
+        }
+        (Operand::Mem(addr), Operand::Reg(reg)) => {
+            let mut parts = vec![op(opcode::MOV_MEM_REG)];
+            parts.extend(wide(addr));
+            parts.push(register(reg));
+            parts
+        }
+        (Operand::Value(value), Operand::Mem(addr)) if value.is_wide() => {
+            let mut parts = vec![op(opcode::MOV_LIT_MEM_WIDE)];
+            parts.extend(wide(value));
+            parts.extend(wide(addr));
+            parts
+        }
+        (Operand::Value(value), Operand::Mem(addr)) => {
+            let mut parts = vec![op(opcode::MOV_LIT_MEM), byte(FROM, value)?];
+            parts.extend(wide(addr));
+            parts
+        }
+        (Operand::Ptr(ptr), Operand::Reg(reg)) => {
+            let ptr = wide_register(ErrorAt::Operand(FROM), ptr)?;
+            vec![op(opcode::MOV_REG_PTR_REG), ptr, register(reg)]
+        }
+        (Operand::Offset(addr, ptr), Operand::Reg(reg)) => {
+            let mut parts = vec![op(opcode::MOV_LIT_OFF_REG)];
+            parts.extend(wide(addr));
+            parts.push(wide_register(ErrorAt::Register(FROM), ptr)?);
+            parts.push(register(reg));
+            parts
+        }
+        _ => return Err(unsupported(TO, "destination")),
+    };
+    Ok(parts)
+}
+
+/// Compares the destination against the source, like `mov` without the move
+fn cmp<L: Clone>(from: &Operand<L>, to: &Operand<L>) -> Result<Vec<Part<L>>, EncodeError> {
+    let op = Part::Byte;
+    let Operand::Reg(reg) = to else {
+        return Err(unsupported(TO, "destination"));
+    };
+    let parts = match from {
+        Operand::Value(value) if reg.wide => {
+            let mut parts = vec![op(opcode::CMP_LIT_REG_WIDE)];
+            parts.extend(wide(value));
+            parts.push(register(reg));
+            parts
+        }
+        Operand::Value(value) => vec![op(opcode::CMP_LIT_REG), byte(FROM, value)?, register(reg)],
+        Operand::Reg(from) => {
+            same_width(from, reg)?;
+            vec![op(opcode::CMP_REG_REG), register(from), register(reg)]
+        }
+        Operand::Mem(addr) => {
+            let mut parts = vec![op(opcode::CMP_MEM_REG)];
+            parts.extend(wide(addr));
+            parts.push(register(reg));
+            parts
+        }
+        _ => return Err(unsupported(FROM, "source")),
+    };
+    Ok(parts)
+}
+
+/// A byte source multiplies the low byte of the destination into all of it
+fn mul<L: Clone>(from: &Operand<L>, to: &Operand<L>) -> Result<Vec<Part<L>>, EncodeError> {
+    let op = Part::Byte;
+    let Operand::Reg(reg) = to else {
+        return Err(unsupported(TO, "destination"));
+    };
+    let reg = wide_register(ErrorAt::Operand(TO), reg)?;
+    let parts = match from {
+        Operand::Value(value) if value.is_wide() => {
+            let mut parts = vec![op(opcode::MUL_LIT_REG_WIDE)];
+            parts.extend(wide(value));
+            parts.push(reg);
+            parts
+        }
+        Operand::Value(value) => vec![op(opcode::MUL_LIT_REG), byte(FROM, value)?, reg],
+        Operand::Reg(from) => vec![op(opcode::MUL_REG_REG), register(from), reg],
+        _ => return Err(unsupported(FROM, "source")),
+    };
+    Ok(parts)
+}
+
+/// `div` and `mod`, operating at the width of the destination
+fn divide<L: Clone>(
+    [lit_reg, lit_reg_wide, reg_reg]: [u8; 3],
+    from: &Operand<L>,
+    to: &Operand<L>,
+) -> Result<Vec<Part<L>>, EncodeError> {
+    let op = Part::Byte;
+    let Operand::Reg(reg) = to else {
+        return Err(unsupported(TO, "destination"));
+    };
+    let parts = match from {
+        Operand::Value(value) if reg.wide => {
+            let mut parts = vec![op(lit_reg_wide)];
+            parts.extend(wide(value));
+            parts.push(register(reg));
+            parts
+        }
+        Operand::Value(value) => vec![op(lit_reg), byte(FROM, value)?, register(reg)],
+        Operand::Reg(from) => {
+            same_width(from, reg)?;
+            vec![op(reg_reg), register(from), register(reg)]
+        }
+        _ => return Err(unsupported(FROM, "source")),
+    };
+    Ok(parts)
+}
+
+fn register<L>(reg: &Reg) -> Part<L> {
+    Part::Byte(reg.code)
+}
+
+fn wide_register<L>(at: ErrorAt, reg: &Reg) -> Result<Part<L>, EncodeError> {
+    if reg.wide {
+        Ok(register(reg))
+    } else {
+        Err(EncodeError::new(at, "expected a wide register"))
+    }
+}
+
+/// The destination must have the width of the source
+fn same_width(from: &Reg, to: &Reg) -> Result<(), EncodeError> {
+    if from.wide == to.wide {
+        Ok(())
+    } else {
+        Err(EncodeError::new(
+            ErrorAt::Operand(TO),
+            "register widths differ",
+        ))
+    }
+}
+
+fn byte<L>(index: usize, value: &Value<L>) -> Result<Part<L>, EncodeError> {
+    match *value {
+        Value::Lit { value: byte, .. } if !value.is_wide() => Ok(Part::Byte(byte as u8)),
+        _ => Err(EncodeError::new(
+            ErrorAt::Operand(index),
+            "expected an 8 bit value",
+        )),
+    }
+}
+
+/// Big-endian
+fn wide<L: Clone>(value: &Value<L>) -> Vec<Part<L>> {
+    match value {
+        Value::Lit { value, .. } => {
+            let [high, low] = value.to_be_bytes();
+            vec![Part::Byte(high), Part::Byte(low)]
+        }
+        Value::Label(label) => vec![Part::Label(label.clone())],
+    }
+}
+
+fn unsupported(index: usize, operand: &str) -> EncodeError {
+    let message = format!("unsupported {} operand", operand);
+    EncodeError::new(ErrorAt::Operand(index), message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::register;
+
+    type Op = Operand<&'static str>;
+
+    fn lit(value: u16) -> Value<&'static str> {
+        Value::Lit { value, wide: false }
+    }
+
+    fn reg(name: &str) -> Op {
+        Operand::Reg(Reg::from_name(name).expect("register"))
+    }
+
+    fn bytes(parts: Vec<Part<&str>>) -> Vec<u8> {
+        parts
+            .into_iter()
+            .map(|part| match part {
+                Part::Byte(byte) => byte,
+                Part::Label(label) => panic!("unresolved label {}", label),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn encode_operations() {
+        let encoded = encode("MOV", &[Operand::Value(lit(0xab)), reg("c")]).expect("valid");
+        assert_eq!(vec![opcode::MOV_LIT_REG, 0xab, register::C], bytes(encoded));
+        let encoded = encode("mov", &[Operand::Value(lit(0x12)), reg("ab")]).expect("valid");
+        assert_eq!(
+            vec![opcode::MOV_LIT_REG_WIDE, 0x00, 0x12, register::AB],
+            bytes(encoded)
+        );
+        let offset = Operand::Offset(lit(0x1234), Reg::from_name("ef").expect("register"));
+        let encoded = encode("mov", &[offset, reg("a")]).expect("valid");
+        assert_eq!(
+            vec![
+                opcode::MOV_LIT_OFF_REG,
+                0x12,
+                0x34,
+                register::EF,
+                register::A
+            ],
+            bytes(encoded)
+        );
+        let encoded = encode("mod", &[reg("ab"), reg("cd")]).expect("valid");
+        assert_eq!(
+            vec![opcode::MOD_REG_REG, register::AB, register::CD],
+            bytes(encoded)
+        );
+        let encoded = encode("div", &[Operand::Value(lit(2)), reg("cd")]).expect("valid");
+        assert_eq!(
+            vec![opcode::DIV_LIT_REG_WIDE, 0x00, 0x02, register::CD],
+            bytes(encoded)
+        );
+        let encoded = encode::<&str>("hlt", &[]).expect("valid");
+        assert_eq!(vec![opcode::HLT], bytes(encoded));
+    }
+
+    #[test]
+    fn encode_labels() {
+        let encoded = encode("mov", &[Operand::Mem(Value::Label("value")), reg("b")]);
+        let expected = vec![
+            Part::Byte(opcode::MOV_MEM_REG),
+            Part::Label("value"),
+            Part::Byte(register::B),
+        ];
+        assert_eq!(expected, encoded.expect("valid"));
+    }
+
+    #[test]
+    fn error_locations() {
+        let error = |mnemonic, operands: &[Op]| encode(mnemonic, operands).expect_err("invalid");
+        let err = error("jmp", &[]);
+        assert_eq!(ErrorAt::Mnemonic, err.at);
+        assert_eq!("unknown instruction `jmp`", err.to_string());
+        let err = error("mov", &[reg("a"), reg("ab")]);
+        assert_eq!(ErrorAt::Operand(1), err.at);
+        assert_eq!("register widths differ", err.message);
+        let err = error("mov", &[Operand::Value(lit(0x100)), reg("a")]);
+        assert_eq!(ErrorAt::Operand(0), err.at);
+        let offset = Operand::Offset(lit(0x10), Reg::from_name("a").expect("register"));
+        let err = error("mov", &[offset, reg("b")]);
+        assert_eq!(ErrorAt::Register(0), err.at);
+        assert_eq!("expected a wide register", err.message);
+        let err = error("db", &[Operand::Value(lit(1)), reg("a")]);
+        assert_eq!(ErrorAt::Operand(1), err.at);
+    }
+}
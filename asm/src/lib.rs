@@ -0,0 +1,8 @@
+pub mod assemble;
+pub mod encode;
+pub mod opcode;
+pub mod register;
+
+pub use assemble::{assemble_file, AsmError, Assembler, Program};
+pub use encode::{encode, EncodeError, ErrorAt, Operand, Part, Value};
+pub use register::Reg;
@@ -0,0 +1,32 @@
+//! Operation codes, the first byte of every instruction
+
+pub const NOP: u8 = 0x00;
+
+pub const MOV_LIT_REG: u8 = 0x10;
+pub const MOV_LIT_REG_WIDE: u8 = 0x11;
+pub const MOV_REG_REG: u8 = 0x12;
+pub const MOV_REG_MEM: u8 = 0x13;
+pub const MOV_MEM_REG: u8 = 0x14;
+pub const MOV_LIT_MEM: u8 = 0x15;
+pub const MOV_LIT_MEM_WIDE: u8 = 0x16;
+pub const MOV_REG_PTR_REG: u8 = 0x17;
+pub const MOV_LIT_OFF_REG: u8 = 0x18;
+
+pub const MUL_LIT_REG: u8 = 0x20;
+pub const MUL_LIT_REG_WIDE: u8 = 0x21;
+pub const MUL_REG_REG: u8 = 0x22;
+
+pub const DIV_LIT_REG: u8 = 0x24;
+pub const DIV_LIT_REG_WIDE: u8 = 0x25;
+pub const DIV_REG_REG: u8 = 0x26;
+
+pub const MOD_LIT_REG: u8 = 0x28;
+pub const MOD_LIT_REG_WIDE: u8 = 0x29;
+pub const MOD_REG_REG: u8 = 0x2a;
+
+pub const CMP_LIT_REG: u8 = 0x30;
+pub const CMP_LIT_REG_WIDE: u8 = 0x31;
+pub const CMP_REG_REG: u8 = 0x32;
+pub const CMP_MEM_REG: u8 = 0x34;
+
+pub const HLT: u8 = 0xff;
@@ -0,0 +1,89 @@
+//! Register operand codes
+
+pub const A: u8 = 0x01;
+pub const B: u8 = 0x02;
+pub const C: u8 = 0x03;
+pub const D: u8 = 0x04;
+pub const E: u8 = 0x05;
+pub const F: u8 = 0x06;
+pub const G: u8 = 0x07;
+pub const H: u8 = 0x08;
+pub const MB: u8 = 0x09;
+
+pub const AB: u8 = 0x12;
+pub const CD: u8 = 0x34;
+pub const EF: u8 = 0x56;
+pub const GH: u8 = 0x78;
+pub const PC: u8 = 0xf0;
+pub const SP: u8 = 0xf1;
+
+const REGISTERS: [(&str, u8); 9] = [
+    ("A", A),
+    ("B", B),
+    ("C", C),
+    ("D", D),
+    ("E", E),
+    ("F", F),
+    ("G", G),
+    ("H", H),
+    ("MB", MB),
+];
+
+const WIDE_REGISTERS: [(&str, u8); 6] = [
+    ("AB", AB),
+    ("CD", CD),
+    ("EF", EF),
+    ("GH", GH),
+    ("PC", PC),
+    ("SP", SP),
+];
+
+/// Register operand
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Reg {
+    pub code: u8,
+    pub wide: bool,
+}
+
+impl Reg {
+    /// Register called `name`, ignoring case
+    pub fn from_name(name: &str) -> Option<Self> {
+        let find = |names: &[(&str, u8)]| {
+            names
+                .iter()
+                .find(|(reg, _)| reg.eq_ignore_ascii_case(name))
+                .map(|&(_, code)| code)
+        };
+        match find(&REGISTERS) {
+            Some(code) => Some(Self { code, wide: false }),
+            None => Some(Self {
+                code: find(&WIDE_REGISTERS)?,
+                wide: true,
+            }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn register_names() {
+        assert_eq!(
+            Some(Reg {
+                code: C,
+                wide: false
+            }),
+            Reg::from_name("c")
+        );
+        assert_eq!(
+            Some(Reg {
+                code: SP,
+                wide: true
+            }),
+            Reg::from_name("SP")
+        );
+        assert_eq!(None, Reg::from_name("start"));
+    }
+}
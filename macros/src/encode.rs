@@ -0,0 +1,103 @@
+use crate::parse::Operand;
+use h8bit_asm::{self as asm, ErrorAt, Part};
+use proc_macro2::{Ident, Span};
+
+/// Encode one statement with the encoder `Instruction::encode` uses, pointing
+/// errors at the offending token
+pub fn encode(mnemonic: &Ident, operands: &[Operand]) -> syn::Result<Vec<Part<Ident>>> {
+    let encoded: Vec<_> = operands.iter().map(Operand::to_asm).collect();
+    asm::encode(&mnemonic.to_string(), &encoded).map_err(|err| {
+        let span = match err.at {
+            ErrorAt::Mnemonic => mnemonic.span(),
+            ErrorAt::Operand(index) => operand_span(&operands[index]),
+            ErrorAt::Register(index) => match &operands[index] {
+                Operand::Offset(_, reg) => reg.span,
+                operand => operand_span(operand),
+            },
+        };
+        syn::Error::new(span, err.message)
+    })
+}
+
+fn operand_span(operand: &Operand) -> Span {
+    match operand {
+        Operand::Value(value) | Operand::Mem(value) | Operand::Offset(value, _) => value.span(),
+        Operand::Reg(reg) | Operand::Ptr(reg) => reg.span,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse::{Item, Program};
+
+    fn encode_str(source: &str) -> syn::Result<Vec<Part<Ident>>> {
+        let program: Program = syn::parse_str(source).expect("valid syntax");
+        match &program.items[0] {
+            Item::Instruction { mnemonic, operands } => encode(mnemonic, operands),
+            Item::Label(_) => panic!("expected an instruction"),
+        }
+    }
+
+    fn size(source: &str) -> usize {
+        let parts = encode_str(source).expect("valid instruction");
+        parts.iter().map(Part::size).sum()
+    }
+
+    fn error(source: &str) -> String {
+        match encode_str(source) {
+            Ok(_) => panic!("expected an error"),
+            Err(err) => err.to_string(),
+        }
+    }
+
+    #[test]
+    fn instruction_sizes() {
+        assert_eq!(1, size("hlt"));
+        assert_eq!(3, size("mov 0xab, a"));
+        assert_eq!(4, size("mov 0xab, ab"));
+        assert_eq!(3, size("mov ab, cd"));
+        assert_eq!(4, size("mov a, [0x10]"));
+        assert_eq!(4, size("mov [value], ab"));
+        assert_eq!(4, size("mov 0xab, [0x10]"));
+        assert_eq!(5, size("mov 0xabu16, [0x10]"));
+        assert_eq!(3, size("mov [cd], a"));
+        assert_eq!(5, size("mov [0x10 + ef], gh"));
+        assert_eq!(3, size("db 1, 2, 3"));
+        assert_eq!(4, size("dw 0x1234, value"));
+        assert_eq!(3, size("mul 0x10, ab"));
+        assert_eq!(4, size("mul 0x10u16, ab"));
+        assert_eq!(3, size("mul a, cd"));
+        assert_eq!(3, size("mul ab, cd"));
+        assert_eq!(3, size("div 0x10, a"));
+        assert_eq!(4, size("div 0x10, ab"));
+        assert_eq!(3, size("mod ab, cd"));
+        assert_eq!(3, size("cmp 0x10, a"));
+        assert_eq!(4, size("cmp 0x10, ab"));
+        assert_eq!(3, size("cmp a, b"));
+        assert_eq!(4, size("cmp [value], ab"));
+    }
+
+    #[test]
+    fn operand_errors() {
+        assert_eq!("unknown instruction `jmp`", error("jmp start"));
+        assert_eq!("unexpected operand", error("hlt a"));
+        assert_eq!("mov takes a source and a destination", error("mov a"));
+        assert_eq!("register widths differ", error("mov a, ab"));
+        assert_eq!("expected an 8 bit value", error("mov 0x100, a"));
+        assert_eq!("expected an 8 bit value", error("mov value, a"));
+        assert_eq!("expected a wide register", error("mov [a], b"));
+        assert_eq!("unsupported destination operand", error("mov a, 0x10"));
+        assert_eq!(
+            "unsupported destination operand",
+            error("mov [0x10], [0x20]")
+        );
+        assert_eq!("mul takes a source and a destination", error("mul a"));
+        assert_eq!("expected a wide register", error("mul 2, a"));
+        assert_eq!("unsupported source operand", error("mul [0x10], ab"));
+        assert_eq!("register widths differ", error("div a, ab"));
+        assert_eq!("unsupported destination operand", error("mod 2, [0x10]"));
+        assert_eq!("register widths differ", error("cmp ab, c"));
+        assert_eq!("unsupported source operand", error("cmp [cd], a"));
+    }
+}
@@ -0,0 +1,108 @@
+use crate::encode::encode;
+use crate::parse::{Item, Program};
+use h8bit_asm::Part;
+use proc_macro::TokenStream;
+use proc_macro2::Span;
+use quote::quote;
+use std::collections::HashMap;
+
+mod encode;
+mod parse;
+
+/// Assemble h8bit instructions into a `[u8; N]` at compile time
+///
+/// Statements are separated by `;` and may start with a `label:`. Labels are
+/// offsets from the start of the program, usable for any wide operand.
+/// Literals above `0xff`, or with a `u16` suffix, are wide. `db` and `dw`
+/// emit bytes and wide values. `mul` by a byte multiplies the low byte of
+/// a wide register into all of it, `div` and `mod` work at the width of their
+/// destination. `cmp` sets the flags from the destination minus the source.
+///
+/// ```text
+/// h8asm! {
+///     mov 0x01f0, cd;
+///     mov 0xab, [0x01f0];
+///     mov [cd], a;
+///     hlt
+/// }
+/// ```
+#[proc_macro]
+pub fn h8asm(input: TokenStream) -> TokenStream {
+    let program = syn::parse_macro_input!(input as Program);
+    expand(program)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
+fn expand(program: Program) -> syn::Result<proc_macro2::TokenStream> {
+    let mut labels = HashMap::new();
+    let mut parts = Vec::new();
+    let mut len = 0;
+    for item in program.items {
+        match item {
+            Item::Label(label) => {
+                if labels.insert(label.to_string(), len).is_some() {
+                    let message = format!("duplicate label `{}`", label);
+                    return Err(syn::Error::new(label.span(), message));
+                }
+            }
+            Item::Instruction { mnemonic, operands } => {
+                for part in encode(&mnemonic, &operands)? {
+                    len += part.size();
+                    parts.push(part);
+                }
+            }
+        }
+    }
+    if len > u16::MAX as usize + 1 {
+        let message = "program is larger than memory";
+        return Err(syn::Error::new(Span::call_site(), message));
+    }
+    let mut bytes = Vec::new();
+    for part in parts {
+        match part {
+            Part::Byte(byte) => bytes.push(quote!(#byte)),
+            Part::Label(label) => {
+                let addr = *labels.get(&label.to_string()).ok_or_else(|| {
+                    syn::Error::new(label.span(), format!("undefined label `{}`", label))
+                })? as u16;
+                let [high, low] = addr.to_be_bytes();
+                bytes.push(quote!(#high));
+                bytes.push(quote!(#low));
+            }
+        }
+    }
+    Ok(quote! {
+        {
+            let bytes: [u8; #len] = [#(#bytes),*];
+            bytes
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn expand_str(source: &str) -> syn::Result<String> {
+        let program = syn::parse_str(source).expect("valid syntax");
+        expand(program).map(|tokens| tokens.to_string())
+    }
+
+    fn error(source: &str) -> String {
+        expand_str(source).expect_err("invalid program").to_string()
+    }
+
+    #[test]
+    fn labels_resolved() {
+        let tokens = expand_str("mov value, cd; hlt; value: db 0xab").expect("valid program");
+        assert!(tokens.contains("[u8 ; 6usize]"));
+        assert!(tokens.contains("0u8 , 5u8"));
+    }
+
+    #[test]
+    fn label_errors() {
+        assert_eq!("undefined label `missing`", error("mov [missing], a"));
+        assert_eq!("duplicate label `a1`", error("a1: nop; a1: hlt"));
+    }
+}
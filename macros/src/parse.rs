@@ -0,0 +1,257 @@
+use h8bit_asm as asm;
+use proc_macro2::Span;
+use syn::ext::IdentExt;
+use syn::parse::{Parse, ParseStream};
+use syn::{bracketed, token, Ident, LitInt, Token};
+
+/// Statements separated by `;`
+pub struct Program {
+    pub items: Vec<Item>,
+}
+
+pub enum Item {
+    Label(Ident),
+    Instruction {
+        mnemonic: Ident,
+        operands: Vec<Operand>,
+    },
+}
+
+pub enum Operand {
+    Value(Value),
+    Reg(Reg),
+    /// `[value]`
+    Mem(Value),
+    /// `[reg]`
+    Ptr(Reg),
+    /// `[value + reg]`
+    Offset(Value, Reg),
+}
+
+pub enum Value {
+    Lit(Literal),
+    Label(Ident),
+}
+
+pub struct Literal {
+    pub value: u16,
+    /// Has a `u16` suffix
+    pub wide: bool,
+    pub span: Span,
+}
+
+pub struct Reg {
+    pub reg: asm::Reg,
+    pub span: Span,
+}
+
+enum Term {
+    Value(Value),
+    Reg(Reg),
+}
+
+impl Parse for Program {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let mut items = Vec::new();
+        while !input.is_empty() {
+            if input.peek(Ident) && input.peek2(Token![:]) {
+                items.push(Item::Label(input.parse()?));
+                input.parse::<Token![:]>()?;
+                continue;
+            }
+            // `mod` is a keyword
+            let mnemonic = input.call(Ident::parse_any)?;
+            let mut operands = Vec::new();
+            if !input.is_empty() && !input.peek(Token![;]) {
+                operands.push(input.parse()?);
+                while input.peek(Token![,]) {
+                    input.parse::<Token![,]>()?;
+                    operands.push(input.parse()?);
+                }
+            }
+            items.push(Item::Instruction { mnemonic, operands });
+            if !input.is_empty() {
+                input.parse::<Token![;]>()?;
+            }
+        }
+        Ok(Self { items })
+    }
+}
+
+impl Parse for Operand {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        if !input.peek(token::Bracket) {
+            return match input.parse()? {
+                Term::Value(value) => Ok(Self::Value(value)),
+                Term::Reg(reg) => Ok(Self::Reg(reg)),
+            };
+        }
+        let content;
+        bracketed!(content in input);
+        let operand = match content.parse()? {
+            Term::Reg(reg) => Self::Ptr(reg),
+            Term::Value(value) if content.peek(Token![+]) => {
+                content.parse::<Token![+]>()?;
+                match content.parse()? {
+                    Term::Reg(reg) => Self::Offset(value, reg),
+                    Term::Value(value) => {
+                        return Err(syn::Error::new(value.span(), "expected a wide register"))
+                    }
+                }
+            }
+            Term::Value(value) => Self::Mem(value),
+        };
+        if !content.is_empty() {
+            return Err(content.error("unexpected token in memory operand"));
+        }
+        Ok(operand)
+    }
+}
+
+impl Parse for Term {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        if input.peek(LitInt) {
+            return Ok(Self::Value(Value::Lit(input.parse()?)));
+        }
+        let ident: Ident = input.parse()?;
+        match Reg::from_ident(&ident) {
+            Some(reg) => Ok(Self::Reg(reg)),
+            None => Ok(Self::Value(Value::Label(ident))),
+        }
+    }
+}
+
+impl Parse for Literal {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let lit: LitInt = input.parse()?;
+        let wide = match lit.suffix() {
+            "" | "u8" => false,
+            "u16" => true,
+            suffix => {
+                let message = format!("unsupported suffix `{}`, expected u8 or u16", suffix);
+                return Err(syn::Error::new(lit.span(), message));
+            }
+        };
+        let value = lit.base10_parse::<u16>()?;
+        if lit.suffix() == "u8" && value > u8::MAX as u16 {
+            return Err(syn::Error::new(lit.span(), "literal out of range for u8"));
+        }
+        Ok(Self {
+            value,
+            wide,
+            span: lit.span(),
+        })
+    }
+}
+
+impl Operand {
+    /// Without spans, for the shared encoder
+    pub fn to_asm(&self) -> asm::Operand<Ident> {
+        match self {
+            Operand::Value(value) => asm::Operand::Value(value.to_asm()),
+            Operand::Reg(reg) => asm::Operand::Reg(reg.reg),
+            Operand::Mem(value) => asm::Operand::Mem(value.to_asm()),
+            Operand::Ptr(reg) => asm::Operand::Ptr(reg.reg),
+            Operand::Offset(value, reg) => asm::Operand::Offset(value.to_asm(), reg.reg),
+        }
+    }
+}
+
+impl Value {
+    pub fn span(&self) -> Span {
+        match self {
+            Value::Lit(lit) => lit.span,
+            Value::Label(ident) => ident.span(),
+        }
+    }
+
+    pub fn to_asm(&self) -> asm::Value<Ident> {
+        match self {
+            Value::Lit(lit) => asm::Value::Lit {
+                value: lit.value,
+                wide: lit.wide,
+            },
+            Value::Label(ident) => asm::Value::Label(ident.clone()),
+        }
+    }
+}
+
+impl Reg {
+    fn from_ident(ident: &Ident) -> Option<Self> {
+        Some(Self {
+            reg: asm::Reg::from_name(&ident.to_string())?,
+            span: ident.span(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use h8bit_asm::register;
+
+    fn error(source: &str) -> String {
+        match syn::parse_str::<Program>(source) {
+            Ok(_) => panic!("expected a syntax error"),
+            Err(err) => err.to_string(),
+        }
+    }
+
+    #[test]
+    fn parse_statements() {
+        let program: Program =
+            syn::parse_str("start: mov 0x01f0, CD; mov [cd], a; hlt;").expect("valid program");
+        assert_eq!(4, program.items.len());
+        assert!(matches!(&program.items[0], Item::Label(label) if label == "start"));
+        let Item::Instruction { operands, .. } = &program.items[2] else {
+            panic!("expected an instruction");
+        };
+        assert!(
+            matches!(&operands[0], Operand::Ptr(reg) if reg.reg.code == register::CD && reg.reg.wide)
+        );
+        assert!(
+            matches!(&operands[1], Operand::Reg(reg) if reg.reg.code == register::A && !reg.reg.wide)
+        );
+    }
+
+    #[test]
+    fn parse_memory_operands() {
+        let program: Program =
+            syn::parse_str("mov [0x10 + ef], b; mov 0xab, [value]").expect("valid program");
+        let Item::Instruction { operands, .. } = &program.items[0] else {
+            panic!("expected an instruction");
+        };
+        assert!(matches!(&operands[0], Operand::Offset(Value::Lit(lit), reg)
+            if lit.value == 0x10 && reg.reg.code == register::EF));
+        let Item::Instruction { operands, .. } = &program.items[1] else {
+            panic!("expected an instruction");
+        };
+        assert!(matches!(&operands[1], Operand::Mem(Value::Label(label)) if label == "value"));
+    }
+
+    #[test]
+    fn literal_width() {
+        let lit: Literal = syn::parse_str("0x12u16").expect("valid literal");
+        assert!(Value::Lit(lit).to_asm().is_wide());
+        let lit: Literal = syn::parse_str("0x12").expect("valid literal");
+        assert!(!Value::Lit(lit).to_asm().is_wide());
+        let lit: Literal = syn::parse_str("0x100").expect("valid literal");
+        assert!(Value::Lit(lit).to_asm().is_wide());
+    }
+
+    #[test]
+    fn syntax_errors() {
+        assert_eq!("expected `;`", error("mov a, b c"));
+        assert_eq!("expected a wide register", error("mov [0x10 + 0x20], a"));
+        assert_eq!("unexpected token in memory operand", error("mov [a b], a"));
+        assert_eq!("literal out of range for u8", error("mov 0x100u8, a"));
+        assert_eq!(
+            "unsupported suffix `i32`, expected u8 or u16",
+            error("mov 1i32, a")
+        );
+        assert_eq!(
+            "number too large to fit in target type",
+            error("mov 0x10000, ab")
+        );
+    }
+}
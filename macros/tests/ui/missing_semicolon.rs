@@ -0,0 +1,8 @@
+use h8bit_macros::h8asm;
+
+fn main() {
+    let _ = h8asm! {
+        mov a, b c;
+        hlt
+    };
+}
@@ -0,0 +1,7 @@
+use h8bit_macros::h8asm;
+
+fn main() {
+    let _ = h8asm! {
+        mov [0x10 + 0x20], a
+    };
+}
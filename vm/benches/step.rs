@@ -0,0 +1,101 @@
+//! Instructions per second with and without the decode cache, and on a
+//! statically dispatched bus
+//!
+//! Run with `cargo bench --bench step`.
+
+use h8bit_vm::cpu::{Cpu, Error, WideRegister};
+use h8bit_vm::h8asm;
+use h8bit_vm::memory::{Bus, Device, DeviceError, DynMem, MemoryMapper};
+use std::time::{Duration, Instant};
+
+const BLOCK: &[u8] = &h8asm! {
+    mov 0x12, a;
+    mov a, [0x8000];
+    mov [0x8000], b;
+    mov 0x8000, cd;
+    mov [cd], e;
+    mov 0x0001u16, [0x8002];
+    mov [0x0004 + cd], f
+};
+const BLOCKS: usize = 512;
+const PASSES: u32 = 200;
+
+fn main() {
+    let uncached = instructions_per_second(create_cpu());
+    let mut cpu = create_cpu();
+    cpu.enable_decode_cache();
+    let cached = instructions_per_second(cpu);
+    let flat = instructions_per_second(Cpu::new(FlatRam(program())).expect("memory is mapped"));
+    println!("uncached: {:>12.0} instructions/s", uncached);
+    println!("cached:   {:>12.0} instructions/s", cached);
+    println!("flat bus: {:>12.0} instructions/s", flat);
+    println!("cache speedup: {:.2}x", cached / uncached);
+}
+
+fn instructions_per_second<B: Bus>(mut cpu: Cpu<B>) -> f64 {
+    let mut elapsed = Duration::ZERO;
+    for _ in 0..PASSES {
+        cpu.registers_mut().set_wide(WideRegister::PC, 0);
+        let start = Instant::now();
+        match cpu.run() {
+            Err(Error::Halt) => {}
+            result => panic!("unexpected result: {:?}", result),
+        }
+        elapsed += start.elapsed();
+    }
+    cpu.instructions() as f64 / elapsed.as_secs_f64()
+}
+
+fn create_cpu() -> Cpu {
+    let mut ram = DynMem::new(0xffff + 1);
+    ram.replace(&program(), 0);
+    let mut mapper = MemoryMapper::new();
+    mapper
+        .add_device(Box::new(ram), 0, 0xffff)
+        .expect("valid range");
+    Cpu::new(mapper).expect("memory is mapped")
+}
+
+/// 64 KiB of RAM filled with the program
+fn program() -> Vec<u8> {
+    let mut program: Vec<u8> = BLOCK.repeat(BLOCKS);
+    program.extend(h8asm! { hlt });
+    program.resize(0xffff + 1, 0);
+    program
+}
+
+/// All of memory as one RAM, without a mapper
+struct FlatRam(Vec<u8>);
+
+impl Device for FlatRam {
+    fn set(&mut self, addr: u16, data: u8) -> Result<(), DeviceError> {
+        self.0[addr as usize] = data;
+        Ok(())
+    }
+
+    fn get(&self, addr: u16) -> Result<u8, DeviceError> {
+        Ok(self.0[addr as usize])
+    }
+
+    fn set_wide(&mut self, addr: u16, data: u16) -> Result<(), DeviceError> {
+        let [high, low] = data.to_be_bytes();
+        self.set(addr, high)?;
+        self.set(addr.wrapping_add(1), low)
+    }
+
+    fn get_wide(&self, addr: u16) -> Result<u16, DeviceError> {
+        let high = self.get(addr)?;
+        let low = self.get(addr.wrapping_add(1))?;
+        Ok(u16::from_be_bytes([high, low]))
+    }
+}
+
+impl Bus for FlatRam {
+    fn start(&self) -> Option<u16> {
+        Some(0)
+    }
+
+    fn end(&self) -> Option<u16> {
+        Some(0xffff)
+    }
+}
@@ -0,0 +1,230 @@
+use super::{Cpu, Instruction, PC};
+use crate::memory::{Access, AccessKind, Bus};
+
+/// Instruction decoded at one address
+#[derive(Debug, Clone, Copy)]
+struct Decoded {
+    opcode: u8,
+    instruction: Instruction,
+    /// Bytes the instruction occupies
+    len: u8,
+}
+
+/// Decoded instructions by address
+#[derive(Debug, Default)]
+pub(super) struct DecodeCache {
+    /// One slot per address, empty when the cache is disabled
+    entries: Vec<Option<Decoded>>,
+    /// Length of the longest cached instruction
+    longest: u8,
+}
+
+impl DecodeCache {
+    pub fn enable(&mut self) {
+        if !self.is_enabled() {
+            self.entries = vec![None; 0xffff + 1];
+        }
+    }
+
+    pub fn disable(&mut self) {
+        self.entries = Vec::new();
+        self.longest = 0;
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        !self.entries.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        // nothing has been cached since the last clear, or it is disabled
+        if self.longest == 0 {
+            return;
+        }
+        self.entries.fill(None);
+        self.longest = 0;
+    }
+
+    fn get(&self, pc: u16) -> Option<Decoded> {
+        self.entries.get(pc as usize).copied().flatten()
+    }
+
+    fn insert(&mut self, pc: u16, decoded: Decoded) {
+        if let Some(entry) = self.entries.get_mut(pc as usize) {
+            *entry = Some(decoded);
+            self.longest = self.longest.max(decoded.len);
+        }
+    }
+
+    /// Drop instructions which include the byte at `addr`
+    fn invalidate(&mut self, addr: u16) {
+        let first = addr.saturating_sub(self.longest.saturating_sub(1) as u16);
+        for pc in first..=addr {
+            let slot = &mut self.entries[pc as usize];
+            if slot.is_some_and(|decoded| pc as usize + decoded.len as usize > addr as usize) {
+                *slot = None;
+            }
+        }
+    }
+}
+
+impl<B: Bus> Cpu<B> {
+    /// Decode each instruction once and reuse it while its bytes are unchanged
+    ///
+    /// Cached instructions are not fetched again. Their bytes still count as
+    /// fetches in access stats, see [`Bus::count_fetch`], but no fetch
+    /// accesses are recorded. Only used on buses which record accesses, as
+    /// writes to code must be seen. Memory changed from the host through
+    /// [`Cpu::memory_mut`] empties the cache.
+    pub fn enable_decode_cache(&mut self) {
+        self.decode_cache.enable();
+        self.update_observing();
+    }
+
+    pub fn disable_decode_cache(&mut self) {
+        self.decode_cache.disable();
+        self.update_observing();
+    }
+
+    /// Opcode and instruction at `pc` if cached, moving the program counter past it
+    pub(super) fn cached_instruction(&mut self, pc: u16) -> Option<(u8, Instruction)> {
+        if !self.memory.is_observing() {
+            return None;
+        }
+        let decoded = self.decode_cache.get(pc)?;
+        self.memory.count_fetch(pc, decoded.len as u16);
+        self.registers
+            .set_wide(PC, pc.wrapping_add(decoded.len as u16));
+        Some((decoded.opcode, decoded.instruction))
+    }
+
+    /// Cache an instruction just decoded from `pc`
+    pub(super) fn cache_instruction(&mut self, pc: u16, opcode: u8, instruction: Instruction) {
+        let len = self.registers.get_wide(PC).wrapping_sub(pc) as u8;
+        let decoded = Decoded {
+            opcode,
+            instruction,
+            len,
+        };
+        self.decode_cache.insert(pc, decoded);
+    }
+
+    /// Drop cached instructions overwritten by `accesses`
+    pub(super) fn invalidate_cache(&mut self, accesses: &[Access]) {
+        if !self.decode_cache.is_enabled() {
+            return;
+        }
+        let writes = accesses
+            .iter()
+            .filter(|access| access.kind == AccessKind::Write);
+        for access in writes {
+            if self.memory.is_aliased(access.addr) {
+                // the same bytes appear at other addresses
+                self.decode_cache.clear();
+                return;
+            }
+            self.decode_cache.invalidate(access.addr);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cpu::operation::mov;
+    use crate::memory::{Device, MemoryMapper, TestDevice};
+
+    const PROGRAM: [u8; 5] = [mov::lit_mem::CODE, 0xab, 0x00, 0x06, 0xff];
+
+    fn create_cpu(program: &[u8]) -> Cpu {
+        let mut device = TestDevice::new(0x10);
+        device.write_slice(program);
+        let mut mapper = MemoryMapper::new();
+        mapper
+            .add_device(Box::new(device), 0, 0x0f)
+            .expect("valid range");
+        let mut cpu = Cpu::new(mapper).expect("valid cpu");
+        cpu.enable_decode_cache();
+        cpu
+    }
+
+    fn rerun(cpu: &mut Cpu) {
+        cpu.registers.set_wide(PC, 0);
+        cpu.step().expect("valid instruction");
+    }
+
+    #[test]
+    fn cached_instruction_is_not_fetched() {
+        let mut cpu = create_cpu(&PROGRAM);
+        cpu.step().expect("valid instruction");
+        assert!(cpu.decode_cache.get(0).is_some());
+        cpu.memory.collect_stats(true);
+        cpu.registers.set_wide(PC, 0);
+        cpu.memory.take_accesses();
+        cpu.cached_instruction(0).expect("cached");
+        assert!(cpu.memory.take_accesses().is_empty());
+        rerun(&mut cpu);
+        let stats = cpu.memory.take_stats().expect("collecting");
+        // counted as fetched although not read
+        assert_eq!(8, stats.total().fetches);
+        assert_eq!(2, stats.at(0).fetches);
+        assert_eq!(4, cpu.registers.get_wide(PC));
+        assert_eq!(0xab, cpu.memory.get(0x06).expect("mapped"));
+    }
+
+    #[test]
+    fn write_invalidates_instruction() {
+        // MOV_LIT_MEM 0xcd, [0x0001]: overwrite the literal of the first
+        let program = [
+            mov::lit_mem::CODE,
+            0xab,
+            0x00,
+            0x08,
+            mov::lit_mem::CODE,
+            0xcd,
+            0x00,
+            0x01,
+        ];
+        let mut cpu = create_cpu(&program);
+        cpu.step().expect("valid instruction");
+        cpu.step().expect("valid instruction");
+        assert!(cpu.decode_cache.get(0).is_none());
+        assert!(cpu.decode_cache.get(4).is_some());
+        rerun(&mut cpu);
+        assert_eq!(0xcd, cpu.memory.get(0x08).expect("mapped"));
+    }
+
+    #[test]
+    fn aliased_write_clears_cache() {
+        // MOV_LIT_MEM 0xcd, [0x0011]: overwrite its own literal through a mirror
+        let program = [mov::lit_mem::CODE, 0xcd, 0x00, 0x11, 0xff];
+        let mut cpu = create_cpu(&program);
+        let memory = cpu.memory_mut();
+        let device = memory
+            .add_device(Box::new(TestDevice::new(0x10)), 0, 0x0f)
+            .expect("valid range");
+        memory.add_mirror(device, 0x10, 0x1f).expect("mapped");
+        for (addr, &byte) in program.iter().enumerate() {
+            memory.set(addr as u16, byte).expect("mapped");
+        }
+        cpu.step().expect("valid instruction");
+        assert!(cpu.decode_cache.get(0).is_none());
+    }
+
+    #[test]
+    fn memory_mut_clears_cache() {
+        let mut cpu = create_cpu(&PROGRAM);
+        cpu.step().expect("valid instruction");
+        cpu.memory_mut().set(1, 0xcd).expect("mapped");
+        rerun(&mut cpu);
+        assert_eq!(0xcd, cpu.memory.get(0x06).expect("mapped"));
+    }
+
+    #[test]
+    fn disabled_cache_decodes_every_step() {
+        let mut cpu = create_cpu(&PROGRAM);
+        cpu.disable_decode_cache();
+        cpu.step().expect("valid instruction");
+        assert!(cpu.decode_cache.get(0).is_none());
+        assert!(!cpu.memory.is_observing());
+    }
+}
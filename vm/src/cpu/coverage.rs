@@ -0,0 +1,117 @@
+use crate::source_map::SourceMap;
+use std::collections::BTreeMap;
+use std::io;
+
+/// Addresses of executed instructions, and how often each ran
+///
+/// The instruction set has no conditional branches yet, so coverage is by
+/// instruction only.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Coverage {
+    hits: BTreeMap<u16, u64>,
+}
+
+impl Coverage {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub(super) fn record(&mut self, pc: u16) {
+        *self.hits.entry(pc).or_default() += 1;
+    }
+
+    /// Times the instruction at `addr` was executed
+    pub fn hits(&self, addr: u16) -> u64 {
+        self.hits.get(&addr).copied().unwrap_or_default()
+    }
+
+    /// Executed instruction addresses in order, with their hit counts
+    pub fn executed(&self) -> impl Iterator<Item = (u16, u64)> + '_ {
+        self.hits.iter().map(|(&addr, &hits)| (addr, hits))
+    }
+
+    /// Instructions in `map` which never ran
+    pub fn missed(&self, map: &SourceMap) -> Vec<u16> {
+        map.lines()
+            .map(|(addr, _)| addr)
+            .filter(|&addr| self.hits(addr) == 0)
+            .collect()
+    }
+
+    /// Per source line coverage in lcov tracefile format
+    ///
+    /// Every line in `map` is reported, a line's hits are those of its most
+    /// executed instruction.
+    pub fn write_lcov<W: io::Write + ?Sized>(
+        &self,
+        out: &mut W,
+        map: &SourceMap,
+    ) -> io::Result<()> {
+        let mut lines = BTreeMap::new();
+        for (addr, line) in map.lines() {
+            let hits = lines.entry(line).or_default();
+            *hits = self.hits(addr).max(*hits);
+        }
+        writeln!(out, "TN:")?;
+        writeln!(out, "SF:{}", map.source().display())?;
+        for (line, hits) in &lines {
+            writeln!(out, "DA:{},{}", line, hits)?;
+        }
+        writeln!(out, "LF:{}", lines.len())?;
+        let hit = lines.values().filter(|&&hits| hits > 0).count();
+        writeln!(out, "LH:{}", hit)?;
+        writeln!(out, "end_of_record")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_map() -> SourceMap {
+        let mut map = SourceMap::new("prog.asm");
+        map.insert(0, 1);
+        map.insert(3, 2);
+        map.insert(4, 2);
+        map.insert(7, 4);
+        map
+    }
+
+    #[test]
+    fn coverage_hits() {
+        let mut coverage = Coverage::new();
+        coverage.record(3);
+        coverage.record(0);
+        coverage.record(3);
+        assert_eq!(2, coverage.hits(3));
+        assert_eq!(0, coverage.hits(4));
+        assert_eq!(
+            vec![(0, 1), (3, 2)],
+            coverage.executed().collect::<Vec<_>>()
+        );
+        assert_eq!(vec![4, 7], coverage.missed(&test_map()));
+    }
+
+    #[test]
+    fn coverage_lcov() {
+        let mut coverage = Coverage::new();
+        coverage.record(0);
+        coverage.record(4);
+        coverage.record(4);
+        let mut out = Vec::new();
+        coverage
+            .write_lcov(&mut out, &test_map())
+            .expect("writing to a vector");
+        let expected = "\
+TN:
+SF:prog.asm
+DA:1,1
+DA:2,2
+DA:4,0
+LF:3
+LH:2
+end_of_record
+";
+        assert_eq!(expected, String::from_utf8(out).expect("utf8"));
+    }
+}
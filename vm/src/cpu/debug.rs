@@ -0,0 +1,188 @@
+use crate::memory::{Access, AccessKind};
+use std::collections::BTreeSet;
+use std::ops::RangeInclusive;
+
+/// Outcome of a single [`Cpu::step`](super::Cpu::step)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Step {
+    Executed,
+    Stopped(StopReason),
+}
+
+/// Why execution stopped without an [`Error`](super::Error)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StopReason {
+    /// About to execute the instruction at this address
+    Breakpoint(u16),
+    /// The last instruction made a watched memory access
+    Watchpoint(Access),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Watch {
+    Read,
+    Write,
+    ReadWrite,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Watchpoint {
+    pub range: RangeInclusive<u16>,
+    pub watch: Watch,
+}
+
+#[derive(Debug, Default)]
+pub(super) struct DebugState {
+    breakpoints: BTreeSet<u16>,
+    watchpoints: Vec<Watchpoint>,
+    // breakpoint already reported, execute it on the next step
+    resume_at: Option<u16>,
+}
+
+impl Watch {
+    fn matches(&self, kind: AccessKind) -> bool {
+        matches!(
+            (self, kind),
+            (Watch::Read | Watch::ReadWrite, AccessKind::Read)
+                | (Watch::Write | Watch::ReadWrite, AccessKind::Write)
+        )
+    }
+}
+
+impl Watchpoint {
+    pub fn new(range: RangeInclusive<u16>, watch: Watch) -> Self {
+        Self { range, watch }
+    }
+
+    pub fn is_hit_by(&self, access: &Access) -> bool {
+        self.watch.matches(access.kind) && self.range.contains(&access.addr)
+    }
+}
+
+impl DebugState {
+    pub fn add_breakpoint(&mut self, addr: u16) -> bool {
+        self.breakpoints.insert(addr)
+    }
+
+    pub fn remove_breakpoint(&mut self, addr: u16) -> bool {
+        self.breakpoints.remove(&addr)
+    }
+
+    pub fn clear_breakpoints(&mut self) {
+        self.breakpoints.clear();
+    }
+
+    pub fn breakpoints(&self) -> impl Iterator<Item = u16> + '_ {
+        self.breakpoints.iter().copied()
+    }
+
+    pub fn add_watchpoint(&mut self, watchpoint: Watchpoint) {
+        self.watchpoints.push(watchpoint);
+    }
+
+    pub fn remove_watchpoint(&mut self, watchpoint: &Watchpoint) -> bool {
+        let len = self.watchpoints.len();
+        self.watchpoints.retain(|wp| wp != watchpoint);
+        len != self.watchpoints.len()
+    }
+
+    pub fn clear_watchpoints(&mut self) {
+        self.watchpoints.clear();
+    }
+
+    pub fn watchpoints(&self) -> &[Watchpoint] {
+        &self.watchpoints
+    }
+
+    pub fn has_watchpoints(&self) -> bool {
+        !self.watchpoints.is_empty()
+    }
+
+    /// Check for a breakpoint at the current program counter
+    ///
+    /// A breakpoint only stops execution once, the following check at the
+    /// same address lets the instruction run.
+    pub fn check_breakpoint(&mut self, pc: u16) -> Option<StopReason> {
+        if self.resume_at.take() == Some(pc) || !self.breakpoints.contains(&pc) {
+            return None;
+        }
+        self.resume_at = Some(pc);
+        Some(StopReason::Breakpoint(pc))
+    }
+
+    pub fn check_watchpoints(&self, accesses: &[Access]) -> Option<StopReason> {
+        accesses
+            .iter()
+            .find(|access| self.watchpoints.iter().any(|wp| wp.is_hit_by(access)))
+            .map(|&access| StopReason::Watchpoint(access))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn breakpoint_stops_once() {
+        let mut debug = DebugState::default();
+        debug.add_breakpoint(0x10);
+        assert_eq!(
+            Some(StopReason::Breakpoint(0x10)),
+            debug.check_breakpoint(0x10)
+        );
+        assert_eq!(None, debug.check_breakpoint(0x10));
+        assert_eq!(
+            Some(StopReason::Breakpoint(0x10)),
+            debug.check_breakpoint(0x10)
+        );
+    }
+
+    #[test]
+    fn breakpoint_resume_cleared_by_other_address() {
+        let mut debug = DebugState::default();
+        debug.add_breakpoint(0x10);
+        debug.check_breakpoint(0x10);
+        assert_eq!(None, debug.check_breakpoint(0x20));
+        assert!(debug.check_breakpoint(0x10).is_some());
+    }
+
+    #[test]
+    fn watch_kinds() {
+        use AccessKind::*;
+        let cases = [
+            (Watch::Read, [true, false, false]),
+            (Watch::Write, [false, true, false]),
+            (Watch::ReadWrite, [true, true, false]),
+        ];
+        for (watch, expected) in cases {
+            let actual = [Read, Write, Fetch].map(|kind| watch.matches(kind));
+            assert_eq!(expected, actual, "{:?}", watch);
+        }
+    }
+
+    #[test]
+    fn watchpoint_range() {
+        let wp = Watchpoint::new(0x10..=0x11, Watch::Write);
+        let access = |addr| Access {
+            kind: AccessKind::Write,
+            addr,
+            value: 0,
+            previous: 0,
+        };
+        assert!(!wp.is_hit_by(&access(0x0f)));
+        assert!(wp.is_hit_by(&access(0x10)));
+        assert!(wp.is_hit_by(&access(0x11)));
+        assert!(!wp.is_hit_by(&access(0x12)));
+    }
+
+    #[test]
+    fn remove_watchpoint() {
+        let mut debug = DebugState::default();
+        let wp = Watchpoint::new(0x10..=0x11, Watch::Write);
+        debug.add_watchpoint(wp.clone());
+        assert!(debug.has_watchpoints());
+        assert!(debug.remove_watchpoint(&wp));
+        assert!(!debug.remove_watchpoint(&wp));
+        assert!(!debug.has_watchpoints());
+    }
+}
@@ -0,0 +1,494 @@
+use super::{
+    operation::{cmp, div, hlt, modulo, mov, mul, nop, Operation, MEMORY_CYCLES, WIDE_CYCLES},
+    AnyRegister, Cpu, OpResult, Register, WideRegister,
+};
+use crate::memory::Bus;
+use h8bit_asm as asm;
+use std::convert::Infallible;
+use std::fmt;
+
+/// A fully decoded instruction with its operands
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Instruction {
+    Nop,
+    Hlt,
+    MovLitReg {
+        literal: u8,
+        reg: Register,
+    },
+    MovLitRegWide {
+        literal: u16,
+        reg: WideRegister,
+    },
+    MovRegReg {
+        from: Register,
+        to: Register,
+    },
+    MovRegRegWide {
+        from: WideRegister,
+        to: WideRegister,
+    },
+    MovRegMem {
+        from: AnyRegister,
+        addr: u16,
+    },
+    MovMemReg {
+        addr: u16,
+        to: AnyRegister,
+    },
+    MovLitMem {
+        literal: u8,
+        addr: u16,
+    },
+    MovLitMemWide {
+        literal: u16,
+        addr: u16,
+    },
+    MovRegPtrReg {
+        from: WideRegister,
+        to: AnyRegister,
+    },
+    MovLitOffReg {
+        addr: u16,
+        from: WideRegister,
+        to: AnyRegister,
+    },
+    MulLitReg {
+        literal: u8,
+        reg: WideRegister,
+    },
+    MulLitRegWide {
+        literal: u16,
+        reg: WideRegister,
+    },
+    MulRegReg {
+        from: Register,
+        to: WideRegister,
+    },
+    MulRegRegWide {
+        from: WideRegister,
+        to: WideRegister,
+    },
+    DivLitReg {
+        literal: u8,
+        reg: Register,
+    },
+    DivLitRegWide {
+        literal: u16,
+        reg: WideRegister,
+    },
+    DivRegReg {
+        from: Register,
+        to: Register,
+    },
+    DivRegRegWide {
+        from: WideRegister,
+        to: WideRegister,
+    },
+    ModLitReg {
+        literal: u8,
+        reg: Register,
+    },
+    ModLitRegWide {
+        literal: u16,
+        reg: WideRegister,
+    },
+    ModRegReg {
+        from: Register,
+        to: Register,
+    },
+    ModRegRegWide {
+        from: WideRegister,
+        to: WideRegister,
+    },
+    CmpLitReg {
+        literal: u8,
+        reg: Register,
+    },
+    CmpLitRegWide {
+        literal: u16,
+        reg: WideRegister,
+    },
+    CmpRegReg {
+        from: Register,
+        to: Register,
+    },
+    CmpRegRegWide {
+        from: WideRegister,
+        to: WideRegister,
+    },
+    CmpMemReg {
+        addr: u16,
+        reg: AnyRegister,
+    },
+}
+
+/// Operand of a decoded [`Instruction`], used for display
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Operand {
+    Reg(AnyRegister),
+    Lit(u8),
+    LitWide(u16),
+    /// Memory at address
+    Addr(u16),
+    /// Memory at address held in a wide register
+    Ptr(WideRegister),
+    /// Memory at address plus wide register
+    Offset(u16, WideRegister),
+}
+
+impl Instruction {
+    pub fn execute<B: Bus>(&self, cpu: &mut Cpu<B>) -> OpResult {
+        use Instruction::*;
+        match *self {
+            Nop => nop::execute(cpu),
+            Hlt => hlt::execute(cpu),
+            MovLitReg { literal, reg } => mov::lit_reg::execute(cpu, literal, reg),
+            MovLitRegWide { literal, reg } => mov::lit_reg_wide::execute(cpu, literal, reg),
+            MovRegReg { from, to } => mov::reg_reg::execute(cpu, from, to),
+            MovRegRegWide { from, to } => mov::reg_reg::execute_wide(cpu, from, to),
+            MovRegMem { from, addr } => mov::reg_mem::execute(cpu, from, addr),
+            MovMemReg { addr, to } => mov::mem_reg::execute(cpu, addr, to),
+            MovLitMem { literal, addr } => mov::lit_mem::execute(cpu, literal, addr),
+            MovLitMemWide { literal, addr } => mov::lit_mem_wide::execute(cpu, literal, addr),
+            MovRegPtrReg { from, to } => mov::reg_ptr_reg::execute(cpu, from, to),
+            MovLitOffReg { addr, from, to } => mov::lit_off_reg::execute(cpu, addr, from, to),
+            MulLitReg { literal, reg } => mul::lit_reg::execute(cpu, literal, reg),
+            MulLitRegWide { literal, reg } => mul::lit_reg_wide::execute(cpu, literal, reg),
+            MulRegReg { from, to } => mul::reg_reg::execute(cpu, from, to),
+            MulRegRegWide { from, to } => mul::reg_reg::execute_wide(cpu, from, to),
+            DivLitReg { literal, reg } => div::lit_reg::execute(cpu, literal, reg),
+            DivLitRegWide { literal, reg } => div::lit_reg_wide::execute(cpu, literal, reg),
+            DivRegReg { from, to } => div::reg_reg::execute(cpu, from, to),
+            DivRegRegWide { from, to } => div::reg_reg::execute_wide(cpu, from, to),
+            ModLitReg { literal, reg } => modulo::lit_reg::execute(cpu, literal, reg),
+            ModLitRegWide { literal, reg } => modulo::lit_reg_wide::execute(cpu, literal, reg),
+            ModRegReg { from, to } => modulo::reg_reg::execute(cpu, from, to),
+            ModRegRegWide { from, to } => modulo::reg_reg::execute_wide(cpu, from, to),
+            CmpLitReg { literal, reg } => cmp::lit_reg::execute(cpu, literal, reg),
+            CmpLitRegWide { literal, reg } => cmp::lit_reg_wide::execute(cpu, literal, reg),
+            CmpRegReg { from, to } => cmp::reg_reg::execute(cpu, from, to),
+            CmpRegRegWide { from, to } => cmp::reg_reg::execute_wide(cpu, from, to),
+            CmpMemReg { addr, reg } => cmp::mem_reg::execute(cpu, addr, reg),
+        }
+    }
+
+    pub fn operation(&self) -> Operation {
+        use Instruction::*;
+        match self {
+            Nop => Operation::Nop,
+            Hlt => Operation::Hlt,
+            MovLitReg { .. } => Operation::MovLitReg,
+            MovLitRegWide { .. } => Operation::MovLitRegWide,
+            MovRegReg { .. } | MovRegRegWide { .. } => Operation::MovRegReg,
+            MovRegMem { .. } => Operation::MovRegMem,
+            MovMemReg { .. } => Operation::MovMemReg,
+            MovLitMem { .. } => Operation::MovLitMem,
+            MovLitMemWide { .. } => Operation::MovLitMemWide,
+            MovRegPtrReg { .. } => Operation::MovRegPtrReg,
+            MovLitOffReg { .. } => Operation::MovLitOffReg,
+            MulLitReg { .. } => Operation::MulLitReg,
+            MulLitRegWide { .. } => Operation::MulLitRegWide,
+            MulRegReg { .. } | MulRegRegWide { .. } => Operation::MulRegReg,
+            DivLitReg { .. } => Operation::DivLitReg,
+            DivLitRegWide { .. } => Operation::DivLitRegWide,
+            DivRegReg { .. } | DivRegRegWide { .. } => Operation::DivRegReg,
+            ModLitReg { .. } => Operation::ModLitReg,
+            ModLitRegWide { .. } => Operation::ModLitRegWide,
+            ModRegReg { .. } | ModRegRegWide { .. } => Operation::ModRegReg,
+            CmpLitReg { .. } => Operation::CmpLitReg,
+            CmpLitRegWide { .. } => Operation::CmpLitRegWide,
+            CmpRegReg { .. } | CmpRegRegWide { .. } => Operation::CmpRegReg,
+            CmpMemReg { .. } => Operation::CmpMemReg,
+        }
+    }
+
+    /// Machine code for the instruction, opcode first
+    pub fn encode(&self) -> Vec<u8> {
+        let name = self.operation().name();
+        let mnemonic = name.split('_').next().unwrap_or(name);
+        let operands: Vec<_> = self.operands().into_iter().map(asm_operand).collect();
+        asm::encode::<Infallible>(mnemonic, &operands)
+            .expect("decoded instructions encode")
+            .into_iter()
+            .map(|part| match part {
+                asm::Part::Byte(byte) => byte,
+                asm::Part::Label(never) => match never {},
+            })
+            .collect()
+    }
+
+    /// Clock cycles taken to execute, including fetching
+    pub fn cycles(&self) -> u8 {
+        let (memory, wide) = self.access();
+        let mut cycles = self.operation().cycles();
+        if wide {
+            cycles += WIDE_CYCLES;
+        }
+        if memory {
+            let bytes = if wide { 2 } else { 1 };
+            cycles += MEMORY_CYCLES * bytes;
+        }
+        cycles
+    }
+
+    /// Whether the instruction has a memory operand and moves a wide value
+    fn access(&self) -> (bool, bool) {
+        use Instruction::*;
+        match *self {
+            Nop
+            | Hlt
+            | MovLitReg { .. }
+            | MovRegReg { .. }
+            | DivLitReg { .. }
+            | DivRegReg { .. }
+            | ModLitReg { .. }
+            | ModRegReg { .. }
+            | CmpLitReg { .. }
+            | CmpRegReg { .. } => (false, false),
+            MovLitRegWide { .. }
+            | MovRegRegWide { .. }
+            | MulLitReg { .. }
+            | MulLitRegWide { .. }
+            | MulRegReg { .. }
+            | MulRegRegWide { .. }
+            | DivLitRegWide { .. }
+            | DivRegRegWide { .. }
+            | ModLitRegWide { .. }
+            | ModRegRegWide { .. }
+            | CmpLitRegWide { .. }
+            | CmpRegRegWide { .. } => (false, true),
+            MovLitMem { .. } => (true, false),
+            MovLitMemWide { .. } => (true, true),
+            MovRegMem { from: reg, .. }
+            | MovMemReg { to: reg, .. }
+            | MovRegPtrReg { to: reg, .. }
+            | MovLitOffReg { to: reg, .. }
+            | CmpMemReg { reg, .. } => (true, matches!(reg, AnyRegister::Wide(_))),
+        }
+    }
+
+    pub fn operands(&self) -> Vec<Operand> {
+        use Instruction::*;
+        use Operand::*;
+        match *self {
+            Nop | Hlt => vec![],
+            MovLitReg { literal, reg } => vec![Lit(literal), Reg(reg.into())],
+            MovLitRegWide { literal, reg } => vec![LitWide(literal), Reg(reg.into())],
+            MovRegReg { from, to } => vec![Reg(from.into()), Reg(to.into())],
+            MovRegRegWide { from, to } => vec![Reg(from.into()), Reg(to.into())],
+            MovRegMem { from, addr } => vec![Reg(from), Addr(addr)],
+            MovMemReg { addr, to } => vec![Addr(addr), Reg(to)],
+            MovLitMem { literal, addr } => vec![Lit(literal), Addr(addr)],
+            MovLitMemWide { literal, addr } => vec![LitWide(literal), Addr(addr)],
+            MovRegPtrReg { from, to } => vec![Ptr(from), Reg(to)],
+            MovLitOffReg { addr, from, to } => vec![Offset(addr, from), Reg(to)],
+            MulLitReg { literal, reg } => vec![Lit(literal), Reg(reg.into())],
+            DivLitReg { literal, reg }
+            | ModLitReg { literal, reg }
+            | CmpLitReg { literal, reg } => {
+                vec![Lit(literal), Reg(reg.into())]
+            }
+            MulLitRegWide { literal, reg }
+            | DivLitRegWide { literal, reg }
+            | ModLitRegWide { literal, reg }
+            | CmpLitRegWide { literal, reg } => vec![LitWide(literal), Reg(reg.into())],
+            MulRegReg { from, to } => vec![Reg(from.into()), Reg(to.into())],
+            DivRegReg { from, to } | ModRegReg { from, to } | CmpRegReg { from, to } => {
+                vec![Reg(from.into()), Reg(to.into())]
+            }
+            MulRegRegWide { from, to }
+            | DivRegRegWide { from, to }
+            | ModRegRegWide { from, to }
+            | CmpRegRegWide { from, to } => {
+                vec![Reg(from.into()), Reg(to.into())]
+            }
+            CmpMemReg { addr, reg } => vec![Addr(addr), Reg(reg)],
+        }
+    }
+}
+
+/// The assembler's form of a decoded operand
+fn asm_operand(operand: Operand) -> asm::Operand<Infallible> {
+    let lit = |value, wide| asm::Value::Lit { value, wide };
+    let reg = |reg: AnyRegister| asm::Reg {
+        code: reg.into(),
+        wide: matches!(reg, AnyRegister::Wide(_)),
+    };
+    match operand {
+        Operand::Reg(any) => asm::Operand::Reg(reg(any)),
+        Operand::Lit(value) => asm::Operand::Value(lit(value.into(), false)),
+        Operand::LitWide(value) => asm::Operand::Value(lit(value, true)),
+        Operand::Addr(addr) => asm::Operand::Mem(lit(addr, true)),
+        Operand::Ptr(wide) => asm::Operand::Ptr(reg(wide.into())),
+        Operand::Offset(addr, wide) => asm::Operand::Offset(lit(addr, true), reg(wide.into())),
+    }
+}
+
+impl fmt::Display for Operand {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Operand::Reg(AnyRegister::Std(reg)) => write!(f, "{}", reg),
+            Operand::Reg(AnyRegister::Wide(reg)) => write!(f, "{}", reg),
+            Operand::Lit(value) => write!(f, "{:#04x}", value),
+            Operand::LitWide(value) => write!(f, "{:#06x}", value),
+            Operand::Addr(addr) => write!(f, "[{:#06x}]", addr),
+            Operand::Ptr(reg) => write!(f, "[{}]", reg),
+            Operand::Offset(addr, reg) => write!(f, "[{:#06x} + {}]", addr, reg),
+        }
+    }
+}
+
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.operation().name())?;
+        for (i, operand) in self.operands().iter().enumerate() {
+            let sep = if i == 0 { " " } else { ", " };
+            write!(f, "{}{}", sep, operand)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cpu::tests::create_cpu_with_memory;
+    use crate::memory::TestDevice;
+
+    #[test]
+    fn display_no_operands() {
+        assert_eq!("HLT", Instruction::Hlt.to_string());
+    }
+
+    #[test]
+    fn display_operands() {
+        let instruction = Instruction::MovLitOffReg {
+            addr: 0x10,
+            from: WideRegister::EF,
+            to: Register::A.into(),
+        };
+        assert_eq!("MOV_LIT_OFF_REG [0x0010 + EF], A", instruction.to_string());
+    }
+
+    #[test]
+    fn cycles_register_only() {
+        let instruction = Instruction::MovLitReg {
+            literal: 0xab,
+            reg: Register::A,
+        };
+        assert_eq!(mov::lit_reg::CYCLES, instruction.cycles());
+    }
+
+    #[test]
+    fn cycles_wide_register() {
+        let instruction = Instruction::MovRegRegWide {
+            from: WideRegister::AB,
+            to: WideRegister::CD,
+        };
+        assert_eq!(mov::reg_reg::CYCLES + WIDE_CYCLES, instruction.cycles());
+    }
+
+    #[test]
+    fn cycles_memory() {
+        let instruction = Instruction::MovMemReg {
+            addr: 0x10,
+            to: Register::A.into(),
+        };
+        assert_eq!(mov::mem_reg::CYCLES + MEMORY_CYCLES, instruction.cycles());
+    }
+
+    #[test]
+    fn cycles_wide_memory() {
+        let instruction = Instruction::MovMemReg {
+            addr: 0x10,
+            to: WideRegister::AB.into(),
+        };
+        let expected = mov::mem_reg::CYCLES + WIDE_CYCLES + 2 * MEMORY_CYCLES;
+        assert_eq!(expected, instruction.cycles());
+    }
+
+    #[test]
+    fn encode_decode_round_trip() {
+        let instructions = [
+            Instruction::Hlt,
+            Instruction::MovLitRegWide {
+                literal: 0x1234,
+                reg: WideRegister::CD,
+            },
+            Instruction::MovRegRegWide {
+                from: WideRegister::AB,
+                to: WideRegister::SP,
+            },
+            Instruction::MovRegMem {
+                from: Register::B.into(),
+                addr: 0x80,
+            },
+            Instruction::MovLitMemWide {
+                literal: 0xabcd,
+                addr: 0x90,
+            },
+            Instruction::MovLitOffReg {
+                addr: 0x10,
+                from: WideRegister::EF,
+                to: WideRegister::GH.into(),
+            },
+            Instruction::MulLitReg {
+                literal: 0x10,
+                reg: WideRegister::AB,
+            },
+            Instruction::MulRegRegWide {
+                from: WideRegister::AB,
+                to: WideRegister::CD,
+            },
+            Instruction::DivLitRegWide {
+                literal: 0x0100,
+                reg: WideRegister::EF,
+            },
+            Instruction::ModRegReg {
+                from: Register::C,
+                to: Register::D,
+            },
+            Instruction::CmpLitReg {
+                literal: 0xab,
+                reg: Register::E,
+            },
+            Instruction::CmpRegRegWide {
+                from: WideRegister::AB,
+                to: WideRegister::GH,
+            },
+            Instruction::CmpMemReg {
+                addr: 0x80,
+                reg: WideRegister::CD.into(),
+            },
+        ];
+        let program: Vec<u8> = instructions.iter().flat_map(Instruction::encode).collect();
+        let mut mem = TestDevice::new(0x100);
+        mem.write_slice(&program);
+        let mut cpu = create_cpu_with_memory(mem);
+        for expected in instructions {
+            let opcode = cpu.fetch().expect("in memory");
+            let actual = Operation::from(opcode).decode(&mut cpu).expect("valid");
+            assert_eq!(expected, actual);
+        }
+    }
+
+    #[test]
+    fn reg_reg_wide_is_same_operation() {
+        let instruction = Instruction::MovRegRegWide {
+            from: WideRegister::AB,
+            to: WideRegister::CD,
+        };
+        assert_eq!(Operation::MovRegReg, instruction.operation());
+    }
+
+    #[test]
+    fn mul_byte_is_wide() {
+        let instruction = Instruction::MulRegReg {
+            from: Register::A,
+            to: WideRegister::CD,
+        };
+        assert_eq!(mul::reg_reg::CYCLES + WIDE_CYCLES, instruction.cycles());
+        assert_eq!("MUL_REG_REG A, CD", instruction.to_string());
+    }
+}
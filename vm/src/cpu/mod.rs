@@ -1,72 +1,288 @@
-use self::register::RegisterState;
+use self::cache::DecodeCache;
+use self::debug::DebugState;
+use self::rewind::UndoLog;
 use self::{operation::Operation, register::InvalidRegister};
-use crate::memory::{Device, DeviceError, MemoryMapper};
+use crate::memory::{Bus, DeviceError, MemoryMapper};
 use std::fmt;
-use std::fmt::Write;
+use std::io;
+use std::ops::RangeInclusive;
 
-pub use register::{AnyRegister, Register, WideRegister};
+pub use coverage::Coverage;
+pub use debug::{Step, StopReason, Watch, Watchpoint};
+pub use instruction::{Instruction, Operand};
+pub use profile::{FunctionProfile, Profiler};
+pub use program::{ProgramBuilder, ProgramError, Target};
+pub use register::{AnyRegister, Flags, Register, RegisterState, WideRegister};
+pub use rewind::RewindError;
+pub use snapshot::{Snapshot, SnapshotError, SNAPSHOT_VERSION};
+pub use trace::{RegisterChange, TraceFormat, TraceRecord, Tracer};
 
+mod cache;
+mod coverage;
+mod debug;
+mod instruction;
 pub mod operation;
+mod profile;
+mod program;
 mod register;
+mod rewind;
+mod snapshot;
 #[cfg(test)]
 mod tests;
+mod trace;
 
 const PC: WideRegister = WideRegister::PC;
 #[allow(dead_code)]
 const SP: WideRegister = WideRegister::SP;
 
+/// The processor, running against a [`Bus`]
+///
+/// Defaults to a [`MemoryMapper`], which maps devices at runtime.
 #[derive(Debug)]
-pub struct Cpu {
+pub struct Cpu<B: Bus = MemoryMapper> {
     registers: RegisterState,
-    memory: MemoryMapper,
+    memory: B,
+    debug: DebugState,
+    tracer: Option<Tracer>,
+    coverage: Option<Coverage>,
+    profiler: Option<Profiler>,
+    cycles: u64,
+    instructions: u64,
+    rewind: UndoLog,
+    decode_cache: DecodeCache,
 }
 
-impl Cpu {
-    pub fn new(mem_map: MemoryMapper) -> Result<Self, Error> {
+impl<B: Bus> Cpu<B> {
+    pub fn new(mem_map: B) -> Result<Self, Error> {
         let pc = mem_map.start().ok_or(Error::NoMemory)?;
         let sp = mem_map.end().ok_or(Error::NoMemory)?;
         let mut cpu = Self {
             registers: RegisterState::new(),
             memory: mem_map,
+            debug: DebugState::default(),
+            tracer: None,
+            coverage: None,
+            profiler: None,
+            cycles: 0,
+            instructions: 0,
+            rewind: UndoLog::default(),
+            decode_cache: DecodeCache::default(),
         };
         cpu.registers.set_wide(WideRegister::PC, pc);
         cpu.registers.set_wide(WideRegister::SP, sp);
         Ok(cpu)
     }
 
-    pub fn display_memory_at(&self, addr: u16) {
-        let mut output = format!("{:#06x}:", addr);
-        for a in addr..addr + 8 {
-            let value = self.memory.get(a).unwrap();
-            write!(output, " {:02x}", value).expect("display memory");
+    pub fn registers(&self) -> &RegisterState {
+        &self.registers
+    }
+
+    pub fn registers_mut(&mut self) -> &mut RegisterState {
+        &mut self.registers
+    }
+
+    pub fn memory(&self) -> &B {
+        &self.memory
+    }
+
+    pub fn memory_mut(&mut self) -> &mut B {
+        // the host may change code in ways the CPU never sees
+        self.decode_cache.clear();
+        &mut self.memory
+    }
+
+    /// Clock cycles executed since the CPU was created
+    pub fn cycles(&self) -> u64 {
+        self.cycles
+    }
+
+    /// Instructions executed since the CPU was created
+    pub fn instructions(&self) -> u64 {
+        self.instructions
+    }
+
+    /// Run until at least `cycles` more clock cycles have passed
+    ///
+    /// The last instruction may overrun the budget, check [`Cpu::cycles`]
+    /// for the exact count. Returns early if a breakpoint or watchpoint is hit.
+    pub fn run_for_cycles(&mut self, cycles: u64) -> Result<Option<StopReason>, Error> {
+        let target = self.cycles + cycles;
+        while self.cycles < target {
+            if let Step::Stopped(reason) = self.step()? {
+                return Ok(Some(reason));
+            }
         }
-        println!("{}", output);
+        Ok(None)
     }
 
-    pub fn run(&mut self) {
+    /// Run until a breakpoint or watchpoint is hit, or an error occurs
+    pub fn run(&mut self) -> Result<StopReason, Error> {
         loop {
-            if let Err(_err) = self.step() {
-                return;
+            if let Step::Stopped(reason) = self.step()? {
+                return Ok(reason);
+            }
+        }
+    }
+
+    pub fn step(&mut self) -> Result<Step, Error> {
+        let pc = self.registers.get_wide(PC);
+        if let Some(reason) = self.debug.check_breakpoint(pc) {
+            return Ok(Step::Stopped(reason));
+        }
+        // accesses made from outside the CPU belong to no instruction
+        self.memory.take_accesses();
+        let keep_before = self.tracer.is_some() || self.rewind.is_enabled();
+        let before = keep_before.then(|| self.registers.clone());
+        let cycles = self.cycles;
+        let (opcode, decoded) = match self.cached_instruction(pc) {
+            Some((opcode, instruction)) => (opcode, Ok(instruction)),
+            None => {
+                let opcode = self.fetch()?;
+                let decoded = Operation::from(opcode).decode(self);
+                if let Ok(instruction) = decoded {
+                    self.cache_instruction(pc, opcode, instruction);
+                }
+                (opcode, decoded)
+            }
+        };
+        let result = match &decoded {
+            Ok(instruction) => instruction.execute(self),
+            Err(_) => Ok(()),
+        };
+        let accesses = self.memory.take_accesses();
+        self.invalidate_cache(&accesses);
+        let instruction = decoded?;
+        // a failed trace must not lose the instruction's undo entry or counts
+        let mut traced = Ok(());
+        if let Some(before) = before {
+            if let Some(ref mut tracer) = self.tracer {
+                let record =
+                    TraceRecord::new(pc, opcode, instruction, &before, &self.registers, &accesses);
+                traced = tracer.record(&record);
             }
+            let instructions = self.instructions;
+            self.rewind.record(before, cycles, instructions, &accesses);
+        }
+        // halting completes the instruction, other errors abort it
+        if matches!(result, Ok(()) | Err(Error::Halt)) {
+            self.account(pc, &instruction);
+        }
+        traced?;
+        result?;
+        match self.debug.check_watchpoints(&accesses) {
+            Some(reason) => Ok(Step::Stopped(reason)),
+            None => Ok(Step::Executed),
         }
     }
 
-    pub fn step(&mut self) -> OpResult {
-        let opcode = self.fetch()?;
-        let operation = Operation::from(opcode);
-        operation.execute(self)
+    /// Count a completed instruction
+    fn account(&mut self, pc: u16, instruction: &Instruction) {
+        self.cycles += instruction.cycles() as u64;
+        self.instructions += 1;
+        if let Some(ref mut coverage) = self.coverage {
+            coverage.record(pc);
+        }
+        if let Some(ref mut profiler) = self.profiler {
+            profiler.record(pc, instruction.cycles());
+        }
+    }
+
+    /// Stop before executing the instruction at `addr`
+    ///
+    /// Returns `false` if there was already a breakpoint at `addr`.
+    pub fn add_breakpoint(&mut self, addr: u16) -> bool {
+        self.debug.add_breakpoint(addr)
+    }
+
+    pub fn remove_breakpoint(&mut self, addr: u16) -> bool {
+        self.debug.remove_breakpoint(addr)
+    }
+
+    pub fn clear_breakpoints(&mut self) {
+        self.debug.clear_breakpoints();
+    }
+
+    pub fn breakpoints(&self) -> impl Iterator<Item = u16> + '_ {
+        self.debug.breakpoints()
+    }
+
+    /// Stop after any instruction that accesses memory within `range`
+    pub fn add_watchpoint(&mut self, range: RangeInclusive<u16>, watch: Watch) {
+        self.debug.add_watchpoint(Watchpoint::new(range, watch));
+        self.update_observing();
+    }
+
+    pub fn remove_watchpoint(&mut self, range: RangeInclusive<u16>, watch: Watch) -> bool {
+        let removed = self.debug.remove_watchpoint(&Watchpoint::new(range, watch));
+        self.update_observing();
+        removed
+    }
+
+    pub fn clear_watchpoints(&mut self) {
+        self.debug.clear_watchpoints();
+        self.update_observing();
+    }
+
+    pub fn watchpoints(&self) -> &[Watchpoint] {
+        self.debug.watchpoints()
+    }
+
+    /// Trace every instruction executed from now on
+    pub fn set_tracer(&mut self, tracer: Tracer) {
+        self.tracer = Some(tracer);
+        self.update_observing();
+    }
+
+    pub fn take_tracer(&mut self) -> Option<Tracer> {
+        let tracer = self.tracer.take();
+        self.update_observing();
+        tracer
+    }
+
+    /// Record the address of every instruction executed from now on
+    pub fn set_coverage(&mut self, coverage: Coverage) {
+        self.coverage = Some(coverage);
+    }
+
+    pub fn coverage(&self) -> Option<&Coverage> {
+        self.coverage.as_ref()
+    }
+
+    pub fn take_coverage(&mut self) -> Option<Coverage> {
+        self.coverage.take()
+    }
+
+    /// Attribute every instruction executed from now on to a function
+    pub fn set_profiler(&mut self, profiler: Profiler) {
+        self.profiler = Some(profiler);
+    }
+
+    pub fn profiler(&self) -> Option<&Profiler> {
+        self.profiler.as_ref()
+    }
+
+    pub fn take_profiler(&mut self) -> Option<Profiler> {
+        self.profiler.take()
+    }
+
+    fn update_observing(&mut self) {
+        let observing = self.debug.has_watchpoints()
+            || self.tracer.is_some()
+            || self.rewind.is_enabled()
+            || self.decode_cache.is_enabled();
+        self.memory.observe(observing);
     }
 
     fn fetch(&mut self) -> Result<u8, Error> {
         let addr = self.registers.get_wide(PC);
         self.registers.set_wide(PC, addr + 1);
-        Ok(self.memory.get(addr)?)
+        Ok(self.memory.fetch(addr)?)
     }
 
     fn fetch_wide(&mut self) -> Result<u16, Error> {
         let addr = self.registers.get_wide(PC);
         self.registers.set_wide(PC, addr + 2);
-        Ok(self.memory.get_wide(addr)?)
+        Ok(self.memory.fetch_wide(addr)?)
     }
 
     fn fetch_register(&mut self) -> Result<Register, Error> {
@@ -96,6 +312,10 @@ pub enum Error {
     InvalidRegister(#[from] InvalidRegister),
     #[error("no memory")]
     NoMemory,
+    #[error("divide by zero")]
+    DivideByZero,
+    #[error("trace output: {0}")]
+    Trace(#[from] io::Error),
     #[error("unknown internal error")]
     Unknown(Box<dyn std::error::Error>),
 }
@@ -109,7 +329,7 @@ impl From<DeviceError> for Error {
     }
 }
 
-impl fmt::Display for Cpu {
+impl<B: Bus> fmt::Display for Cpu<B> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(f, "CPU\n{}", self.registers)
     }
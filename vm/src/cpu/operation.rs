@@ -1,11 +1,22 @@
-use super::{Cpu, OpResult};
+use super::{Cpu, Error, Instruction, OpResult};
+use crate::memory::Bus;
 use std::fmt;
 use strum_macros::{FromRepr, IntoStaticStr};
 
+pub mod cmp;
+pub mod div;
 pub mod hlt;
+pub mod modulo;
 pub mod mov;
+pub mod mul;
 pub mod nop;
 
+/// Extra cycles for each byte of a memory operand
+pub const MEMORY_CYCLES: u8 = 2;
+
+/// Extra cycles for moving a wide value
+pub const WIDE_CYCLES: u8 = 1;
+
 #[derive(Debug, PartialEq, Copy, Clone, FromRepr, IntoStaticStr)]
 #[repr(u8)]
 pub enum Operation {
@@ -41,10 +52,49 @@ pub enum Operation {
 
     /// Move value at memory[wide literal + wide register] to register
     MovLitOffReg = mov::lit_off_reg::CODE,
+
+    /// Multiply wide register by literal, byte by byte into a wide result
+    MulLitReg = mul::lit_reg::CODE,
+
+    /// Multiply wide register by wide literal, keeping the low word
+    MulLitRegWide = mul::lit_reg_wide::CODE,
+
+    /// Multiply wide register by register
+    MulRegReg = mul::reg_reg::CODE,
+
+    /// Divide register by literal
+    DivLitReg = div::lit_reg::CODE,
+
+    /// Divide wide register by wide literal
+    DivLitRegWide = div::lit_reg_wide::CODE,
+
+    /// Divide register by register
+    DivRegReg = div::reg_reg::CODE,
+
+    /// Remainder of register divided by literal
+    ModLitReg = modulo::lit_reg::CODE,
+
+    /// Remainder of wide register divided by wide literal
+    ModLitRegWide = modulo::lit_reg_wide::CODE,
+
+    /// Remainder of register divided by register
+    ModRegReg = modulo::reg_reg::CODE,
+
+    /// Compare register with literal
+    CmpLitReg = cmp::lit_reg::CODE,
+
+    /// Compare wide register with wide literal
+    CmpLitRegWide = cmp::lit_reg_wide::CODE,
+
+    /// Compare register with register
+    CmpRegReg = cmp::reg_reg::CODE,
+
+    /// Compare register with memory
+    CmpMemReg = cmp::mem_reg::CODE,
 }
 
 impl Operation {
-    pub fn execute(&self, cpu: &mut Cpu) -> OpResult {
+    pub fn execute<B: Bus>(&self, cpu: &mut Cpu<B>) -> OpResult {
         match self {
             Operation::Nop => nop::run(cpu),
             Operation::Hlt => hlt::run(cpu),
@@ -57,6 +107,109 @@ impl Operation {
             Operation::MovLitMemWide => mov::lit_mem_wide::run(cpu),
             Operation::MovRegPtrReg => mov::reg_ptr_reg::run(cpu),
             Operation::MovLitOffReg => mov::lit_off_reg::run(cpu),
+            Operation::MulLitReg => mul::lit_reg::run(cpu),
+            Operation::MulLitRegWide => mul::lit_reg_wide::run(cpu),
+            Operation::MulRegReg => mul::reg_reg::run(cpu),
+            Operation::DivLitReg => div::lit_reg::run(cpu),
+            Operation::DivLitRegWide => div::lit_reg_wide::run(cpu),
+            Operation::DivRegReg => div::reg_reg::run(cpu),
+            Operation::ModLitReg => modulo::lit_reg::run(cpu),
+            Operation::ModLitRegWide => modulo::lit_reg_wide::run(cpu),
+            Operation::ModRegReg => modulo::reg_reg::run(cpu),
+            Operation::CmpLitReg => cmp::lit_reg::run(cpu),
+            Operation::CmpLitRegWide => cmp::lit_reg_wide::run(cpu),
+            Operation::CmpRegReg => cmp::reg_reg::run(cpu),
+            Operation::CmpMemReg => cmp::mem_reg::run(cpu),
+        }
+    }
+
+    /// Fetch operands following the opcode
+    pub fn decode<B: Bus>(&self, cpu: &mut Cpu<B>) -> Result<Instruction, Error> {
+        match self {
+            Operation::Nop => nop::decode(cpu),
+            Operation::Hlt => hlt::decode(cpu),
+            Operation::MovLitReg => mov::lit_reg::decode(cpu),
+            Operation::MovLitRegWide => mov::lit_reg_wide::decode(cpu),
+            Operation::MovRegReg => mov::reg_reg::decode(cpu),
+            Operation::MovRegMem => mov::reg_mem::decode(cpu),
+            Operation::MovMemReg => mov::mem_reg::decode(cpu),
+            Operation::MovLitMem => mov::lit_mem::decode(cpu),
+            Operation::MovLitMemWide => mov::lit_mem_wide::decode(cpu),
+            Operation::MovRegPtrReg => mov::reg_ptr_reg::decode(cpu),
+            Operation::MovLitOffReg => mov::lit_off_reg::decode(cpu),
+            Operation::MulLitReg => mul::lit_reg::decode(cpu),
+            Operation::MulLitRegWide => mul::lit_reg_wide::decode(cpu),
+            Operation::MulRegReg => mul::reg_reg::decode(cpu),
+            Operation::DivLitReg => div::lit_reg::decode(cpu),
+            Operation::DivLitRegWide => div::lit_reg_wide::decode(cpu),
+            Operation::DivRegReg => div::reg_reg::decode(cpu),
+            Operation::ModLitReg => modulo::lit_reg::decode(cpu),
+            Operation::ModLitRegWide => modulo::lit_reg_wide::decode(cpu),
+            Operation::ModRegReg => modulo::reg_reg::decode(cpu),
+            Operation::CmpLitReg => cmp::lit_reg::decode(cpu),
+            Operation::CmpLitRegWide => cmp::lit_reg_wide::decode(cpu),
+            Operation::CmpRegReg => cmp::reg_reg::decode(cpu),
+            Operation::CmpMemReg => cmp::mem_reg::decode(cpu),
+        }
+    }
+
+    /// Base clock cycles, before memory and wide access costs
+    pub fn cycles(&self) -> u8 {
+        match self {
+            Operation::Nop => nop::CYCLES,
+            Operation::Hlt => hlt::CYCLES,
+            Operation::MovLitReg => mov::lit_reg::CYCLES,
+            Operation::MovLitRegWide => mov::lit_reg_wide::CYCLES,
+            Operation::MovRegReg => mov::reg_reg::CYCLES,
+            Operation::MovRegMem => mov::reg_mem::CYCLES,
+            Operation::MovMemReg => mov::mem_reg::CYCLES,
+            Operation::MovLitMem => mov::lit_mem::CYCLES,
+            Operation::MovLitMemWide => mov::lit_mem_wide::CYCLES,
+            Operation::MovRegPtrReg => mov::reg_ptr_reg::CYCLES,
+            Operation::MovLitOffReg => mov::lit_off_reg::CYCLES,
+            Operation::MulLitReg => mul::lit_reg::CYCLES,
+            Operation::MulLitRegWide => mul::lit_reg_wide::CYCLES,
+            Operation::MulRegReg => mul::reg_reg::CYCLES,
+            Operation::DivLitReg => div::lit_reg::CYCLES,
+            Operation::DivLitRegWide => div::lit_reg_wide::CYCLES,
+            Operation::DivRegReg => div::reg_reg::CYCLES,
+            Operation::ModLitReg => modulo::lit_reg::CYCLES,
+            Operation::ModLitRegWide => modulo::lit_reg_wide::CYCLES,
+            Operation::ModRegReg => modulo::reg_reg::CYCLES,
+            Operation::CmpLitReg => cmp::lit_reg::CYCLES,
+            Operation::CmpLitRegWide => cmp::lit_reg_wide::CYCLES,
+            Operation::CmpRegReg => cmp::reg_reg::CYCLES,
+            Operation::CmpMemReg => cmp::mem_reg::CYCLES,
+        }
+    }
+
+    /// Assembly name of the operation
+    pub fn name(&self) -> &'static str {
+        match self {
+            Operation::Nop => nop::NAME,
+            Operation::Hlt => hlt::NAME,
+            Operation::MovLitReg => mov::lit_reg::NAME,
+            Operation::MovLitRegWide => mov::lit_reg_wide::NAME,
+            Operation::MovRegReg => mov::reg_reg::NAME,
+            Operation::MovRegMem => mov::reg_mem::NAME,
+            Operation::MovMemReg => mov::mem_reg::NAME,
+            Operation::MovLitMem => mov::lit_mem::NAME,
+            Operation::MovLitMemWide => mov::lit_mem_wide::NAME,
+            Operation::MovRegPtrReg => mov::reg_ptr_reg::NAME,
+            Operation::MovLitOffReg => mov::lit_off_reg::NAME,
+            Operation::MulLitReg => mul::lit_reg::NAME,
+            Operation::MulLitRegWide => mul::lit_reg_wide::NAME,
+            Operation::MulRegReg => mul::reg_reg::NAME,
+            Operation::DivLitReg => div::lit_reg::NAME,
+            Operation::DivLitRegWide => div::lit_reg_wide::NAME,
+            Operation::DivRegReg => div::reg_reg::NAME,
+            Operation::ModLitReg => modulo::lit_reg::NAME,
+            Operation::ModLitRegWide => modulo::lit_reg_wide::NAME,
+            Operation::ModRegReg => modulo::reg_reg::NAME,
+            Operation::CmpLitReg => cmp::lit_reg::NAME,
+            Operation::CmpLitRegWide => cmp::lit_reg_wide::NAME,
+            Operation::CmpRegReg => cmp::reg_reg::NAME,
+            Operation::CmpMemReg => cmp::mem_reg::NAME,
         }
     }
 
@@ -95,10 +248,7 @@ impl fmt::Display for Operation {
 #[cfg(test)]
 pub mod tests {
     use super::*;
-    use crate::cpu::{
-        tests::{assert_cpu_state, TestCpuState},
-        Error,
-    };
+    use crate::cpu::tests::{assert_cpu_state, TestCpuState};
 
     pub const TEST_OP_MEM_SIZE: u16 = 256;
 
@@ -0,0 +1,81 @@
+use crate::cpu::{Cpu, Error, Flags, Instruction, OpResult, Register};
+use crate::memory::Bus;
+use h8bit_asm::opcode;
+
+pub const CODE: u8 = opcode::CMP_LIT_REG;
+pub const NAME: &str = "CMP_LIT_REG";
+pub const SIZE: u8 = 3;
+pub const CYCLES: u8 = 3;
+
+pub(in crate::cpu::operation) fn run<B: Bus>(cpu: &mut Cpu<B>) -> OpResult {
+    decode(cpu)?.execute(cpu)
+}
+
+pub(in crate::cpu) fn decode<B: Bus>(cpu: &mut Cpu<B>) -> Result<Instruction, Error> {
+    let literal = cpu.fetch()?;
+    let reg = cpu.fetch_register()?;
+    Ok(Instruction::CmpLitReg { literal, reg })
+}
+
+pub(in crate::cpu) fn execute<B: Bus>(cpu: &mut Cpu<B>, literal: u8, reg: Register) -> OpResult {
+    let flags = Flags::compare(cpu.registers.get(reg), literal);
+    cpu.registers.set_flags(flags);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cpu::{
+        operation::tests::{
+            op_run_success, test_builder_size, test_invalid_register, test_run_no_mem,
+        },
+        tests::TestCpuState,
+    };
+
+    #[test]
+    fn equal() {
+        let mut expected = TestCpuState::new();
+        expected.reg(Register::C, 0xAB).flags(Flags {
+            zero: true,
+            ..Flags::default()
+        });
+
+        op_run_success(&expected, &mut builder(0xAB, Register::C, 0xAB), run);
+    }
+
+    #[test]
+    fn below() {
+        let mut expected = TestCpuState::new();
+        expected.reg(Register::C, 0x10).flags(Flags {
+            carry: true,
+            negative: true,
+            ..Flags::default()
+        });
+
+        op_run_success(&expected, &mut builder(0x20, Register::C, 0x10), run);
+    }
+
+    #[test]
+    fn clears_flags() {
+        let mut expected = TestCpuState::new();
+        expected.flags(Flags::default());
+
+        let mut build = builder(0x01, Register::C, 0x10);
+        build.flags(Flags::from_bits(0b111));
+        op_run_success(&expected, &mut build, run);
+    }
+
+    test_builder_size!(builder(0xAB, Register::C, 0), SIZE);
+
+    test_invalid_register!(&[0xAB, 0x00], 0x00);
+
+    test_run_no_mem!();
+
+    fn builder(literal: u8, reg: Register, value: u8) -> TestCpuState {
+        let opargs = [literal, reg.into()];
+        let mut build = TestCpuState::new_with_program(&opargs);
+        build.reg(reg, value);
+        build
+    }
+}
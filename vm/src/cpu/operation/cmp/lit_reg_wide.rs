@@ -0,0 +1,88 @@
+use crate::cpu::{Cpu, Error, Flags, Instruction, OpResult, WideRegister};
+use crate::memory::Bus;
+use h8bit_asm::opcode;
+
+pub const CODE: u8 = opcode::CMP_LIT_REG_WIDE;
+pub const NAME: &str = "CMP_LIT_REG_WIDE";
+pub const SIZE: u8 = 4;
+pub const CYCLES: u8 = 4;
+
+pub(in crate::cpu::operation) fn run<B: Bus>(cpu: &mut Cpu<B>) -> OpResult {
+    decode(cpu)?.execute(cpu)
+}
+
+pub(in crate::cpu) fn decode<B: Bus>(cpu: &mut Cpu<B>) -> Result<Instruction, Error> {
+    let literal = cpu.fetch_wide()?;
+    let reg = cpu.fetch_register_wide()?;
+    Ok(Instruction::CmpLitRegWide { literal, reg })
+}
+
+pub(in crate::cpu) fn execute<B: Bus>(
+    cpu: &mut Cpu<B>,
+    literal: u16,
+    reg: WideRegister,
+) -> OpResult {
+    let flags = Flags::compare_wide(cpu.registers.get_wide(reg), literal);
+    cpu.registers.set_flags(flags);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        cpu::{
+            operation::tests::{
+                op_run_success, test_builder_size, test_invalid_register, test_run_no_mem,
+            },
+            tests::TestCpuState,
+        },
+        util::high_and_low_value,
+    };
+
+    #[test]
+    fn equal() {
+        let mut expected = TestCpuState::new();
+        expected.reg_wide(WideRegister::CD, 0xABCD).flags(Flags {
+            zero: true,
+            ..Flags::default()
+        });
+
+        op_run_success(
+            &expected,
+            &mut builder(0xABCD, WideRegister::CD, 0xABCD),
+            run,
+        );
+    }
+
+    #[test]
+    fn below() {
+        // only the high byte differs
+        let mut expected = TestCpuState::new();
+        expected.flags(Flags {
+            carry: true,
+            negative: true,
+            ..Flags::default()
+        });
+
+        op_run_success(
+            &expected,
+            &mut builder(0x0200, WideRegister::CD, 0x0100),
+            run,
+        );
+    }
+
+    test_builder_size!(builder(0xABCD, WideRegister::AB, 0), SIZE);
+
+    test_invalid_register!(&[0xAB, 0xCD, 0x00], 0x00);
+
+    test_run_no_mem!();
+
+    fn builder(literal: u16, reg: WideRegister, value: u16) -> TestCpuState {
+        let (high, low) = high_and_low_value(literal);
+        let opargs = [high, low, reg.into()];
+        let mut build = TestCpuState::new_with_program(&opargs);
+        build.reg_wide(reg, value);
+        build
+    }
+}
@@ -0,0 +1,26 @@
+use crate::{
+    cpu::{AnyRegister, Cpu, Flags, OpResult},
+    memory::Bus,
+};
+
+pub mod lit_reg;
+pub mod lit_reg_wide;
+
+pub mod reg_reg;
+
+pub mod mem_reg;
+
+fn cmp_mem_reg<B: Bus>(cpu: &mut Cpu<B>, addr: u16, reg: AnyRegister) -> OpResult {
+    let flags = match reg {
+        AnyRegister::Std(reg) => {
+            let value = cpu.memory.get(addr)?;
+            Flags::compare(cpu.registers.get(reg), value)
+        }
+        AnyRegister::Wide(reg) => {
+            let value = cpu.memory.get_wide(addr)?;
+            Flags::compare_wide(cpu.registers.get_wide(reg), value)
+        }
+    };
+    cpu.registers.set_flags(flags);
+    Ok(())
+}
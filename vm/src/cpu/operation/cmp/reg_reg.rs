@@ -0,0 +1,106 @@
+use crate::cpu::{AnyRegister, Cpu, Error, Flags, Instruction, OpResult, Register, WideRegister};
+use crate::memory::Bus;
+use h8bit_asm::opcode;
+
+pub const CODE: u8 = opcode::CMP_REG_REG;
+pub const NAME: &str = "CMP_REG_REG";
+pub const SIZE: u8 = 3;
+pub const CYCLES: u8 = 3;
+
+pub(in crate::cpu::operation) fn run<B: Bus>(cpu: &mut Cpu<B>) -> OpResult {
+    decode(cpu)?.execute(cpu)
+}
+
+pub(in crate::cpu) fn decode<B: Bus>(cpu: &mut Cpu<B>) -> Result<Instruction, Error> {
+    match cpu.fetch_any_register()? {
+        AnyRegister::Std(from) => {
+            let to = cpu.fetch_register()?;
+            Ok(Instruction::CmpRegReg { from, to })
+        }
+        AnyRegister::Wide(from) => {
+            let to = cpu.fetch_register_wide()?;
+            Ok(Instruction::CmpRegRegWide { from, to })
+        }
+    }
+}
+
+pub(in crate::cpu) fn execute<B: Bus>(cpu: &mut Cpu<B>, from: Register, to: Register) -> OpResult {
+    let flags = Flags::compare(cpu.registers.get(to), cpu.registers.get(from));
+    cpu.registers.set_flags(flags);
+    Ok(())
+}
+
+pub(in crate::cpu) fn execute_wide<B: Bus>(
+    cpu: &mut Cpu<B>,
+    from: WideRegister,
+    to: WideRegister,
+) -> OpResult {
+    let flags = Flags::compare_wide(cpu.registers.get_wide(to), cpu.registers.get_wide(from));
+    cpu.registers.set_flags(flags);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cpu::{
+        operation::tests::{
+            op_run_success, test_builder_size, test_invalid_register, test_run_no_mem,
+        },
+        tests::TestCpuState,
+    };
+
+    #[test]
+    fn success() {
+        let from = Register::C;
+        let to = Register::D;
+
+        let mut expected = TestCpuState::new();
+        expected
+            .reg(from, 0x20)
+            .reg(to, 0x30)
+            .flags(Flags::default());
+
+        let mut build = builder(from, to);
+        build.reg(from, 0x20).reg(to, 0x30);
+        op_run_success(&expected, &mut build, run);
+    }
+
+    #[test]
+    fn wide_success() {
+        let from = WideRegister::CD;
+        let to = WideRegister::EF;
+
+        let mut expected = TestCpuState::new();
+        expected.reg_wide(to, 0x1234).flags(Flags {
+            zero: true,
+            ..Flags::default()
+        });
+
+        let mut build = builder(from, to);
+        build.reg_wide(from, 0x1234).reg_wide(to, 0x1234);
+        op_run_success(&expected, &mut build, run);
+    }
+
+    test_builder_size!(builder(Register::A, Register::B), SIZE);
+
+    test_invalid_register!(&[0x00, Register::A.into()], 0x00, first);
+    test_invalid_register!(&[Register::A.into(), 0x00], 0x00, second);
+    test_invalid_register!(
+        &[Register::A.into(), WideRegister::CD.into()],
+        WideRegister::CD.into(),
+        second_wide
+    );
+    test_invalid_register!(
+        &[WideRegister::AB.into(), Register::C.into()],
+        Register::C.into(),
+        second_std
+    );
+
+    test_run_no_mem!();
+
+    fn builder(from: impl Into<AnyRegister>, to: impl Into<AnyRegister>) -> TestCpuState {
+        let opargs = [from.into().into(), to.into().into()];
+        TestCpuState::new_with_program(&opargs)
+    }
+}
@@ -0,0 +1,65 @@
+use crate::cpu::{Cpu, Error, Instruction, OpResult, Register};
+use crate::memory::Bus;
+use h8bit_asm::opcode;
+
+pub const CODE: u8 = opcode::DIV_LIT_REG;
+pub const NAME: &str = "DIV_LIT_REG";
+pub const SIZE: u8 = 3;
+pub const CYCLES: u8 = 10;
+
+pub(in crate::cpu::operation) fn run<B: Bus>(cpu: &mut Cpu<B>) -> OpResult {
+    decode(cpu)?.execute(cpu)
+}
+
+pub(in crate::cpu) fn decode<B: Bus>(cpu: &mut Cpu<B>) -> Result<Instruction, Error> {
+    let literal = cpu.fetch()?;
+    let reg = cpu.fetch_register()?;
+    Ok(Instruction::DivLitReg { literal, reg })
+}
+
+pub(in crate::cpu) fn execute<B: Bus>(cpu: &mut Cpu<B>, literal: u8, reg: Register) -> OpResult {
+    let value = cpu.registers.get(reg);
+    let value = value.checked_div(literal).ok_or(Error::DivideByZero)?;
+    cpu.registers.set(reg, value);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cpu::{
+        operation::tests::{
+            op_run_error, op_run_success, test_builder_size, test_invalid_register, test_run_no_mem,
+        },
+        tests::TestCpuState,
+    };
+
+    #[test]
+    fn success() {
+        let reg = Register::C;
+
+        let mut expected = TestCpuState::new();
+        expected.reg(reg, 0xAB / 0x10);
+
+        op_run_success(&expected, &mut builder(0x10, reg, 0xAB), run);
+    }
+
+    #[test]
+    fn divide_by_zero() {
+        let err = op_run_error(&mut builder(0, Register::C, 0xAB), run);
+        assert!(matches!(err, Error::DivideByZero));
+    }
+
+    test_builder_size!(builder(0x01, Register::C, 0), SIZE);
+
+    test_invalid_register!(&[0xAB, 0x00], 0x00);
+
+    test_run_no_mem!();
+
+    fn builder(literal: u8, reg: Register, value: u8) -> TestCpuState {
+        let opargs = [literal, reg.into()];
+        let mut build = TestCpuState::new_with_program(&opargs);
+        build.reg(reg, value);
+        build
+    }
+}
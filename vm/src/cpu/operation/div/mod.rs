@@ -0,0 +1,4 @@
+pub mod lit_reg;
+pub mod lit_reg_wide;
+
+pub mod reg_reg;
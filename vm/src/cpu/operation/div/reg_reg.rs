@@ -0,0 +1,126 @@
+use crate::cpu::{AnyRegister, Cpu, Error, Instruction, OpResult, Register, WideRegister};
+use crate::memory::Bus;
+use h8bit_asm::opcode;
+
+pub const CODE: u8 = opcode::DIV_REG_REG;
+pub const NAME: &str = "DIV_REG_REG";
+pub const SIZE: u8 = 3;
+pub const CYCLES: u8 = 10;
+
+pub(in crate::cpu::operation) fn run<B: Bus>(cpu: &mut Cpu<B>) -> OpResult {
+    decode(cpu)?.execute(cpu)
+}
+
+pub(in crate::cpu) fn decode<B: Bus>(cpu: &mut Cpu<B>) -> Result<Instruction, Error> {
+    match cpu.fetch_any_register()? {
+        AnyRegister::Std(from) => {
+            let to = cpu.fetch_register()?;
+            Ok(Instruction::DivRegReg { from, to })
+        }
+        AnyRegister::Wide(from) => {
+            let to = cpu.fetch_register_wide()?;
+            Ok(Instruction::DivRegRegWide { from, to })
+        }
+    }
+}
+
+pub(in crate::cpu) fn execute<B: Bus>(cpu: &mut Cpu<B>, from: Register, to: Register) -> OpResult {
+    let value = cpu.registers.get(to);
+    let value = value.checked_div(cpu.registers.get(from));
+    cpu.registers.set(to, value.ok_or(Error::DivideByZero)?);
+    Ok(())
+}
+
+pub(in crate::cpu) fn execute_wide<B: Bus>(
+    cpu: &mut Cpu<B>,
+    from: WideRegister,
+    to: WideRegister,
+) -> OpResult {
+    let value = cpu.registers.get_wide(to);
+    let value = value.checked_div(cpu.registers.get_wide(from));
+    cpu.registers
+        .set_wide(to, value.ok_or(Error::DivideByZero)?);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cpu::{
+        operation::tests::{
+            op_run_error, op_run_success, test_builder_size, test_invalid_register, test_run_no_mem,
+        },
+        tests::TestCpuState,
+    };
+
+    #[test]
+    fn success() {
+        let from = Register::C;
+        let to = Register::D;
+
+        let mut expected = TestCpuState::new();
+        expected.reg(to, 0xAB / 0x10);
+
+        let mut build = builder(from, to);
+        build.reg(from, 0x10).reg(to, 0xAB);
+        op_run_success(&expected, &mut build, run);
+    }
+
+    #[test]
+    fn wide_success() {
+        let from = WideRegister::CD;
+        let to = WideRegister::EF;
+
+        let mut expected = TestCpuState::new();
+        expected.reg_wide(to, 0xABCD / 0x0100);
+
+        let mut build = builder(from, to);
+        build.reg_wide(from, 0x0100).reg_wide(to, 0xABCD);
+        op_run_success(&expected, &mut build, run);
+    }
+
+    #[test]
+    fn divide_by_zero() {
+        let mut build = builder(Register::C, Register::D);
+        build.reg(Register::D, 0xAB);
+        let err = op_run_error(&mut build, run);
+        assert!(matches!(err, Error::DivideByZero));
+    }
+
+    #[test]
+    fn wide_divide_by_zero() {
+        let mut build = builder(WideRegister::CD, WideRegister::EF);
+        build.reg_wide(WideRegister::EF, 0xABCD);
+        let err = op_run_error(&mut build, run);
+        assert!(matches!(err, Error::DivideByZero));
+    }
+
+    test_builder_size!(
+        {
+            let mut build = builder(Register::A, Register::B);
+            build.reg(Register::A, 1);
+            build
+        },
+        SIZE
+    );
+
+    test_invalid_register!(&[0x00, Register::A.into()], 0x00, first);
+    test_invalid_register!(&[Register::A.into(), 0x00], 0x00, second);
+    test_invalid_register!(
+        &[Register::A.into(), WideRegister::CD.into()],
+        WideRegister::CD.into(),
+        second_wide
+    );
+    test_invalid_register!(
+        &[WideRegister::AB.into(), Register::C.into()],
+        Register::C.into(),
+        second_std
+    );
+
+    test_run_no_mem!();
+
+    fn builder(from: impl Into<AnyRegister>, to: impl Into<AnyRegister>) -> TestCpuState {
+        let opargs = [from.into().into(), to.into().into()];
+        TestCpuState::new_with_program(&opargs)
+    }
+}
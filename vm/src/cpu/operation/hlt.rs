@@ -1,10 +1,21 @@
-use crate::cpu::{Cpu, Error, OpResult};
+use crate::cpu::{Cpu, Error, Instruction, OpResult};
+use crate::memory::Bus;
+use h8bit_asm::opcode;
 
-pub const CODE: u8 = 0xff;
+pub const CODE: u8 = opcode::HLT;
 pub const NAME: &str = "HLT";
 pub const SIZE: u8 = 1;
+pub const CYCLES: u8 = 1;
 
-pub(in crate::cpu::operation) fn run(_cpu: &mut Cpu) -> OpResult {
+pub(in crate::cpu::operation) fn run<B: Bus>(cpu: &mut Cpu<B>) -> OpResult {
+    decode(cpu)?.execute(cpu)
+}
+
+pub(in crate::cpu) fn decode<B: Bus>(_cpu: &mut Cpu<B>) -> Result<Instruction, Error> {
+    Ok(Instruction::Hlt)
+}
+
+pub(in crate::cpu) fn execute<B: Bus>(_cpu: &mut Cpu<B>) -> OpResult {
     Err(Error::Halt)
 }
 
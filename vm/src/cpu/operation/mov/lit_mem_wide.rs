@@ -1,13 +1,24 @@
-use crate::cpu::{Cpu, Device, OpResult};
+use crate::cpu::{Cpu, Error, Instruction, OpResult};
+use crate::memory::Bus;
+use h8bit_asm::opcode;
 
-pub const CODE: u8 = 0x16;
+pub const CODE: u8 = opcode::MOV_LIT_MEM_WIDE;
 pub const NAME: &str = "MOV_LIT_MEM_WIDE";
 pub const SIZE: u8 = 5;
+pub const CYCLES: u8 = 5;
 
-pub(in crate::cpu::operation) fn run(cpu: &mut Cpu) -> OpResult {
-    let value = cpu.fetch_wide()?;
+pub(in crate::cpu::operation) fn run<B: Bus>(cpu: &mut Cpu<B>) -> OpResult {
+    decode(cpu)?.execute(cpu)
+}
+
+pub(in crate::cpu) fn decode<B: Bus>(cpu: &mut Cpu<B>) -> Result<Instruction, Error> {
+    let literal = cpu.fetch_wide()?;
     let addr = cpu.fetch_wide()?;
-    cpu.memory.set_wide(addr, value)?;
+    Ok(Instruction::MovLitMemWide { literal, addr })
+}
+
+pub(in crate::cpu) fn execute<B: Bus>(cpu: &mut Cpu<B>, literal: u16, addr: u16) -> OpResult {
+    cpu.memory.set_wide(addr, literal)?;
     Ok(())
 }
 
@@ -1,17 +1,34 @@
-use crate::cpu::{Cpu, OpResult};
+use crate::cpu::{AnyRegister, Cpu, Error, Instruction, OpResult, WideRegister};
+use crate::memory::Bus;
 
 use super::mov_mem_reg;
+use h8bit_asm::opcode;
 
-pub const CODE: u8 = 0x18;
+pub const CODE: u8 = opcode::MOV_LIT_OFF_REG;
 pub const NAME: &str = "MOV_LIT_OFF_REG";
 pub const SIZE: u8 = 5;
+pub const CYCLES: u8 = 6;
 
-pub(in crate::cpu::operation) fn run(cpu: &mut Cpu) -> OpResult {
-    let mut addr = cpu.fetch_wide()?;
+pub(in crate::cpu::operation) fn run<B: Bus>(cpu: &mut Cpu<B>) -> OpResult {
+    decode(cpu)?.execute(cpu)
+}
+
+pub(in crate::cpu) fn decode<B: Bus>(cpu: &mut Cpu<B>) -> Result<Instruction, Error> {
+    let addr = cpu.fetch_wide()?;
     let from = cpu.fetch_register_wide()?;
+    let to = cpu.fetch_any_register()?;
+    Ok(Instruction::MovLitOffReg { addr, from, to })
+}
+
+pub(in crate::cpu) fn execute<B: Bus>(
+    cpu: &mut Cpu<B>,
+    mut addr: u16,
+    from: WideRegister,
+    to: AnyRegister,
+) -> OpResult {
     let offset = cpu.registers.get_wide(from);
     addr += offset;
-    mov_mem_reg(cpu, addr)
+    mov_mem_reg(cpu, addr, to)
 }
 
 #[cfg(test)]
@@ -24,7 +41,7 @@ mod tests {
                 test_run_no_mem, TEST_OP_MEM_SIZE,
             },
             tests::TestCpuState,
-            Register, WideRegister,
+            Register,
         },
         util::high_and_low_value,
     };
@@ -1,12 +1,23 @@
-use crate::cpu::{Cpu, OpResult};
+use crate::cpu::{Cpu, Error, Instruction, OpResult, Register};
+use crate::memory::Bus;
+use h8bit_asm::opcode;
 
-pub const CODE: u8 = 0x10;
+pub const CODE: u8 = opcode::MOV_LIT_REG;
 pub const NAME: &str = "MOV_LIT_REG";
 pub const SIZE: u8 = 3;
+pub const CYCLES: u8 = 3;
 
-pub(in crate::cpu::operation) fn run(cpu: &mut Cpu) -> OpResult {
+pub(in crate::cpu::operation) fn run<B: Bus>(cpu: &mut Cpu<B>) -> OpResult {
+    decode(cpu)?.execute(cpu)
+}
+
+pub(in crate::cpu) fn decode<B: Bus>(cpu: &mut Cpu<B>) -> Result<Instruction, Error> {
     let literal = cpu.fetch()?;
     let reg = cpu.fetch_register()?;
+    Ok(Instruction::MovLitReg { literal, reg })
+}
+
+pub(in crate::cpu) fn execute<B: Bus>(cpu: &mut Cpu<B>, literal: u8, reg: Register) -> OpResult {
     cpu.registers.set(reg, literal);
     Ok(())
 }
@@ -19,7 +30,6 @@ mod tests {
             op_run_success, test_builder_size, test_invalid_register, test_run_no_mem,
         },
         tests::TestCpuState,
-        Register,
     };
 
     #[test]
@@ -1,12 +1,27 @@
-use crate::cpu::{Cpu, OpResult};
+use crate::cpu::{Cpu, Error, Instruction, OpResult, WideRegister};
+use crate::memory::Bus;
+use h8bit_asm::opcode;
 
-pub const CODE: u8 = 0x11;
+pub const CODE: u8 = opcode::MOV_LIT_REG_WIDE;
 pub const NAME: &str = "MOV_LIT_REG_WIDE";
 pub const SIZE: u8 = 4;
+pub const CYCLES: u8 = 4;
 
-pub(in crate::cpu::operation) fn run(cpu: &mut Cpu) -> OpResult {
+pub(in crate::cpu::operation) fn run<B: Bus>(cpu: &mut Cpu<B>) -> OpResult {
+    decode(cpu)?.execute(cpu)
+}
+
+pub(in crate::cpu) fn decode<B: Bus>(cpu: &mut Cpu<B>) -> Result<Instruction, Error> {
     let literal = cpu.fetch_wide()?;
     let reg = cpu.fetch_register_wide()?;
+    Ok(Instruction::MovLitRegWide { literal, reg })
+}
+
+pub(in crate::cpu) fn execute<B: Bus>(
+    cpu: &mut Cpu<B>,
+    literal: u16,
+    reg: WideRegister,
+) -> OpResult {
     cpu.registers.set_wide(reg, literal);
     Ok(())
 }
@@ -20,7 +35,6 @@ mod tests {
                 op_run_success, test_builder_size, test_invalid_register, test_run_no_mem,
             },
             tests::TestCpuState,
-            WideRegister,
         },
         util::high_and_low_value,
     };
@@ -1,13 +1,25 @@
 use super::mov_mem_reg;
-use crate::cpu::{Cpu, OpResult};
+use crate::cpu::{AnyRegister, Cpu, Error, Instruction, OpResult};
+use crate::memory::Bus;
+use h8bit_asm::opcode;
 
-pub const CODE: u8 = 0x14;
+pub const CODE: u8 = opcode::MOV_MEM_REG;
 pub const NAME: &str = "MOV_MEM_REG";
 pub const SIZE: u8 = 4;
+pub const CYCLES: u8 = 4;
 
-pub(in crate::cpu::operation) fn run(cpu: &mut Cpu) -> OpResult {
+pub(in crate::cpu::operation) fn run<B: Bus>(cpu: &mut Cpu<B>) -> OpResult {
+    decode(cpu)?.execute(cpu)
+}
+
+pub(in crate::cpu) fn decode<B: Bus>(cpu: &mut Cpu<B>) -> Result<Instruction, Error> {
     let addr = cpu.fetch_wide()?;
-    mov_mem_reg(cpu, addr)
+    let to = cpu.fetch_any_register()?;
+    Ok(Instruction::MovMemReg { addr, to })
+}
+
+pub(in crate::cpu) fn execute<B: Bus>(cpu: &mut Cpu<B>, addr: u16, to: AnyRegister) -> OpResult {
+    mov_mem_reg(cpu, addr, to)
 }
 
 #[cfg(test)]
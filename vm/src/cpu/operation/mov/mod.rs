@@ -1,6 +1,6 @@
 use crate::{
     cpu::{AnyRegister, Cpu, OpResult},
-    memory::Device,
+    memory::Bus,
 };
 
 pub mod lit_reg;
@@ -19,8 +19,8 @@ pub mod reg_ptr_reg;
 
 pub mod lit_off_reg;
 
-fn mov_mem_reg(cpu: &mut Cpu, addr: u16) -> OpResult {
-    match cpu.fetch_any_register()? {
+fn mov_mem_reg<B: Bus>(cpu: &mut Cpu<B>, addr: u16, to: AnyRegister) -> OpResult {
+    match to {
         AnyRegister::Std(reg) => {
             let value = cpu.memory.get(addr)?;
             cpu.registers.set(reg, value);
@@ -1,19 +1,29 @@
-use crate::cpu::{AnyRegister, Cpu, OpResult};
-use crate::memory::Device;
+use crate::cpu::{AnyRegister, Cpu, Error, Instruction, OpResult};
+use crate::memory::Bus;
+use h8bit_asm::opcode;
 
-pub const CODE: u8 = 0x13;
+pub const CODE: u8 = opcode::MOV_REG_MEM;
 pub const NAME: &str = "MOV_REG_MEM";
 pub const SIZE: u8 = 4;
+pub const CYCLES: u8 = 4;
 
-pub(in crate::cpu::operation) fn run(cpu: &mut Cpu) -> OpResult {
-    match cpu.fetch_any_register()? {
+pub(in crate::cpu::operation) fn run<B: Bus>(cpu: &mut Cpu<B>) -> OpResult {
+    decode(cpu)?.execute(cpu)
+}
+
+pub(in crate::cpu) fn decode<B: Bus>(cpu: &mut Cpu<B>) -> Result<Instruction, Error> {
+    let from = cpu.fetch_any_register()?;
+    let addr = cpu.fetch_wide()?;
+    Ok(Instruction::MovRegMem { from, addr })
+}
+
+pub(in crate::cpu) fn execute<B: Bus>(cpu: &mut Cpu<B>, from: AnyRegister, addr: u16) -> OpResult {
+    match from {
         AnyRegister::Std(reg) => {
-            let addr = cpu.fetch_wide()?;
             let value = cpu.registers.get(reg);
             cpu.memory.set(addr, value)?;
         }
         AnyRegister::Wide(reg) => {
-            let addr = cpu.fetch_wide()?;
             let value = cpu.registers.get_wide(reg);
             cpu.memory.set_wide(addr, value)?;
         }
@@ -1,14 +1,30 @@
 use super::mov_mem_reg;
-use crate::cpu::{Cpu, OpResult};
+use crate::cpu::{AnyRegister, Cpu, Error, Instruction, OpResult, WideRegister};
+use crate::memory::Bus;
+use h8bit_asm::opcode;
 
-pub const CODE: u8 = 0x17;
+pub const CODE: u8 = opcode::MOV_REG_PTR_REG;
 pub const NAME: &str = "MOV_REG_PTR_REG";
 pub const SIZE: u8 = 3;
+pub const CYCLES: u8 = 3;
 
-pub(in crate::cpu::operation) fn run(cpu: &mut Cpu) -> OpResult {
+pub(in crate::cpu::operation) fn run<B: Bus>(cpu: &mut Cpu<B>) -> OpResult {
+    decode(cpu)?.execute(cpu)
+}
+
+pub(in crate::cpu) fn decode<B: Bus>(cpu: &mut Cpu<B>) -> Result<Instruction, Error> {
     let from = cpu.fetch_register_wide()?;
+    let to = cpu.fetch_any_register()?;
+    Ok(Instruction::MovRegPtrReg { from, to })
+}
+
+pub(in crate::cpu) fn execute<B: Bus>(
+    cpu: &mut Cpu<B>,
+    from: WideRegister,
+    to: AnyRegister,
+) -> OpResult {
     let addr = cpu.registers.get_wide(from);
-    mov_mem_reg(cpu, addr)
+    mov_mem_reg(cpu, addr, to)
 }
 
 #[cfg(test)]
@@ -20,7 +36,7 @@ mod tests {
             test_run_no_mem, TEST_OP_MEM_SIZE,
         },
         tests::TestCpuState,
-        Register, WideRegister,
+        Register,
     };
 
     #[test]
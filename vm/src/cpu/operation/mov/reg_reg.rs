@@ -1,22 +1,42 @@
-use crate::cpu::{AnyRegister, Cpu, OpResult};
+use crate::cpu::{AnyRegister, Cpu, Error, Instruction, OpResult, Register, WideRegister};
+use crate::memory::Bus;
+use h8bit_asm::opcode;
 
-pub const CODE: u8 = 0x12;
+pub const CODE: u8 = opcode::MOV_REG_REG;
 pub const NAME: &str = "MOV_REG_REG";
 pub const SIZE: u8 = 3;
+pub const CYCLES: u8 = 3;
 
-pub(in crate::cpu::operation) fn run(cpu: &mut Cpu) -> OpResult {
+pub(in crate::cpu::operation) fn run<B: Bus>(cpu: &mut Cpu<B>) -> OpResult {
+    decode(cpu)?.execute(cpu)
+}
+
+pub(in crate::cpu) fn decode<B: Bus>(cpu: &mut Cpu<B>) -> Result<Instruction, Error> {
     match cpu.fetch_any_register()? {
         AnyRegister::Std(from) => {
             let to = cpu.fetch_register()?;
-            let value = cpu.registers.get(from);
-            cpu.registers.set(to, value);
+            Ok(Instruction::MovRegReg { from, to })
         }
         AnyRegister::Wide(from) => {
             let to = cpu.fetch_register_wide()?;
-            let value = cpu.registers.get_wide(from);
-            cpu.registers.set_wide(to, value);
+            Ok(Instruction::MovRegRegWide { from, to })
         }
     }
+}
+
+pub(in crate::cpu) fn execute<B: Bus>(cpu: &mut Cpu<B>, from: Register, to: Register) -> OpResult {
+    let value = cpu.registers.get(from);
+    cpu.registers.set(to, value);
+    Ok(())
+}
+
+pub(in crate::cpu) fn execute_wide<B: Bus>(
+    cpu: &mut Cpu<B>,
+    from: WideRegister,
+    to: WideRegister,
+) -> OpResult {
+    let value = cpu.registers.get_wide(from);
+    cpu.registers.set_wide(to, value);
     Ok(())
 }
 
@@ -27,7 +47,6 @@ mod tests {
             op_run_success, test_builder_size, test_invalid_register, test_run_no_mem,
         },
         tests::TestCpuState,
-        Register, WideRegister,
     };
 
     use super::*;
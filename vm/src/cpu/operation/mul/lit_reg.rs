@@ -0,0 +1,63 @@
+use crate::cpu::{Cpu, Error, Instruction, OpResult, WideRegister};
+use crate::memory::Bus;
+use h8bit_asm::opcode;
+
+pub const CODE: u8 = opcode::MUL_LIT_REG;
+pub const NAME: &str = "MUL_LIT_REG";
+pub const SIZE: u8 = 3;
+pub const CYCLES: u8 = 6;
+
+pub(in crate::cpu::operation) fn run<B: Bus>(cpu: &mut Cpu<B>) -> OpResult {
+    decode(cpu)?.execute(cpu)
+}
+
+pub(in crate::cpu) fn decode<B: Bus>(cpu: &mut Cpu<B>) -> Result<Instruction, Error> {
+    let literal = cpu.fetch()?;
+    let reg = cpu.fetch_register_wide()?;
+    Ok(Instruction::MulLitReg { literal, reg })
+}
+
+/// Multiply the low byte of `reg` by `literal`, into all of `reg`
+pub(in crate::cpu) fn execute<B: Bus>(
+    cpu: &mut Cpu<B>,
+    literal: u8,
+    reg: WideRegister,
+) -> OpResult {
+    let value = cpu.registers.get_wide(reg) & 0xff;
+    cpu.registers.set_wide(reg, value * literal as u16);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cpu::{
+        operation::tests::{
+            op_run_success, test_builder_size, test_invalid_register, test_run_no_mem,
+        },
+        tests::TestCpuState,
+    };
+
+    #[test]
+    fn success() {
+        let reg = WideRegister::CD;
+
+        let mut expected = TestCpuState::new();
+        expected.reg_wide(reg, 0xfe01);
+
+        op_run_success(&expected, &mut builder(0xff, reg, 0xabff), run);
+    }
+
+    test_builder_size!(builder(0xAB, WideRegister::AB, 0), SIZE);
+
+    test_invalid_register!(&[0xAB, 0x00], 0x00);
+
+    test_run_no_mem!();
+
+    fn builder(literal: u8, reg: WideRegister, value: u16) -> TestCpuState {
+        let opargs = [literal, reg.into()];
+        let mut build = TestCpuState::new_with_program(&opargs);
+        build.reg_wide(reg, value);
+        build
+    }
+}
@@ -0,0 +1,67 @@
+use crate::cpu::{Cpu, Error, Instruction, OpResult, WideRegister};
+use crate::memory::Bus;
+use h8bit_asm::opcode;
+
+pub const CODE: u8 = opcode::MUL_LIT_REG_WIDE;
+pub const NAME: &str = "MUL_LIT_REG_WIDE";
+pub const SIZE: u8 = 4;
+pub const CYCLES: u8 = 6;
+
+pub(in crate::cpu::operation) fn run<B: Bus>(cpu: &mut Cpu<B>) -> OpResult {
+    decode(cpu)?.execute(cpu)
+}
+
+pub(in crate::cpu) fn decode<B: Bus>(cpu: &mut Cpu<B>) -> Result<Instruction, Error> {
+    let literal = cpu.fetch_wide()?;
+    let reg = cpu.fetch_register_wide()?;
+    Ok(Instruction::MulLitRegWide { literal, reg })
+}
+
+/// Multiply `reg` by `literal`, keeping the low 16 bits
+pub(in crate::cpu) fn execute<B: Bus>(
+    cpu: &mut Cpu<B>,
+    literal: u16,
+    reg: WideRegister,
+) -> OpResult {
+    let value = cpu.registers.get_wide(reg).wrapping_mul(literal);
+    cpu.registers.set_wide(reg, value);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        cpu::{
+            operation::tests::{
+                op_run_success, test_builder_size, test_invalid_register, test_run_no_mem,
+            },
+            tests::TestCpuState,
+        },
+        util::high_and_low_value,
+    };
+
+    #[test]
+    fn success() {
+        let reg = WideRegister::CD;
+
+        let mut expected = TestCpuState::new();
+        expected.reg_wide(reg, 0x1234u16.wrapping_mul(0x0302));
+
+        op_run_success(&expected, &mut builder(0x0302, reg, 0x1234), run);
+    }
+
+    test_builder_size!(builder(0x0001, WideRegister::AB, 0), SIZE);
+
+    test_invalid_register!(&[0xAB, 0xCD, 0x00], 0x00);
+
+    test_run_no_mem!();
+
+    fn builder(literal: u16, reg: WideRegister, value: u16) -> TestCpuState {
+        let (high, low) = high_and_low_value(literal);
+        let opargs = [high, low, reg.into()];
+        let mut build = TestCpuState::new_with_program(&opargs);
+        build.reg_wide(reg, value);
+        build
+    }
+}
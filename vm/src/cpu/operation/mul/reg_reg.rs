@@ -0,0 +1,98 @@
+use crate::cpu::{AnyRegister, Cpu, Error, Instruction, OpResult, Register, WideRegister};
+use crate::memory::Bus;
+use h8bit_asm::opcode;
+
+pub const CODE: u8 = opcode::MUL_REG_REG;
+pub const NAME: &str = "MUL_REG_REG";
+pub const SIZE: u8 = 3;
+pub const CYCLES: u8 = 6;
+
+pub(in crate::cpu::operation) fn run<B: Bus>(cpu: &mut Cpu<B>) -> OpResult {
+    decode(cpu)?.execute(cpu)
+}
+
+pub(in crate::cpu) fn decode<B: Bus>(cpu: &mut Cpu<B>) -> Result<Instruction, Error> {
+    let from = cpu.fetch_any_register()?;
+    let to = cpu.fetch_register_wide()?;
+    match from {
+        AnyRegister::Std(from) => Ok(Instruction::MulRegReg { from, to }),
+        AnyRegister::Wide(from) => Ok(Instruction::MulRegRegWide { from, to }),
+    }
+}
+
+/// Multiply the low byte of `to` by `from`, into all of `to`
+pub(in crate::cpu) fn execute<B: Bus>(
+    cpu: &mut Cpu<B>,
+    from: Register,
+    to: WideRegister,
+) -> OpResult {
+    let value = cpu.registers.get_wide(to) & 0xff;
+    let from = cpu.registers.get(from) as u16;
+    cpu.registers.set_wide(to, value * from);
+    Ok(())
+}
+
+/// Multiply `to` by `from`, keeping the low 16 bits
+pub(in crate::cpu) fn execute_wide<B: Bus>(
+    cpu: &mut Cpu<B>,
+    from: WideRegister,
+    to: WideRegister,
+) -> OpResult {
+    let value = cpu.registers.get_wide(to);
+    let from = cpu.registers.get_wide(from);
+    cpu.registers.set_wide(to, value.wrapping_mul(from));
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cpu::{
+        operation::tests::{
+            op_run_success, test_builder_size, test_invalid_register, test_run_no_mem,
+        },
+        tests::TestCpuState,
+    };
+
+    #[test]
+    fn success() {
+        let from = Register::A;
+        let to = WideRegister::CD;
+
+        let mut expected = TestCpuState::new();
+        expected.reg(from, 0x10).reg_wide(to, 0x0AB0);
+
+        let mut build = builder(from, to);
+        build.reg(from, 0x10).reg_wide(to, 0xFFAB);
+        op_run_success(&expected, &mut build, run);
+    }
+
+    #[test]
+    fn wide_success() {
+        let from = WideRegister::AB;
+        let to = WideRegister::CD;
+
+        let mut expected = TestCpuState::new();
+        expected.reg_wide(to, 0x1234u16.wrapping_mul(0x0302));
+
+        let mut build = builder(from, to);
+        build.reg_wide(from, 0x0302).reg_wide(to, 0x1234);
+        op_run_success(&expected, &mut build, run);
+    }
+
+    test_builder_size!(builder(Register::A, WideRegister::CD), SIZE);
+
+    test_invalid_register!(&[0x00, WideRegister::CD.into()], 0x00, first);
+    test_invalid_register!(
+        &[Register::A.into(), Register::C.into()],
+        Register::C.into(),
+        second_std
+    );
+
+    test_run_no_mem!();
+
+    fn builder(from: impl Into<AnyRegister>, to: WideRegister) -> TestCpuState {
+        let opargs = [from.into().into(), to.into()];
+        TestCpuState::new_with_program(&opargs)
+    }
+}
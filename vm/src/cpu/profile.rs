@@ -0,0 +1,175 @@
+use crate::object::{Object, SectionKind};
+use std::collections::BTreeMap;
+use std::io;
+
+/// Name for instructions before the first function
+const UNKNOWN: &str = "[unknown]";
+
+/// Attributes executed instructions and cycles to functions
+///
+/// A function runs from its symbol's address up to the next function.
+/// There are no call instructions yet, so every stack is a single frame.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Profiler {
+    /// Function names by start address
+    functions: BTreeMap<u16, String>,
+    counts: BTreeMap<u16, Counts>,
+    unknown: Counts,
+}
+
+/// Totals for one function, from [`Profiler::profile`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FunctionProfile {
+    pub name: String,
+    pub instructions: u64,
+    pub cycles: u64,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+struct Counts {
+    instructions: u64,
+    cycles: u64,
+}
+
+impl Profiler {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Profile the symbols of an object's code sections
+    pub fn from_object(object: &Object) -> Self {
+        let mut profiler = Self::new();
+        for symbol in &object.symbols {
+            let section = symbol
+                .section
+                .and_then(|index| object.sections.get(index as usize));
+            if let Some(section) = section.filter(|section| section.kind == SectionKind::Code) {
+                profiler.add_function(
+                    symbol.name.clone(),
+                    section.addr.wrapping_add(symbol.offset),
+                );
+            }
+        }
+        profiler
+    }
+
+    /// Start a function at `addr`, replacing any already there
+    pub fn add_function(&mut self, name: impl Into<String>, addr: u16) {
+        self.functions.insert(addr, name.into());
+    }
+
+    pub(super) fn record(&mut self, pc: u16, cycles: u8) {
+        let counts = match self.functions.range(..=pc).next_back() {
+            Some((&addr, _)) => self.counts.entry(addr).or_default(),
+            None => &mut self.unknown,
+        };
+        counts.instructions += 1;
+        counts.cycles += cycles as u64;
+    }
+
+    /// Functions which ran, most cycles first
+    pub fn profile(&self) -> Vec<FunctionProfile> {
+        let mut profile: Vec<_> = self
+            .counts
+            .iter()
+            .map(|(addr, counts)| counts.profile(&self.functions[addr]))
+            .collect();
+        if self.unknown.instructions > 0 {
+            profile.push(self.unknown.profile(UNKNOWN));
+        }
+        profile.sort_by(|a, b| b.cycles.cmp(&a.cycles).then_with(|| a.name.cmp(&b.name)));
+        profile
+    }
+
+    /// Table of cycles, their share of the total and instructions
+    pub fn write_flat<W: io::Write + ?Sized>(&self, out: &mut W) -> io::Result<()> {
+        let profile = self.profile();
+        let total: u64 = profile.iter().map(|function| function.cycles).sum();
+        writeln!(
+            out,
+            "{:>10} {:>7} {:>12}  function",
+            "cycles", "%", "instructions"
+        )?;
+        for function in &profile {
+            let percent = function.cycles as f64 * 100.0 / total as f64;
+            writeln!(
+                out,
+                "{:>10} {:>6.2}% {:>12}  {}",
+                function.cycles, percent, function.instructions, function.name
+            )?;
+        }
+        Ok(())
+    }
+
+    /// One `stack cycles` line per function, for flamegraph tools
+    pub fn write_folded<W: io::Write + ?Sized>(&self, out: &mut W) -> io::Result<()> {
+        for function in self.profile() {
+            writeln!(out, "{} {}", function.name, function.cycles)?;
+        }
+        Ok(())
+    }
+}
+
+impl Counts {
+    fn profile(&self, name: &str) -> FunctionProfile {
+        FunctionProfile {
+            name: name.to_string(),
+            instructions: self.instructions,
+            cycles: self.cycles,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::object::tests::test_object;
+
+    fn test_profiler() -> Profiler {
+        let mut profiler = Profiler::new();
+        profiler.add_function("main", 0x10);
+        profiler.add_function("helper", 0x20);
+        profiler.record(0x00, 2);
+        profiler.record(0x10, 3);
+        profiler.record(0x14, 3);
+        profiler.record(0x20, 10);
+        profiler
+    }
+
+    #[test]
+    fn profile_attributes_to_functions() {
+        let profile = test_profiler().profile();
+        let names: Vec<_> = profile.iter().map(|f| f.name.as_str()).collect();
+        assert_eq!(vec!["helper", "main", UNKNOWN], names);
+        assert_eq!(2, profile[1].instructions);
+        assert_eq!(6, profile[1].cycles);
+    }
+
+    #[test]
+    fn profile_flat() {
+        let mut out = Vec::new();
+        test_profiler()
+            .write_flat(&mut out)
+            .expect("writing to a vector");
+        let out = String::from_utf8(out).expect("utf8");
+        let expected = "        10  55.56%            1  helper";
+        assert_eq!(Some(expected), out.lines().nth(1));
+    }
+
+    #[test]
+    fn profile_folded() {
+        let mut out = Vec::new();
+        test_profiler()
+            .write_folded(&mut out)
+            .expect("writing to a vector");
+        let expected = "helper 10\nmain 6\n[unknown] 2\n";
+        assert_eq!(expected, String::from_utf8(out).expect("utf8"));
+    }
+
+    #[test]
+    fn profiler_from_object() {
+        let profiler = Profiler::from_object(&test_object());
+        let expected = BTreeMap::from([(0x10, "start".to_string())]);
+        assert_eq!(expected, profiler.functions);
+    }
+}
@@ -0,0 +1,468 @@
+use super::{AnyRegister, Instruction, Register, WideRegister};
+use crate::util::high_and_low_value;
+use h8bit_asm::{self as asm, Operand, Part, Value};
+use std::collections::HashMap;
+
+/// Wide operand given as a value or a label
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Target {
+    Addr(u16),
+    Label(String),
+}
+
+#[derive(Debug, thiserror::Error, PartialEq, Eq)]
+pub enum ProgramError {
+    #[error("undefined label {0}")]
+    UndefinedLabel(String),
+    #[error("duplicate label {0}")]
+    DuplicateLabel(String),
+    #[error("program extends past the end of memory")]
+    TooLong,
+}
+
+/// Assembles guest code with typed operands
+///
+/// Each method takes the operand kinds its operation decodes, so passing a
+/// wide register where a register is expected doesn't compile. Wide operands
+/// accept a [`Target`], labels are resolved by [`ProgramBuilder::build`].
+#[derive(Debug, Clone, Default)]
+pub struct ProgramBuilder {
+    origin: u16,
+    bytes: Vec<u8>,
+    labels: HashMap<String, usize>,
+    fixups: Vec<(usize, String)>,
+    duplicate: Option<String>,
+}
+
+impl ProgramBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Builder for a program loaded at `origin`
+    pub fn at(origin: u16) -> Self {
+        Self {
+            origin,
+            ..Self::default()
+        }
+    }
+
+    /// Name the address of the next byte
+    pub fn label(mut self, name: impl Into<String>) -> Self {
+        let name = name.into();
+        if self.labels.contains_key(&name) {
+            self.duplicate.get_or_insert(name);
+        } else {
+            self.labels.insert(name, self.bytes.len());
+        }
+        self
+    }
+
+    /// Address of a label defined so far
+    pub fn address_of(&self, label: &str) -> Option<u16> {
+        let offset = *self.labels.get(label)?;
+        Some(self.origin.wrapping_add(offset as u16))
+    }
+
+    /// Raw bytes, for data or operations without a method
+    pub fn data(mut self, bytes: &[u8]) -> Self {
+        self.bytes.extend_from_slice(bytes);
+        self
+    }
+
+    pub fn instruction(self, instruction: Instruction) -> Self {
+        self.data(&instruction.encode())
+    }
+
+    pub fn nop(self) -> Self {
+        self.encode("nop", &[])
+    }
+
+    pub fn hlt(self) -> Self {
+        self.encode("hlt", &[])
+    }
+
+    pub fn mov_lit_reg(self, literal: u8, reg: Register) -> Self {
+        self.encode("mov", &[lit(literal), Operand::Reg(asm_reg(reg))])
+    }
+
+    pub fn mov_lit_reg_wide(self, literal: impl Into<Target>, reg: WideRegister) -> Self {
+        self.encode("mov", &[lit_wide(literal), Operand::Reg(asm_reg(reg))])
+    }
+
+    pub fn mov_reg_reg(self, from: Register, to: Register) -> Self {
+        self.encode(
+            "mov",
+            &[Operand::Reg(asm_reg(from)), Operand::Reg(asm_reg(to))],
+        )
+    }
+
+    pub fn mov_reg_reg_wide(self, from: WideRegister, to: WideRegister) -> Self {
+        self.encode(
+            "mov",
+            &[Operand::Reg(asm_reg(from)), Operand::Reg(asm_reg(to))],
+        )
+    }
+
+    pub fn mov_reg_mem(self, from: impl Into<AnyRegister>, addr: impl Into<Target>) -> Self {
+        self.encode("mov", &[Operand::Reg(asm_reg(from)), mem(addr)])
+    }
+
+    pub fn mov_mem_reg(self, addr: impl Into<Target>, to: impl Into<AnyRegister>) -> Self {
+        self.encode("mov", &[mem(addr), Operand::Reg(asm_reg(to))])
+    }
+
+    pub fn mov_lit_mem(self, literal: u8, addr: impl Into<Target>) -> Self {
+        self.encode("mov", &[lit(literal), mem(addr)])
+    }
+
+    pub fn mov_lit_mem_wide(self, literal: impl Into<Target>, addr: impl Into<Target>) -> Self {
+        self.encode("mov", &[lit_wide(literal), mem(addr)])
+    }
+
+    pub fn mov_reg_ptr_reg(self, from: WideRegister, to: impl Into<AnyRegister>) -> Self {
+        self.encode(
+            "mov",
+            &[Operand::Ptr(asm_reg(from)), Operand::Reg(asm_reg(to))],
+        )
+    }
+
+    pub fn mov_lit_off_reg(
+        self,
+        addr: impl Into<Target>,
+        from: WideRegister,
+        to: impl Into<AnyRegister>,
+    ) -> Self {
+        let offset = Operand::Offset(value(addr), asm_reg(from));
+        self.encode("mov", &[offset, Operand::Reg(asm_reg(to))])
+    }
+
+    pub fn cmp_lit_reg(self, literal: u8, reg: Register) -> Self {
+        self.encode("cmp", &[lit(literal), Operand::Reg(asm_reg(reg))])
+    }
+
+    pub fn cmp_lit_reg_wide(self, literal: impl Into<Target>, reg: WideRegister) -> Self {
+        self.encode("cmp", &[lit_wide(literal), Operand::Reg(asm_reg(reg))])
+    }
+
+    pub fn cmp_reg_reg(self, from: Register, to: Register) -> Self {
+        self.encode(
+            "cmp",
+            &[Operand::Reg(asm_reg(from)), Operand::Reg(asm_reg(to))],
+        )
+    }
+
+    pub fn cmp_reg_reg_wide(self, from: WideRegister, to: WideRegister) -> Self {
+        self.encode(
+            "cmp",
+            &[Operand::Reg(asm_reg(from)), Operand::Reg(asm_reg(to))],
+        )
+    }
+
+    pub fn cmp_mem_reg(self, addr: impl Into<Target>, reg: impl Into<AnyRegister>) -> Self {
+        self.encode("cmp", &[mem(addr), Operand::Reg(asm_reg(reg))])
+    }
+
+    pub fn mul_lit_reg(self, literal: u8, reg: WideRegister) -> Self {
+        self.encode("mul", &[lit(literal), Operand::Reg(asm_reg(reg))])
+    }
+
+    pub fn mul_lit_reg_wide(self, literal: impl Into<Target>, reg: WideRegister) -> Self {
+        self.encode("mul", &[lit_wide(literal), Operand::Reg(asm_reg(reg))])
+    }
+
+    pub fn mul_reg_reg(self, from: Register, to: WideRegister) -> Self {
+        self.encode(
+            "mul",
+            &[Operand::Reg(asm_reg(from)), Operand::Reg(asm_reg(to))],
+        )
+    }
+
+    pub fn mul_reg_reg_wide(self, from: WideRegister, to: WideRegister) -> Self {
+        self.encode(
+            "mul",
+            &[Operand::Reg(asm_reg(from)), Operand::Reg(asm_reg(to))],
+        )
+    }
+
+    pub fn div_lit_reg(self, literal: u8, reg: Register) -> Self {
+        self.encode("div", &[lit(literal), Operand::Reg(asm_reg(reg))])
+    }
+
+    pub fn div_lit_reg_wide(self, literal: impl Into<Target>, reg: WideRegister) -> Self {
+        self.encode("div", &[lit_wide(literal), Operand::Reg(asm_reg(reg))])
+    }
+
+    pub fn div_reg_reg(self, from: Register, to: Register) -> Self {
+        self.encode(
+            "div",
+            &[Operand::Reg(asm_reg(from)), Operand::Reg(asm_reg(to))],
+        )
+    }
+
+    pub fn div_reg_reg_wide(self, from: WideRegister, to: WideRegister) -> Self {
+        self.encode(
+            "div",
+            &[Operand::Reg(asm_reg(from)), Operand::Reg(asm_reg(to))],
+        )
+    }
+
+    pub fn mod_lit_reg(self, literal: u8, reg: Register) -> Self {
+        self.encode("mod", &[lit(literal), Operand::Reg(asm_reg(reg))])
+    }
+
+    pub fn mod_lit_reg_wide(self, literal: impl Into<Target>, reg: WideRegister) -> Self {
+        self.encode("mod", &[lit_wide(literal), Operand::Reg(asm_reg(reg))])
+    }
+
+    pub fn mod_reg_reg(self, from: Register, to: Register) -> Self {
+        self.encode(
+            "mod",
+            &[Operand::Reg(asm_reg(from)), Operand::Reg(asm_reg(to))],
+        )
+    }
+
+    pub fn mod_reg_reg_wide(self, from: WideRegister, to: WideRegister) -> Self {
+        self.encode(
+            "mod",
+            &[Operand::Reg(asm_reg(from)), Operand::Reg(asm_reg(to))],
+        )
+    }
+
+    /// Resolve labels and return the machine code
+    pub fn build(mut self) -> Result<Vec<u8>, ProgramError> {
+        if let Some(name) = self.duplicate {
+            return Err(ProgramError::DuplicateLabel(name));
+        }
+        if self.origin as usize + self.bytes.len() > u16::MAX as usize + 1 {
+            return Err(ProgramError::TooLong);
+        }
+        for (offset, label) in &self.fixups {
+            let addr = self
+                .address_of(label)
+                .ok_or_else(|| ProgramError::UndefinedLabel(label.clone()))?;
+            let (high, low) = high_and_low_value(addr);
+            self.bytes[*offset] = high;
+            self.bytes[offset + 1] = low;
+        }
+        Ok(self.bytes)
+    }
+
+    /// Append the encoding, recording label parts for [`ProgramBuilder::build`]
+    fn encode(mut self, mnemonic: &str, operands: &[Operand<String>]) -> Self {
+        let parts = asm::encode(mnemonic, operands).expect("typed operands encode");
+        for part in parts {
+            match part {
+                Part::Byte(byte) => self.bytes.push(byte),
+                Part::Label(label) => {
+                    self.fixups.push((self.bytes.len(), label));
+                    self.bytes.extend([0, 0]);
+                }
+            }
+        }
+        self
+    }
+}
+
+fn asm_reg(reg: impl Into<AnyRegister>) -> asm::Reg {
+    let reg = reg.into();
+    asm::Reg {
+        code: reg.into(),
+        wide: matches!(reg, AnyRegister::Wide(_)),
+    }
+}
+
+fn lit(literal: u8) -> Operand<String> {
+    Operand::Value(Value::Lit {
+        value: literal.into(),
+        wide: false,
+    })
+}
+
+fn lit_wide(target: impl Into<Target>) -> Operand<String> {
+    Operand::Value(value(target))
+}
+
+fn mem(target: impl Into<Target>) -> Operand<String> {
+    Operand::Mem(value(target))
+}
+
+fn value(target: impl Into<Target>) -> Value<String> {
+    match target.into() {
+        Target::Addr(value) => Value::Lit { value, wide: true },
+        Target::Label(label) => Value::Label(label),
+    }
+}
+
+impl From<u16> for Target {
+    fn from(addr: u16) -> Self {
+        Self::Addr(addr)
+    }
+}
+
+impl From<&str> for Target {
+    fn from(label: &str) -> Self {
+        Self::Label(label.to_string())
+    }
+}
+
+impl From<String> for Target {
+    fn from(label: String) -> Self {
+        Self::Label(label)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cpu::{tests::create_cpu_with_memory, Error};
+    use crate::memory::{Device, TestDevice};
+
+    #[test]
+    fn matches_instruction_encoding() {
+        let built = ProgramBuilder::new()
+            .mov_lit_off_reg(0x1234, WideRegister::EF, Register::A)
+            .mov_lit_mem_wide(0xabcd, 0x80)
+            .build()
+            .expect("no labels");
+        let expected = [
+            Instruction::MovLitOffReg {
+                addr: 0x1234,
+                from: WideRegister::EF,
+                to: Register::A.into(),
+            }
+            .encode(),
+            Instruction::MovLitMemWide {
+                literal: 0xabcd,
+                addr: 0x80,
+            }
+            .encode(),
+        ]
+        .concat();
+        assert_eq!(expected, built);
+    }
+
+    #[test]
+    fn labels_resolved_from_origin() {
+        let builder = ProgramBuilder::at(0x40)
+            .mov_lit_reg_wide("value", WideRegister::CD)
+            .label("loop")
+            .mov_mem_reg("value", Register::A)
+            .hlt()
+            .label("value")
+            .data(&[0xab]);
+        assert_eq!(Some(0x44), builder.address_of("loop"));
+        let program = builder.build().expect("labels defined");
+        assert_eq!([0x00, 0x49], program[1..3]);
+        assert_eq!([0x00, 0x49], program[5..7]);
+    }
+
+    #[test]
+    fn runs_on_cpu() {
+        let program = ProgramBuilder::new()
+            .mov_lit_reg_wide("value", WideRegister::CD)
+            .mov_reg_ptr_reg(WideRegister::CD, Register::A)
+            .mov_lit_mem(0xcd, "value")
+            .hlt()
+            .label("value")
+            .data(&[0xab])
+            .build()
+            .expect("labels defined");
+        let mut mem = TestDevice::new(0x100);
+        mem.write_slice(&program);
+        let mut cpu = create_cpu_with_memory(mem);
+        let err = cpu.run().expect_err("halts");
+        assert!(matches!(err, Error::Halt));
+        assert_eq!(0xab, cpu.registers().get(Register::A));
+        let value = program.len() as u16 - 1;
+        assert_eq!(0xcd, cpu.memory().get(value).expect("mapped"));
+    }
+
+    #[test]
+    fn arithmetic_runs_on_cpu() {
+        let program = ProgramBuilder::new()
+            .mov_lit_reg(0x10, Register::B)
+            .mul_lit_reg(0x12, WideRegister::AB)
+            .mov_reg_reg_wide(WideRegister::AB, WideRegister::CD)
+            .div_lit_reg_wide(0x0007, WideRegister::AB)
+            .mod_lit_reg_wide(0x0007, WideRegister::CD)
+            .hlt()
+            .build()
+            .expect("no labels");
+        let mut mem = TestDevice::new(0x100);
+        mem.write_slice(&program);
+        let mut cpu = create_cpu_with_memory(mem);
+        let err = cpu.run().expect_err("halts");
+        assert!(matches!(err, Error::Halt));
+        assert_eq!(0x120 / 7, cpu.registers().get_wide(WideRegister::AB));
+        assert_eq!(0x120 % 7, cpu.registers().get_wide(WideRegister::CD));
+    }
+
+    #[test]
+    fn label_errors() {
+        let err = ProgramBuilder::new().mov_lit_mem(0, "missing").build();
+        assert_eq!(
+            Err(ProgramError::UndefinedLabel("missing".to_string())),
+            err
+        );
+
+        let err = ProgramBuilder::new().label("a").nop().label("a").build();
+        assert_eq!(Err(ProgramError::DuplicateLabel("a".to_string())), err);
+    }
+
+    #[test]
+    fn too_long() {
+        let err = ProgramBuilder::at(0xfffe).nop().nop().nop().build();
+        assert_eq!(Err(ProgramError::TooLong), err);
+    }
+
+    #[test]
+    fn matches_h8asm() {
+        let program = ProgramBuilder::new()
+            .mov_lit_reg_wide("value", WideRegister::CD)
+            .mov_lit_mem(0xab, 0x01f0)
+            .mov_lit_mem_wide(0xabcd, "value")
+            .mov_reg_reg_wide(WideRegister::CD, WideRegister::AB)
+            .mov_reg_mem(Register::A, 0x01f0)
+            .mov_mem_reg(0x01f0, WideRegister::GH)
+            .mov_reg_ptr_reg(WideRegister::CD, Register::A)
+            .mov_lit_off_reg(0x10, WideRegister::EF, Register::B)
+            .mul_lit_reg(0x03, WideRegister::AB)
+            .mul_reg_reg(Register::C, WideRegister::EF)
+            .div_lit_reg_wide(0x0010, WideRegister::GH)
+            .mod_reg_reg(Register::A, Register::B)
+            .cmp_lit_reg(0x10, Register::A)
+            .cmp_lit_reg_wide(0x1000, WideRegister::AB)
+            .cmp_reg_reg_wide(WideRegister::AB, WideRegister::CD)
+            .cmp_mem_reg("value", Register::B)
+            .nop()
+            .hlt()
+            .label("value")
+            .data(&[0x12, 0x34])
+            .build()
+            .expect("labels defined");
+        let assembled = crate::h8asm! {
+            mov value, cd;
+            mov 0xab, [0x01f0];
+            mov 0xabcd, [value];
+            mov cd, ab;
+            mov a, [0x01f0];
+            mov [0x01f0], gh;
+            mov [cd], a;
+            mov [0x10 + ef], b;
+            mul 0x03, ab;
+            mul c, ef;
+            div 0x0010, gh;
+            mod a, b;
+            cmp 0x10, a;
+            cmp 0x1000, ab;
+            cmp ab, cd;
+            cmp [value], b;
+            nop;
+            hlt;
+        value:
+            dw 0x1234
+        };
+        assert_eq!(program, assembled);
+    }
+}
@@ -1,4 +1,5 @@
 use crate::util::{high_and_low_value, wide_value};
+use h8bit_asm::register as code;
 use std::fmt::{self, Write};
 use strum::IntoEnumIterator;
 use strum_macros::{EnumIter, FromRepr, IntoStaticStr};
@@ -10,35 +11,46 @@ pub struct InvalidRegister(u8);
 #[derive(Debug, Clone, Copy, PartialEq, FromRepr, IntoStaticStr, PartialOrd, Ord, Eq, EnumIter)]
 #[repr(u8)]
 pub enum Register {
-    A = 1,
-    B,
-    C,
-    D,
-    E,
-    F,
-    G,
-    H,
-    MB,
+    A = code::A,
+    B = code::B,
+    C = code::C,
+    D = code::D,
+    E = code::E,
+    F = code::F,
+    G = code::G,
+    H = code::H,
+    MB = code::MB,
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, FromRepr, IntoStaticStr)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, FromRepr, IntoStaticStr)]
 #[repr(u8)]
 pub enum WideRegister {
-    AB = 0x12,
-    CD = 0x34,
-    EF = 0x56,
-    GH = 0x78,
-    PC = 0xF0,
-    SP = 0xF1,
+    AB = code::AB,
+    CD = code::CD,
+    EF = code::EF,
+    GH = code::GH,
+    PC = code::PC,
+    SP = code::SP,
 }
 
-#[derive(Debug, Clone, Copy, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum AnyRegister {
     Std(Register),
     Wide(WideRegister),
 }
 
-#[derive(Debug, Default, PartialEq)]
+/// Condition flags, set by comparisons
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Flags {
+    /// The operands were equal
+    pub zero: bool,
+    /// Subtracting the source from the destination borrowed
+    pub carry: bool,
+    /// The top bit of the difference was set
+    pub negative: bool,
+}
+
+#[derive(Debug, Default, Clone, PartialEq)]
 pub struct RegisterState {
     a: u8,
     b: u8,
@@ -51,6 +63,7 @@ pub struct RegisterState {
     mb: u8,
     pc: u16,
     sp: u16,
+    flags: Flags,
 }
 
 impl RegisterState {
@@ -113,6 +126,58 @@ impl RegisterState {
     }
 }
 
+impl RegisterState {
+    pub fn flags(&self) -> Flags {
+        self.flags
+    }
+
+    pub fn set_flags(&mut self, flags: Flags) {
+        self.flags = flags;
+    }
+}
+
+impl Flags {
+    const ZERO: u8 = 0b001;
+    const CARRY: u8 = 0b010;
+    const NEGATIVE: u8 = 0b100;
+
+    /// Flags for `to - from` on byte operands
+    pub fn compare(to: u8, from: u8) -> Self {
+        let (diff, carry) = to.overflowing_sub(from);
+        Self {
+            zero: diff == 0,
+            carry,
+            negative: diff & 0x80 != 0,
+        }
+    }
+
+    /// Flags for `to - from` on wide operands
+    pub fn compare_wide(to: u16, from: u16) -> Self {
+        let (diff, carry) = to.overflowing_sub(from);
+        Self {
+            zero: diff == 0,
+            carry,
+            negative: diff & 0x8000 != 0,
+        }
+    }
+
+    /// Zero in bit 0, carry in bit 1 and negative in bit 2
+    pub fn bits(&self) -> u8 {
+        let bit = |set, bit| if set { bit } else { 0 };
+        bit(self.zero, Self::ZERO)
+            | bit(self.carry, Self::CARRY)
+            | bit(self.negative, Self::NEGATIVE)
+    }
+
+    pub fn from_bits(bits: u8) -> Self {
+        Self {
+            zero: bits & Self::ZERO != 0,
+            carry: bits & Self::CARRY != 0,
+            negative: bits & Self::NEGATIVE != 0,
+        }
+    }
+}
+
 impl Register {
     pub fn as_str(&self) -> &'static str {
         self.into()
@@ -194,6 +259,15 @@ impl From<WideRegister> for u8 {
     }
 }
 
+impl From<AnyRegister> for u8 {
+    fn from(reg: AnyRegister) -> Self {
+        match reg {
+            AnyRegister::Std(reg) => reg.into(),
+            AnyRegister::Wide(reg) => reg.into(),
+        }
+    }
+}
+
 impl fmt::Display for Register {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(f, "{}", self.as_str())
@@ -206,6 +280,20 @@ impl fmt::Display for WideRegister {
     }
 }
 
+/// Set flags as letters, `-` for clear ones: `ZCN`
+impl fmt::Display for Flags {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let flag = |set, name| if set { name } else { '-' };
+        write!(
+            f,
+            "{}{}{}",
+            flag(self.zero, 'Z'),
+            flag(self.carry, 'C'),
+            flag(self.negative, 'N')
+        )
+    }
+}
+
 impl fmt::Display for RegisterState {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         let mut output = String::with_capacity(256);
@@ -215,6 +303,7 @@ impl fmt::Display for RegisterState {
         let wide_fmt = |wide_reg| format!("  {}:   {:#06x}", wide_reg, self.get_wide(wide_reg));
         output += &wide_fmt(WideRegister::PC);
         output += &wide_fmt(WideRegister::SP);
+        write!(&mut output, "\n  FLAGS: {}", self.flags).expect("format flags");
         output.fmt(f)
     }
 }
@@ -241,6 +330,7 @@ pub mod tests {
             mb: 0,
             pc: 0,
             sp: 0,
+            flags: Flags::default(),
         };
         assert_eq!(expected, reg_state);
     }
@@ -299,6 +389,26 @@ pub mod tests {
         assert_eq!(value, reg_state.get_wide(wide));
     }
 
+    #[test]
+    fn flags_compare() {
+        let equal = Flags::compare(0x10, 0x10);
+        assert!(equal.zero && !equal.carry && !equal.negative);
+        let below = Flags::compare(0x10, 0x20);
+        assert!(!below.zero && below.carry && below.negative);
+        let above = Flags::compare(0x90, 0x01);
+        assert!(!above.zero && !above.carry && above.negative);
+        let wide = Flags::compare_wide(0x0100, 0x00ff);
+        assert!(!wide.zero && !wide.carry && !wide.negative);
+    }
+
+    #[test]
+    fn flags_bits_round_trip() {
+        for bits in 0..8 {
+            assert_eq!(bits, Flags::from_bits(bits).bits());
+        }
+        assert_eq!("Z-N", Flags::from_bits(0b101).to_string());
+    }
+
     #[test]
     fn any_register_from_u8() {
         let addr_reg = addr_name_reg_map();
@@ -0,0 +1,341 @@
+use super::{Cpu, RegisterState};
+use crate::memory::{Access, AccessKind, Bus, DeviceError};
+use std::collections::VecDeque;
+use std::mem;
+
+#[derive(Debug, thiserror::Error)]
+pub enum RewindError {
+    #[error("no history to rewind")]
+    NoHistory,
+    #[error("instruction {0} is not in the rewind history")]
+    OutOfRange(u64),
+    #[error("restoring memory: {0}")]
+    Device(#[from] DeviceError),
+}
+
+/// State needed to undo one instruction
+#[derive(Debug)]
+struct UndoEntry {
+    registers: RegisterState,
+    cycles: u64,
+    /// Instructions completed before, failed instructions are not counted
+    instructions: u64,
+    /// Bytes written as `(address, previous value)`
+    writes: Vec<(u16, u8)>,
+}
+
+/// Bounded history of executed instructions
+#[derive(Debug, Default)]
+pub(super) struct UndoLog {
+    entries: VecDeque<UndoEntry>,
+    /// Memory limit in bytes, `None` when rewinding is disabled
+    limit: Option<usize>,
+    used: usize,
+}
+
+impl UndoEntry {
+    fn size(&self) -> usize {
+        mem::size_of::<Self>() + self.writes.len() * mem::size_of::<(u16, u8)>()
+    }
+}
+
+impl UndoLog {
+    pub fn enable(&mut self, limit: usize) {
+        self.limit = Some(limit);
+        self.shrink();
+    }
+
+    pub fn disable(&mut self) {
+        self.limit = None;
+        self.clear();
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.limit.is_some()
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+        self.used = 0;
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn record(
+        &mut self,
+        registers: RegisterState,
+        cycles: u64,
+        instructions: u64,
+        accesses: &[Access],
+    ) {
+        if !self.is_enabled() {
+            return;
+        }
+        let writes = accesses
+            .iter()
+            .filter(|access| access.kind == AccessKind::Write)
+            .map(|access| (access.addr, access.previous))
+            .collect();
+        let entry = UndoEntry {
+            registers,
+            cycles,
+            instructions,
+            writes,
+        };
+        self.used += entry.size();
+        self.entries.push_back(entry);
+        self.shrink();
+    }
+
+    /// Instruction count at the oldest entry
+    fn oldest(&self) -> Option<u64> {
+        self.entries.front().map(|entry| entry.instructions)
+    }
+
+    fn pop(&mut self) -> Option<UndoEntry> {
+        let entry = self.entries.pop_back()?;
+        self.used -= entry.size();
+        Some(entry)
+    }
+
+    fn shrink(&mut self) {
+        let limit = self.limit.unwrap_or(0);
+        while self.used > limit {
+            match self.entries.pop_front() {
+                Some(entry) => self.used -= entry.size(),
+                None => break,
+            }
+        }
+    }
+}
+
+impl<B: Bus> Cpu<B> {
+    /// Keep an undo log of each instruction, using at most `limit` bytes
+    ///
+    /// The oldest history is dropped once the limit is reached.
+    pub fn enable_rewind(&mut self, limit: usize) {
+        self.rewind.enable(limit);
+        self.update_observing();
+    }
+
+    pub fn disable_rewind(&mut self) {
+        self.rewind.disable();
+        self.update_observing();
+    }
+
+    /// Number of instructions which can currently be undone
+    pub fn rewind_history(&self) -> usize {
+        self.rewind.len()
+    }
+
+    /// Undo the last executed instruction
+    ///
+    /// Memory is restored without being recorded and IO devices are left
+    /// alone, see [`Bus::undo_write`]. An instruction which failed part way
+    /// is undone too.
+    pub fn step_back(&mut self) -> Result<(), RewindError> {
+        let entry = self.rewind.pop().ok_or(RewindError::NoHistory)?;
+        // undo in reverse so bytes written twice get their oldest value
+        let mut undone = Vec::with_capacity(entry.writes.len());
+        for &(addr, previous) in entry.writes.iter().rev() {
+            self.memory.undo_write(addr, previous)?;
+            undone.push(Access {
+                kind: AccessKind::Write,
+                addr,
+                value: previous,
+                previous,
+            });
+        }
+        self.invalidate_cache(&undone);
+        self.registers = entry.registers;
+        self.cycles = entry.cycles;
+        self.instructions = entry.instructions;
+        Ok(())
+    }
+
+    /// Undo instructions until [`Cpu::instructions`] equals `instruction_count`
+    pub fn rewind_to(&mut self, instruction_count: u64) -> Result<(), RewindError> {
+        let oldest = self.rewind.oldest().unwrap_or(self.instructions);
+        if instruction_count > self.instructions || instruction_count < oldest {
+            return Err(RewindError::OutOfRange(instruction_count));
+        }
+        while self.instructions > instruction_count {
+            self.step_back()?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cpu::{tests::create_cpu_with_memory, ProgramBuilder, Register, WideRegister};
+    use crate::memory::{Device, DeviceKind, TestDevice};
+
+    const ADDR: u16 = 0xf0;
+
+    fn test_cpu() -> Cpu {
+        let program = ProgramBuilder::new()
+            .mov_lit_reg(0xab, Register::C)
+            .mov_lit_mem(0xcd, ADDR)
+            .mov_lit_mem_wide(0x1234, ADDR)
+            .hlt()
+            .build()
+            .expect("no labels");
+        let mut mem = TestDevice::new(0x100);
+        mem.write_slice(&program);
+        let mut cpu = create_cpu_with_memory(mem);
+        cpu.enable_rewind(1024);
+        cpu
+    }
+
+    #[test]
+    fn step_back_registers() {
+        let mut cpu = test_cpu();
+        cpu.step().expect("valid instruction");
+        assert_eq!(0xab, cpu.registers.get(Register::C));
+        cpu.step_back().expect("history");
+        assert_eq!(0, cpu.registers.get(Register::C));
+        assert_eq!(0, cpu.registers.get_wide(WideRegister::PC));
+        assert_eq!(0, cpu.cycles());
+        assert_eq!(0, cpu.instructions());
+    }
+
+    #[test]
+    fn step_back_memory() {
+        let mut cpu = test_cpu();
+        cpu.step().expect("valid instruction");
+        cpu.step().expect("valid instruction");
+        cpu.step().expect("valid instruction");
+        assert_eq!(0x1234, cpu.memory.get_wide(ADDR).expect("valid"));
+        cpu.step_back().expect("history");
+        assert_eq!(0xcd, cpu.memory.get(ADDR).expect("valid"));
+        cpu.step_back().expect("history");
+        assert_eq!(0, cpu.memory.get(ADDR).expect("valid"));
+    }
+
+    #[test]
+    fn step_back_after_halt() {
+        let mut cpu = test_cpu();
+        let _ = cpu.run();
+        let pc = cpu.registers.get_wide(WideRegister::PC);
+        cpu.step_back().expect("history");
+        assert_eq!(pc - 1, cpu.registers.get_wide(WideRegister::PC));
+    }
+
+    #[test]
+    fn step_back_without_history() {
+        let mut cpu = test_cpu();
+        assert!(matches!(cpu.step_back(), Err(RewindError::NoHistory)));
+    }
+
+    #[test]
+    fn step_back_is_not_observed() {
+        let mut cpu = test_cpu();
+        cpu.add_watchpoint(0..=0xff, crate::cpu::Watch::Write);
+        cpu.step().expect("valid instruction");
+        cpu.step().expect("valid instruction");
+        cpu.step_back().expect("history");
+        assert!(cpu.memory.take_accesses().is_empty());
+    }
+
+    #[test]
+    fn step_back_is_not_counted() {
+        let mut cpu = test_cpu();
+        cpu.memory.collect_stats(true);
+        cpu.step().expect("valid instruction");
+        cpu.step().expect("valid instruction");
+        cpu.step_back().expect("history");
+        let stats = cpu.memory.take_stats().expect("collecting");
+        assert_eq!(1, stats.total().writes);
+    }
+
+    #[test]
+    fn step_back_skips_io() {
+        let mut cpu = test_cpu();
+        let port = cpu
+            .memory_mut()
+            .add_named_device(
+                Box::new(TestDevice::new(1)),
+                0x8000,
+                0x8000,
+                "port",
+                DeviceKind::Io,
+            )
+            .expect("valid range");
+        let program = ProgramBuilder::new()
+            .mov_lit_mem(0xab, 0x8000)
+            .build()
+            .expect("no labels");
+        for (addr, &byte) in program.iter().enumerate() {
+            cpu.memory_mut().set(addr as u16, byte).expect("mapped");
+        }
+        cpu.step().expect("valid instruction");
+        cpu.step_back().expect("history");
+        let port = cpu.memory_mut().remove_device(port).expect("mapped");
+        assert_eq!(0xab, port.get(0).expect("valid"));
+    }
+
+    #[test]
+    fn step_back_failed_instruction() {
+        let program = ProgramBuilder::new()
+            .mov_lit_reg(0xab, Register::C)
+            .div_lit_reg(0, Register::C)
+            .build()
+            .expect("no labels");
+        let mut mem = TestDevice::new(0x100);
+        mem.write_slice(&program);
+        let mut cpu = create_cpu_with_memory(mem);
+        cpu.enable_rewind(1024);
+        cpu.step().expect("valid instruction");
+        cpu.step().expect_err("divide by zero");
+        assert_eq!(1, cpu.instructions());
+        assert_eq!(2, cpu.rewind_history());
+        cpu.step_back().expect("history");
+        assert_eq!(1, cpu.instructions());
+        assert_eq!(3, cpu.registers.get_wide(WideRegister::PC));
+        cpu.rewind_to(0).expect("in history");
+        assert_eq!(0, cpu.registers.get(Register::C));
+    }
+
+    #[test]
+    fn rewind_to_instruction() {
+        let mut cpu = test_cpu();
+        let snapshot = cpu.snapshot().expect("devices have state");
+        let _ = cpu.run();
+        assert_eq!(4, cpu.instructions());
+        cpu.rewind_to(0).expect("in history");
+        assert_eq!(snapshot, cpu.snapshot().expect("devices have state"));
+    }
+
+    #[test]
+    fn rewind_to_future() {
+        let mut cpu = test_cpu();
+        cpu.step().expect("valid instruction");
+        assert!(matches!(cpu.rewind_to(2), Err(RewindError::OutOfRange(2))));
+    }
+
+    #[test]
+    fn memory_limit_drops_oldest() {
+        let mut cpu = test_cpu();
+        let entry_size = mem::size_of::<UndoEntry>();
+        cpu.enable_rewind(entry_size * 2);
+        cpu.step().expect("valid instruction");
+        cpu.step().expect("valid instruction");
+        cpu.step().expect("valid instruction");
+        assert_eq!(1, cpu.rewind_history());
+        assert!(matches!(cpu.rewind_to(1), Err(RewindError::OutOfRange(1))));
+        cpu.rewind_to(2).expect("in history");
+    }
+
+    #[test]
+    fn disabled_records_nothing() {
+        let mut cpu = test_cpu();
+        cpu.disable_rewind();
+        cpu.step().expect("valid instruction");
+        assert_eq!(0, cpu.rewind_history());
+        assert!(!cpu.memory.is_observing());
+    }
+}
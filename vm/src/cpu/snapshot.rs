@@ -0,0 +1,406 @@
+use super::{Cpu, Flags, Register, RegisterState, WideRegister};
+use crate::memory::{DeviceError, DeviceKind, MapError, RegionState};
+use crate::util::bytes::{push_wide, Reader, Truncated};
+use strum::IntoEnumIterator;
+
+const MAGIC: &[u8; 4] = b"H8SS";
+
+/// Current version of the snapshot binary format
+///
+/// Older versions do not record enough of the memory layout to check it
+/// on restore, or the instruction count, so they are rejected.
+pub const SNAPSHOT_VERSION: u8 = 4;
+
+/// Complete machine state which can be restored into a [`Cpu`] with the
+/// same memory layout
+///
+/// # Binary format
+///
+/// All values are big-endian.
+///
+/// | Field      | Size                                   |
+/// |------------|----------------------------------------|
+/// | magic      | 4 bytes, `H8SS`                        |
+/// | version    | 1 byte                                 |
+/// | registers  | 1 byte each for `A`..`MB`, 2 each for `PC` and `SP` |
+/// | flags      | 1 byte, see [`Flags::bits`]            |
+/// | cycles     | 8 bytes                                |
+/// | instructions | 8 bytes                              |
+/// | regions    | 2 byte count, then for each region:    |
+/// |            | 2 byte name length then UTF-8 name, 1 byte kind, |
+/// |            | 2 byte start, 2 byte end, 2 byte mask, |
+/// |            | 2 byte mirror count then 2 byte start and end of each, |
+/// |            | 1 byte saved flag, if set 4 byte state length then contents |
+#[derive(Debug, Clone, PartialEq)]
+pub struct Snapshot {
+    pub registers: RegisterState,
+    pub cycles: u64,
+    pub instructions: u64,
+    pub regions: Vec<RegionState>,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum SnapshotError {
+    #[error("not an h8bit snapshot")]
+    BadMagic,
+    #[error("unsupported snapshot version: {0}")]
+    Version(u8),
+    #[error("snapshot ends unexpectedly")]
+    Truncated,
+    #[error("snapshot is corrupt")]
+    Corrupt,
+    #[error("memory layout does not match snapshot")]
+    Layout,
+    #[error("device error: {0}")]
+    Device(#[from] DeviceError),
+    #[error(transparent)]
+    Map(#[from] MapError),
+}
+
+impl From<Truncated> for SnapshotError {
+    fn from(_: Truncated) -> Self {
+        Self::Truncated
+    }
+}
+
+impl Cpu {
+    /// Fails if any mapped device neither exports its state nor skips
+    /// snapshots
+    pub fn snapshot(&self) -> Result<Snapshot, SnapshotError> {
+        Ok(Snapshot {
+            registers: self.registers.clone(),
+            cycles: self.cycles,
+            instructions: self.instructions,
+            regions: self.memory.export_regions()?,
+        })
+    }
+
+    /// Restore registers and memory contents
+    ///
+    /// Breakpoints, watchpoints and the tracer are left untouched, the rewind
+    /// history is cleared.
+    pub fn restore(&mut self, snapshot: &Snapshot) -> Result<(), SnapshotError> {
+        if !self.memory.has_layout(&snapshot.regions) {
+            return Err(SnapshotError::Layout);
+        }
+        self.memory.import_regions(&snapshot.regions)?;
+        self.registers = snapshot.registers.clone();
+        self.cycles = snapshot.cycles;
+        self.instructions = snapshot.instructions;
+        self.rewind.clear();
+        self.decode_cache.clear();
+        Ok(())
+    }
+}
+
+impl Snapshot {
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = MAGIC.to_vec();
+        bytes.push(SNAPSHOT_VERSION);
+        for reg in Register::iter() {
+            bytes.push(self.registers.get(reg));
+        }
+        for reg in [WideRegister::PC, WideRegister::SP] {
+            push_wide(&mut bytes, self.registers.get_wide(reg));
+        }
+        bytes.push(self.registers.flags().bits());
+        bytes.extend_from_slice(&self.cycles.to_be_bytes());
+        bytes.extend_from_slice(&self.instructions.to_be_bytes());
+        push_wide(&mut bytes, self.regions.len() as u16);
+        for region in &self.regions {
+            push_wide(&mut bytes, region.name.len() as u16);
+            bytes.extend_from_slice(region.name.as_bytes());
+            bytes.push(region.kind as u8);
+            push_wide(&mut bytes, region.start);
+            push_wide(&mut bytes, region.end);
+            push_wide(&mut bytes, region.mask);
+            push_wide(&mut bytes, region.mirrors.len() as u16);
+            for &(start, end) in &region.mirrors {
+                push_wide(&mut bytes, start);
+                push_wide(&mut bytes, end);
+            }
+            bytes.push(region.state.is_some() as u8);
+            if let Some(state) = &region.state {
+                bytes.extend_from_slice(&(state.len() as u32).to_be_bytes());
+                bytes.extend_from_slice(state);
+            }
+        }
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, SnapshotError> {
+        let mut reader = Reader::new(bytes);
+        if reader.take(MAGIC.len())? != MAGIC {
+            return Err(SnapshotError::BadMagic);
+        }
+        match reader.byte()? {
+            SNAPSHOT_VERSION => (),
+            version => return Err(SnapshotError::Version(version)),
+        }
+        let mut registers = RegisterState::new();
+        for reg in Register::iter() {
+            registers.set(reg, reader.byte()?);
+        }
+        for reg in [WideRegister::PC, WideRegister::SP] {
+            registers.set_wide(reg, reader.wide()?);
+        }
+        registers.set_flags(Flags::from_bits(reader.byte()?));
+        let cycles = u64::from_be_bytes(reader.array()?);
+        let instructions = u64::from_be_bytes(reader.array()?);
+        let count = reader.wide()?;
+        let mut regions = Vec::with_capacity(count as usize);
+        for _ in 0..count {
+            let len = reader.wide()?;
+            let name = String::from_utf8(reader.take(len as usize)?.to_vec())
+                .map_err(|_| SnapshotError::Corrupt)?;
+            let kind = DeviceKind::from_repr(reader.byte()?).ok_or(SnapshotError::Corrupt)?;
+            let start = reader.wide()?;
+            let end = reader.wide()?;
+            let mask = reader.wide()?;
+            let mirror_count = reader.wide()?;
+            let mut mirrors = Vec::with_capacity(mirror_count as usize);
+            for _ in 0..mirror_count {
+                mirrors.push((reader.wide()?, reader.wide()?));
+            }
+            let state = match reader.byte()? {
+                0 => None,
+                1 => {
+                    let len = u32::from_be_bytes(reader.array()?);
+                    Some(reader.take(len as usize)?.to_vec())
+                }
+                _ => return Err(SnapshotError::Corrupt),
+            };
+            regions.push(RegionState {
+                name,
+                kind,
+                start,
+                end,
+                mask,
+                mirrors,
+                state,
+            });
+        }
+        Ok(Self {
+            registers,
+            cycles,
+            instructions,
+            regions,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cpu::{operation::mov, tests::create_cpu_with_memory, Error};
+    use crate::memory::{Device, MemoryMapper, TestDevice};
+
+    fn test_cpu() -> Cpu {
+        let program = [
+            mov::lit_reg::CODE,
+            0xab,
+            Register::C.into(),
+            mov::lit_mem::CODE,
+            0xcd,
+            0x00,
+            0xf0,
+            0xFF,
+        ];
+        let mut mem = TestDevice::new(0x100);
+        mem.write_slice(&program);
+        create_cpu_with_memory(mem)
+    }
+
+    #[test]
+    fn snapshot_bytes_round_trip() {
+        let mut cpu = test_cpu();
+        cpu.step().expect("valid instruction");
+        let snapshot = cpu.snapshot().expect("devices have state");
+        let actual = Snapshot::from_bytes(&snapshot.to_bytes()).expect("valid snapshot");
+        assert_eq!(snapshot, actual);
+    }
+
+    #[test]
+    fn snapshot_keeps_flags() {
+        let mut cpu = test_cpu();
+        let flags = Flags::from_bits(0b011);
+        cpu.registers.set_flags(flags);
+        let actual = Snapshot::from_bytes(&cpu.snapshot().expect("devices have state").to_bytes())
+            .expect("valid snapshot");
+        assert_eq!(flags, actual.registers.flags());
+    }
+
+    #[test]
+    fn from_bytes_rejects_versions_without_layout() {
+        let mut bytes = test_cpu()
+            .snapshot()
+            .expect("devices have state")
+            .to_bytes();
+        bytes[MAGIC.len()] = SNAPSHOT_VERSION - 1;
+        let err = Snapshot::from_bytes(&bytes).expect_err("old version");
+        assert!(matches!(err, SnapshotError::Version(v) if v == SNAPSHOT_VERSION - 1));
+    }
+
+    #[test]
+    fn restore_mirror_mismatch() {
+        let mut cpu = test_cpu();
+        let snapshot = cpu.snapshot().expect("devices have state");
+        let memory = cpu.memory_mut();
+        let device = memory
+            .add_device(Box::new(TestDevice::new(0x10)), 0x8000, 0x800f)
+            .expect("valid range");
+        let with_device = cpu.snapshot().expect("devices have state");
+        cpu.memory_mut()
+            .add_mirror(device, 0x9000, 0x900f)
+            .expect("mapped");
+        for snapshot in [snapshot, with_device] {
+            let err = cpu.restore(&snapshot).expect_err("different layout");
+            assert!(matches!(err, SnapshotError::Layout));
+        }
+    }
+
+    #[test]
+    fn snapshot_lists_devices_without_state() {
+        let mut cpu = test_cpu();
+        let port = Box::new(Port { skip: false });
+        cpu.memory_mut()
+            .add_named_device(port, 0x8000, 0x8000, "port", DeviceKind::Io)
+            .expect("valid range");
+        let err = cpu.snapshot().expect_err("device without state");
+        assert_eq!("devices cannot save their state: port", err.to_string());
+    }
+
+    #[test]
+    fn snapshot_skips_opted_out_devices() {
+        let mut cpu = test_cpu();
+        let port = Box::new(Port { skip: true });
+        cpu.memory_mut()
+            .add_named_device(port, 0x8000, 0x8000, "port", DeviceKind::Io)
+            .expect("valid range");
+        cpu.step().expect("valid instruction");
+        let snapshot = cpu.snapshot().expect("port skips snapshots");
+        let bytes = snapshot.to_bytes();
+        assert_eq!(
+            snapshot,
+            Snapshot::from_bytes(&bytes).expect("valid snapshot")
+        );
+        assert_eq!(None, snapshot.regions[0].state);
+        assert!(snapshot.regions[1].state.is_some());
+
+        let mut fresh = test_cpu();
+        let err = fresh.restore(&snapshot).expect_err("port not mapped");
+        assert!(matches!(err, SnapshotError::Layout));
+        let port = Box::new(Port { skip: true });
+        fresh
+            .memory_mut()
+            .add_named_device(port, 0x8000, 0x8000, "port", DeviceKind::Io)
+            .expect("valid range");
+        fresh.restore(&snapshot).expect("same layout");
+        assert_eq!(cpu.registers, fresh.registers);
+    }
+
+    /// Device without snapshot support, which may opt out of snapshots
+    struct Port {
+        skip: bool,
+    }
+
+    impl Device for Port {
+        fn skip_snapshot(&self) -> bool {
+            self.skip
+        }
+
+        fn set(&mut self, _addr: u16, _data: u8) -> Result<(), DeviceError> {
+            Ok(())
+        }
+
+        fn get(&self, _addr: u16) -> Result<u8, DeviceError> {
+            Ok(0)
+        }
+
+        fn set_wide(&mut self, _addr: u16, _data: u16) -> Result<(), DeviceError> {
+            Ok(())
+        }
+
+        fn get_wide(&self, _addr: u16) -> Result<u16, DeviceError> {
+            Ok(0)
+        }
+    }
+
+    #[test]
+    fn restore_into_fresh_cpu() {
+        let mut cpu = test_cpu();
+        cpu.step().expect("valid instruction");
+        cpu.step().expect("valid instruction");
+        let bytes = cpu.snapshot().expect("devices have state").to_bytes();
+
+        let mut fresh = test_cpu();
+        let snapshot = Snapshot::from_bytes(&bytes).expect("valid snapshot");
+        fresh.restore(&snapshot).expect("same layout");
+        assert_eq!(cpu.registers, fresh.registers);
+        assert_eq!(cpu.cycles(), fresh.cycles());
+        assert_eq!(2, fresh.instructions());
+        assert_eq!(0xcd, fresh.memory.get(0xf0).expect("valid address"));
+        let err = fresh.step().expect_err("halting error");
+        assert!(matches!(err, Error::Halt));
+    }
+
+    #[test]
+    fn restore_rewinds() {
+        let mut cpu = test_cpu();
+        let snapshot = cpu.snapshot().expect("devices have state");
+        cpu.step().expect("valid instruction");
+        cpu.step().expect("valid instruction");
+        cpu.restore(&snapshot).expect("same layout");
+        assert_eq!(snapshot, cpu.snapshot().expect("devices have state"));
+    }
+
+    #[test]
+    fn restore_layout_mismatch() {
+        let snapshot = test_cpu().snapshot().expect("devices have state");
+        let mut mapper = MemoryMapper::new();
+        mapper
+            .add_device(Box::new(TestDevice::new(0x10)), 0, 0x0f)
+            .expect("valid range");
+        let mut cpu = Cpu::new(mapper).expect("valid CPU");
+        let err = cpu.restore(&snapshot).expect_err("different layout");
+        assert!(matches!(err, SnapshotError::Layout));
+    }
+
+    #[test]
+    fn restore_wrong_device_size() {
+        let mut snapshot = test_cpu().snapshot().expect("devices have state");
+        snapshot.regions[0].state = Some(vec![0; 4]);
+        let err = test_cpu().restore(&snapshot).expect_err("wrong size");
+        assert!(matches!(
+            err,
+            SnapshotError::Device(DeviceError::InvalidState)
+        ));
+    }
+
+    #[test]
+    fn from_bytes_bad_magic() {
+        let err = Snapshot::from_bytes(b"NOPE\x01").expect_err("bad magic");
+        assert!(matches!(err, SnapshotError::BadMagic));
+    }
+
+    #[test]
+    fn from_bytes_unsupported_version() {
+        let mut bytes = test_cpu()
+            .snapshot()
+            .expect("devices have state")
+            .to_bytes();
+        bytes[MAGIC.len()] = SNAPSHOT_VERSION + 1;
+        let err = Snapshot::from_bytes(&bytes).expect_err("bad version");
+        assert!(matches!(err, SnapshotError::Version(v) if v == SNAPSHOT_VERSION + 1));
+    }
+
+    #[test]
+    fn from_bytes_truncated() {
+        let bytes = test_cpu()
+            .snapshot()
+            .expect("devices have state")
+            .to_bytes();
+        let err = Snapshot::from_bytes(&bytes[..bytes.len() - 1]).expect_err("truncated");
+        assert!(matches!(err, SnapshotError::Truncated));
+    }
+}
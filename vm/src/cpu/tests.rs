@@ -1,5 +1,9 @@
 use super::*;
-use crate::{memory::TestDevice, util::high_and_low_value};
+use crate::cpu::trace::tests::SharedOutput;
+use crate::{
+    memory::{Access, AccessKind, Device, TestDevice},
+    util::{high_and_low_value, wide_value},
+};
 use std::collections::BTreeMap;
 
 #[test]
@@ -43,7 +47,369 @@ fn fetch_wide_no_mem() {
 #[test]
 fn run_no_program() {
     let mut cpu = create_cpu_with_boot(&[]);
-    cpu.run();
+    let err = cpu.run().expect_err("out of bounds error");
+    assert_cpu_error_is_out_of_bounds(err, TEST_DEVICE_SIZE);
+}
+
+#[test]
+fn step_stops_at_breakpoint() {
+    let program = [0x00, 0x00, 0xFF];
+    let mut cpu = create_cpu_with_boot(&program);
+    cpu.add_breakpoint(1);
+    assert_eq!(Step::Executed, cpu.step().expect("NOP doesn't error"));
+    let step = cpu.step().expect("breakpoint isn't an error");
+    assert_eq!(Step::Stopped(StopReason::Breakpoint(1)), step);
+    assert_eq!(1, cpu.registers.get_wide(PC));
+}
+
+#[test]
+fn step_resumes_from_breakpoint() {
+    let program = [0x00, 0xFF];
+    let mut cpu = create_cpu_with_boot(&program);
+    cpu.add_breakpoint(0);
+    cpu.step().expect("breakpoint isn't an error");
+    assert_eq!(Step::Executed, cpu.step().expect("NOP doesn't error"));
+    assert_eq!(1, cpu.registers.get_wide(PC));
+}
+
+#[test]
+fn run_stops_at_breakpoint_again() {
+    let program = [0x00, 0x00, 0xFF];
+    let mut cpu = create_cpu_with_boot(&program);
+    cpu.add_breakpoint(1);
+    let reason = cpu.run().expect("breakpoint isn't an error");
+    assert_eq!(StopReason::Breakpoint(1), reason);
+    let err = cpu.run().expect_err("halting error");
+    assert!(matches!(err, Error::Halt));
+}
+
+#[test]
+fn run_removed_breakpoint() {
+    let program = [0x00, 0xFF];
+    let mut cpu = create_cpu_with_boot(&program);
+    cpu.add_breakpoint(1);
+    assert!(cpu.remove_breakpoint(1));
+    let err = cpu.run().expect_err("halting error");
+    assert!(matches!(err, Error::Halt));
+}
+
+#[test]
+fn run_stops_at_write_watchpoint() {
+    let addr = 0x01f0;
+    let (high, low) = high_and_low_value(addr);
+    let program = [operation::mov::lit_mem::CODE, 0xab, high, low, 0xFF];
+    let mut cpu = create_cpu_with_boot(&program);
+    cpu.add_watchpoint(addr..=addr, Watch::Write);
+    let reason = cpu.run().expect("watchpoint isn't an error");
+    let expected = Access {
+        kind: AccessKind::Write,
+        addr,
+        value: 0xab,
+        previous: 0,
+    };
+    assert_eq!(StopReason::Watchpoint(expected), reason);
+    assert_eq!(program.len() as u16 - 1, cpu.registers.get_wide(PC));
+}
+
+#[test]
+fn run_ignores_fetch_for_read_watchpoint() {
+    let program = [0x00, 0xFF];
+    let mut cpu = create_cpu_with_boot(&program);
+    cpu.add_watchpoint(0..=1, Watch::ReadWrite);
+    let err = cpu.run().expect_err("halting error");
+    assert!(matches!(err, Error::Halt));
+}
+
+#[test]
+fn run_stops_at_read_watchpoint() {
+    let addr = 0x01f0;
+    let (high, low) = high_and_low_value(addr);
+    let program = [
+        operation::mov::mem_reg::CODE,
+        high,
+        low,
+        Register::A.into(),
+        0xFF,
+    ];
+    let mut cpu = create_cpu_with_boot(&program);
+    cpu.add_watchpoint(addr..=addr, Watch::Write);
+    cpu.add_watchpoint(addr..=addr, Watch::Read);
+    let reason = cpu.run().expect("watchpoint isn't an error");
+    assert!(matches!(reason, StopReason::Watchpoint(access) if access.addr == addr));
+}
+
+#[test]
+fn cleared_watchpoints_stop_observing() {
+    let mut cpu = create_cpu_with_boot(&[]);
+    cpu.add_watchpoint(0..=1, Watch::Read);
+    assert!(cpu.memory.is_observing());
+    cpu.clear_watchpoints();
+    assert!(!cpu.memory.is_observing());
+}
+
+#[test]
+fn step_counts_cycles() {
+    let program = [
+        operation::mov::lit_reg::CODE,
+        0xab,
+        Register::C.into(),
+        0x00,
+    ];
+    let mut cpu = create_cpu_with_boot(&program);
+    assert_eq!(0, cpu.cycles());
+    cpu.step().expect("valid instruction");
+    let mov_cycles = operation::mov::lit_reg::CYCLES as u64;
+    assert_eq!(mov_cycles, cpu.cycles());
+    cpu.step().expect("NOP doesn't error");
+    assert_eq!(mov_cycles + operation::nop::CYCLES as u64, cpu.cycles());
+}
+
+#[test]
+fn halt_counts_cycles() {
+    let mut cpu = create_cpu_with_boot(&[0xFF]);
+    cpu.step().expect_err("halting error");
+    assert_eq!(operation::hlt::CYCLES as u64, cpu.cycles());
+}
+
+#[test]
+fn failed_instruction_is_not_counted() {
+    let program = [operation::div::lit_reg::CODE, 0x00, Register::C.into()];
+    let mut cpu = create_cpu_with_boot(&program);
+    cpu.set_coverage(Coverage::new());
+    let err = cpu.step().expect_err("divide by zero");
+    assert!(matches!(err, Error::DivideByZero));
+    assert_eq!(0, cpu.cycles());
+    assert_eq!(0, cpu.instructions());
+    assert_eq!(0, cpu.coverage().expect("enabled").hits(0));
+}
+
+#[test]
+fn breakpoint_takes_no_cycles() {
+    let mut cpu = create_cpu_with_boot(&[0x00]);
+    cpu.add_breakpoint(0);
+    cpu.step().expect("breakpoint isn't an error");
+    assert_eq!(0, cpu.cycles());
+}
+
+#[test]
+fn run_for_cycles_stops_after_budget() {
+    let mut cpu = create_cpu_with_boot(&[]);
+    let stop = cpu.run_for_cycles(10).expect("NOPs don't error");
+    assert_eq!(None, stop);
+    assert_eq!(10, cpu.cycles());
+    assert_eq!(10, cpu.registers.get_wide(PC));
+}
+
+#[test]
+fn run_for_cycles_overruns_with_last_instruction() {
+    let program = [operation::mov::lit_reg::CODE, 0xab, Register::C.into()];
+    let mut cpu = create_cpu_with_boot(&program);
+    cpu.run_for_cycles(1).expect("valid instruction");
+    assert_eq!(operation::mov::lit_reg::CYCLES as u64, cpu.cycles());
+}
+
+#[test]
+fn run_for_cycles_stops_at_breakpoint() {
+    let mut cpu = create_cpu_with_boot(&[]);
+    cpu.add_breakpoint(2);
+    let stop = cpu.run_for_cycles(10).expect("NOPs don't error");
+    assert_eq!(Some(StopReason::Breakpoint(2)), stop);
+    assert_eq!(2, cpu.cycles());
+}
+
+#[test]
+fn trace_text() {
+    let program = [
+        operation::mov::lit_reg::CODE,
+        0xab,
+        Register::C.into(),
+        0xFF,
+    ];
+    let (trace, err) = run_traced(&program, TraceFormat::Text);
+    assert!(matches!(err, Error::Halt));
+    let expected = "0x0000: 10 MOV_LIT_REG 0xab, C | C=0xab\n0x0003: ff HLT\n";
+    assert_eq!(expected, trace);
+}
+
+#[test]
+fn trace_cmp_flags() {
+    let program = [
+        operation::mov::lit_reg::CODE,
+        0xab,
+        Register::C.into(),
+        operation::cmp::lit_reg::CODE,
+        0xab,
+        Register::C.into(),
+        0xFF,
+    ];
+    let (trace, _) = run_traced(&program, TraceFormat::Text);
+    let cmp = trace.lines().nth(1).expect("CMP trace line");
+    assert_eq!("0x0003: 30 CMP_LIT_REG 0xab, C | FLAGS=Z--", cmp);
+
+    let (trace, _) = run_traced(&program, TraceFormat::JsonLines);
+    let lines: Vec<serde_json::Value> = trace
+        .lines()
+        .map(|line| serde_json::from_str(line).expect("valid JSON"))
+        .collect();
+    assert_eq!(serde_json::json!({"C": 0xab}), lines[0]["registers"]);
+    assert_eq!(serde_json::json!({"FLAGS": "Z--"}), lines[1]["registers"]);
+}
+
+#[test]
+fn trace_json_lines_memory_writes() {
+    let addr = 0x01f0;
+    let (high, low) = high_and_low_value(addr);
+    let program = [operation::mov::lit_mem::CODE, 0xab, high, low, 0xFF];
+    let (trace, _) = run_traced(&program, TraceFormat::JsonLines);
+    let first = trace.lines().next().expect("first trace line");
+    let first: serde_json::Value = serde_json::from_str(first).expect("valid JSON");
+    assert_eq!(
+        serde_json::json!([{"addr": 496, "value": 171}]),
+        first["writes"]
+    );
+    assert_eq!(2, trace.lines().count());
+}
+
+#[test]
+fn trace_skips_undecodable_instruction() {
+    let program = [operation::mov::lit_reg::CODE, 0xab, 0x00];
+    let (trace, err) = run_traced(&program, TraceFormat::Text);
+    crate::cpu::register::tests::assert_cpu_error_is_invalid_register(err, 0x00);
+    assert!(trace.is_empty());
+}
+
+#[test]
+fn take_tracer_stops_tracing() {
+    let mut cpu = create_cpu_with_boot(&[0x00, 0xFF]);
+    cpu.set_tracer(Tracer::new(Vec::new(), TraceFormat::Text));
+    assert!(cpu.memory.is_observing());
+    assert!(cpu.take_tracer().is_some());
+    assert!(!cpu.memory.is_observing());
+}
+
+#[test]
+fn trace_failure_keeps_undo_entry() {
+    /// Trace output which always fails
+    struct Broken;
+
+    impl io::Write for Broken {
+        fn write(&mut self, _buf: &[u8]) -> io::Result<usize> {
+            Err(io::ErrorKind::BrokenPipe.into())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    let (high, low) = high_and_low_value(0x01f0);
+    let program = [operation::mov::lit_mem::CODE, 0xab, high, low, 0xFF];
+    let mut cpu = create_cpu_with_boot(&program);
+    cpu.enable_rewind(1024);
+    cpu.set_tracer(Tracer::new(Broken, TraceFormat::Text));
+    let err = cpu.step().expect_err("trace output fails");
+    assert!(matches!(err, Error::Trace(_)));
+    assert_eq!(1, cpu.instructions());
+    assert_eq!(0xab, cpu.memory.get(0x01f0).expect("valid address"));
+    cpu.step_back().expect("instruction was recorded");
+    assert_eq!(0, cpu.instructions());
+    assert_eq!(0, cpu.cycles());
+    assert_eq!(0, cpu.memory.get(0x01f0).expect("valid address"));
+}
+
+/// Run a program until it errors, returning the trace for failure reports
+#[test]
+fn coverage_records_executed_pcs() {
+    let program = [0x00, 0x00, 0xFF, 0x00];
+    let mut cpu = create_cpu_with_boot(&program);
+    cpu.step().expect("NOP doesn't error");
+    cpu.set_coverage(Coverage::new());
+    cpu.run().expect_err("halting error");
+    let coverage = cpu.take_coverage().expect("recording coverage");
+    assert_eq!(
+        vec![(1, 1), (2, 1)],
+        coverage.executed().collect::<Vec<_>>()
+    );
+    assert!(cpu.coverage().is_none());
+}
+
+#[test]
+fn profiler_counts_cycles() {
+    let program = [0x00, 0x00, 0xFF];
+    let mut cpu = create_cpu_with_boot(&program);
+    let mut profiler = Profiler::new();
+    profiler.add_function("main", 0);
+    profiler.add_function("end", 2);
+    cpu.set_profiler(profiler);
+    cpu.run().expect_err("halting error");
+    let profile = cpu.take_profiler().expect("profiling").profile();
+    let main = profile.iter().find(|f| f.name == "main").expect("ran");
+    assert_eq!(2, main.instructions);
+    assert_eq!(2 * operation::nop::CYCLES as u64, main.cycles);
+    assert!(cpu.profiler().is_none());
+}
+
+#[test]
+fn cpu_runs_on_fixed_bus() {
+    let program = ProgramBuilder::new()
+        .mov_lit_mem(0xab, 0x80)
+        .mov_mem_reg(0x80, Register::C)
+        .hlt()
+        .build()
+        .expect("valid program");
+    let mut bus = FlatBus([0; 256]);
+    bus.0[..program.len()].copy_from_slice(&program);
+    let mut cpu = Cpu::new(bus).expect("valid cpu");
+    cpu.enable_decode_cache();
+    let err = cpu.run().expect_err("halting error");
+    assert!(matches!(err, Error::Halt));
+    assert_eq!(0xab, cpu.registers().get(Register::C));
+    assert_eq!(0xff, cpu.registers().get_wide(WideRegister::SP));
+    assert!(cpu.memory().take_accesses().is_empty());
+}
+
+/// Bus with a fixed layout, recording no accesses
+struct FlatBus([u8; 256]);
+
+impl Device for FlatBus {
+    fn set(&mut self, addr: u16, data: u8) -> Result<(), DeviceError> {
+        let byte = self.0.get_mut(addr as usize);
+        *byte.ok_or(DeviceError::OutOfBounds(addr))? = data;
+        Ok(())
+    }
+
+    fn get(&self, addr: u16) -> Result<u8, DeviceError> {
+        let byte = self.0.get(addr as usize);
+        byte.copied().ok_or(DeviceError::OutOfBounds(addr))
+    }
+
+    fn set_wide(&mut self, addr: u16, data: u16) -> Result<(), DeviceError> {
+        let (high, low) = high_and_low_value(data);
+        self.set(addr, high)?;
+        self.set(addr + 1, low)
+    }
+
+    fn get_wide(&self, addr: u16) -> Result<u16, DeviceError> {
+        Ok(wide_value(self.get(addr)?, self.get(addr + 1)?))
+    }
+}
+
+impl Bus for FlatBus {
+    fn start(&self) -> Option<u16> {
+        Some(0)
+    }
+
+    fn end(&self) -> Option<u16> {
+        Some(0xff)
+    }
+}
+
+pub fn run_traced(program: &[u8], format: TraceFormat) -> (String, Error) {
+    let output = SharedOutput::default();
+    let mut cpu = create_cpu_with_boot(program);
+    cpu.set_tracer(Tracer::new(output.clone(), format));
+    let err = cpu.run().expect_err("program ends with an error");
+    (output.contents(), err)
 }
 
 pub fn assert_cpu_error_is_out_of_bounds(err: Error, expected: u16) {
@@ -62,7 +428,7 @@ pub fn create_cpu_with_memory(mem: TestDevice) -> Cpu {
     let mut mapper = MemoryMapper::new();
     let end = mem.end();
     let device = Box::new(mem);
-    mapper.add_device(device, 0, end);
+    mapper.add_device(device, 0, end).expect("valid range");
     Cpu::new(mapper).expect("valid CPU")
 }
 
@@ -72,6 +438,7 @@ pub struct TestCpuState {
     pc: Option<u16>,
     sp: Option<u16>,
     registers: Option<TestRegState>,
+    flags: Option<Flags>,
     memory: Option<TestDevice>,
 }
 
@@ -108,6 +475,11 @@ impl TestCpuState {
         self
     }
 
+    pub fn flags(&mut self, flags: Flags) -> &mut Self {
+        self.flags = Some(flags);
+        self
+    }
+
     pub fn reg_wide(&mut self, reg: WideRegister, value: u16) -> &mut Self {
         let (high_val, low_val) = high_and_low_value(value);
         let (high_reg, low_reg) = reg.high_and_low();
@@ -171,6 +543,9 @@ impl TestCpuState {
                 cpu.registers.set(reg, value);
             }
         }
+        if let Some(flags) = self.flags {
+            cpu.registers.set_flags(flags);
+        }
         cpu
     }
 }
@@ -182,6 +557,7 @@ pub fn assert_cpu_state(cpu: &Cpu, expected: &TestCpuState) {
     assert_pc(cpu, expected.pc);
     assert_sp(cpu, expected.sp);
     assert_all_registers(cpu, expected.registers.as_ref());
+    assert_flags(cpu, expected.flags);
     assert_mem(cpu, expected.memory.as_ref());
 }
 
@@ -233,6 +609,17 @@ fn assert_all_registers(cpu: &Cpu, registers: Option<&TestRegState>) {
     }
 }
 
+fn assert_flags(cpu: &Cpu, flags: Option<Flags>) {
+    if let Some(flags) = flags {
+        let actual = cpu.registers.flags();
+        assert_eq!(
+            flags, actual,
+            "\nFlags\n{:>12}: {}\n{:>12}: {}\n",
+            "expected", flags, "actual", actual
+        );
+    }
+}
+
 fn assert_mem(cpu: &Cpu, mem: Option<&TestDevice>) {
     if let Some(mem) = mem {
         for i in 0..mem.size() {
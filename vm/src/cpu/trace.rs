@@ -0,0 +1,341 @@
+use super::{AnyRegister, Flags, Instruction, Register, RegisterState, WideRegister};
+use crate::memory::{Access, AccessKind};
+use serde_json::{json, Map};
+use std::fmt;
+use std::io;
+use strum::IntoEnumIterator;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TraceFormat {
+    /// One human readable line per instruction
+    Text,
+    /// One JSON object per line
+    JsonLines,
+}
+
+/// Writes a [`TraceRecord`] for every instruction the CPU executes
+pub struct Tracer {
+    out: Box<dyn io::Write>,
+    format: TraceFormat,
+}
+
+/// Everything one instruction did
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TraceRecord {
+    pub pc: u16,
+    pub opcode: u8,
+    pub instruction: Instruction,
+    /// Registers which changed, excluding the program counter
+    pub registers: Vec<RegisterChange>,
+    /// New condition flags, `None` if unchanged
+    pub flags: Option<Flags>,
+    /// Bytes written to memory as `(address, value)`
+    pub writes: Vec<(u16, u8)>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RegisterChange {
+    pub reg: AnyRegister,
+    pub value: u16,
+}
+
+impl fmt::Debug for Tracer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Tracer")
+            .field("format", &self.format)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Tracer {
+    pub fn new(out: impl io::Write + 'static, format: TraceFormat) -> Self {
+        Self {
+            out: Box::new(out),
+            format,
+        }
+    }
+
+    pub fn format(&self) -> TraceFormat {
+        self.format
+    }
+
+    pub fn into_inner(self) -> Box<dyn io::Write> {
+        self.out
+    }
+
+    pub fn record(&mut self, record: &TraceRecord) -> io::Result<()> {
+        match self.format {
+            TraceFormat::Text => writeln!(self.out, "{}", record),
+            TraceFormat::JsonLines => writeln!(self.out, "{}", record.to_json()),
+        }
+    }
+}
+
+impl TraceRecord {
+    pub(super) fn new(
+        pc: u16,
+        opcode: u8,
+        instruction: Instruction,
+        before: &RegisterState,
+        after: &RegisterState,
+        accesses: &[Access],
+    ) -> Self {
+        let writes = accesses
+            .iter()
+            .filter(|access| access.kind == AccessKind::Write)
+            .map(|access| (access.addr, access.value))
+            .collect();
+        Self {
+            pc,
+            opcode,
+            instruction,
+            registers: register_changes(before, after),
+            flags: (before.flags() != after.flags()).then(|| after.flags()),
+            writes,
+        }
+    }
+
+    pub fn to_json(&self) -> String {
+        let operands: Vec<_> = self
+            .instruction
+            .operands()
+            .iter()
+            .map(ToString::to_string)
+            .collect();
+        let mut registers: Map<_, _> = self
+            .registers
+            .iter()
+            .map(|change| (change.name().to_string(), change.value.into()))
+            .collect();
+        if let Some(flags) = self.flags {
+            registers.insert("FLAGS".to_string(), flags.to_string().into());
+        }
+        let writes: Vec<_> = self
+            .writes
+            .iter()
+            .map(|(addr, value)| json!({"addr": addr, "value": value}))
+            .collect();
+        json!({
+            "pc": self.pc,
+            "opcode": self.opcode,
+            "instruction": self.instruction.operation().name(),
+            "operands": operands,
+            "registers": registers,
+            "writes": writes,
+        })
+        .to_string()
+    }
+}
+
+impl RegisterChange {
+    pub fn name(&self) -> &'static str {
+        match self.reg {
+            AnyRegister::Std(reg) => reg.as_str(),
+            AnyRegister::Wide(reg) => reg.as_str(),
+        }
+    }
+}
+
+fn register_changes(before: &RegisterState, after: &RegisterState) -> Vec<RegisterChange> {
+    let mut changes: Vec<_> = Register::iter()
+        .filter(|&reg| before.get(reg) != after.get(reg))
+        .map(|reg| RegisterChange {
+            reg: reg.into(),
+            value: after.get(reg) as u16,
+        })
+        .collect();
+    let sp = WideRegister::SP;
+    if before.get_wide(sp) != after.get_wide(sp) {
+        changes.push(RegisterChange {
+            reg: sp.into(),
+            value: after.get_wide(sp),
+        });
+    }
+    changes
+}
+
+impl fmt::Display for RegisterChange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.reg {
+            AnyRegister::Std(_) => write!(f, "{}={:#04x}", self.name(), self.value),
+            AnyRegister::Wide(_) => write!(f, "{}={:#06x}", self.name(), self.value),
+        }
+    }
+}
+
+impl fmt::Display for TraceRecord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:#06x}: {:02x} {}",
+            self.pc, self.opcode, self.instruction
+        )?;
+        if !self.registers.is_empty() || self.flags.is_some() {
+            write!(f, " |")?;
+            for change in &self.registers {
+                write!(f, " {}", change)?;
+            }
+            if let Some(flags) = self.flags {
+                write!(f, " FLAGS={}", flags)?;
+            }
+        }
+        if !self.writes.is_empty() {
+            write!(f, " |")?;
+            for (addr, value) in &self.writes {
+                write!(f, " [{:#06x}]={:#04x}", addr, value)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use serde_json::Value;
+    use std::{cell::RefCell, rc::Rc};
+
+    /// Trace output which can be read after the [`Tracer`] is given to a CPU
+    #[derive(Debug, Clone, Default)]
+    pub struct SharedOutput(Rc<RefCell<Vec<u8>>>);
+
+    impl SharedOutput {
+        pub fn contents(&self) -> String {
+            String::from_utf8(self.0.borrow().clone()).expect("utf8 trace")
+        }
+    }
+
+    impl io::Write for SharedOutput {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.borrow_mut().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn test_record() -> TraceRecord {
+        TraceRecord {
+            pc: 0x10,
+            opcode: 0x11,
+            instruction: Instruction::MovLitRegWide {
+                literal: 0x01f0,
+                reg: WideRegister::CD,
+            },
+            registers: vec![
+                RegisterChange {
+                    reg: Register::C.into(),
+                    value: 0x01,
+                },
+                RegisterChange {
+                    reg: WideRegister::SP.into(),
+                    value: 0xfff0,
+                },
+            ],
+            flags: None,
+            writes: vec![(0x01f0, 0xab)],
+        }
+    }
+
+    fn test_cmp_record() -> TraceRecord {
+        TraceRecord {
+            pc: 0x03,
+            opcode: 0x30,
+            instruction: Instruction::CmpLitReg {
+                literal: 0xab,
+                reg: Register::C,
+            },
+            registers: vec![],
+            flags: Some(Flags {
+                zero: true,
+                ..Flags::default()
+            }),
+            writes: vec![],
+        }
+    }
+
+    #[test]
+    fn record_text() {
+        let expected = "0x0010: 11 MOV_LIT_REG_WIDE 0x01f0, CD | C=0x01 SP=0xfff0 | [0x01f0]=0xab";
+        assert_eq!(expected, test_record().to_string());
+    }
+
+    #[test]
+    fn record_text_flags() {
+        let expected = "0x0003: 30 CMP_LIT_REG 0xab, C | FLAGS=Z--";
+        assert_eq!(expected, test_cmp_record().to_string());
+    }
+
+    fn parse(json: &str) -> Value {
+        serde_json::from_str(json).expect("valid JSON")
+    }
+
+    #[test]
+    fn record_json() {
+        let expected = json!({
+            "pc": 16,
+            "opcode": 17,
+            "instruction": "MOV_LIT_REG_WIDE",
+            "operands": ["0x01f0", "CD"],
+            "registers": {"C": 1, "SP": 65520},
+            "writes": [{"addr": 496, "value": 171}],
+        });
+        assert_eq!(expected, parse(&test_record().to_json()));
+    }
+
+    #[test]
+    fn record_json_flags() {
+        let json = parse(&test_cmp_record().to_json());
+        assert_eq!(json!({"FLAGS": "Z--"}), json["registers"]);
+    }
+
+    #[test]
+    fn record_json_empty() {
+        let record = TraceRecord {
+            pc: 0,
+            opcode: 0,
+            instruction: Instruction::Nop,
+            registers: vec![],
+            flags: None,
+            writes: vec![],
+        };
+        let expected = json!({
+            "pc": 0,
+            "opcode": 0,
+            "instruction": "NOP",
+            "operands": [],
+            "registers": {},
+            "writes": [],
+        });
+        assert_eq!(expected, parse(&record.to_json()));
+    }
+
+    #[test]
+    fn register_changes_skip_pc() {
+        let before = RegisterState::new();
+        let mut after = RegisterState::new();
+        after.set_wide(WideRegister::PC, 4);
+        after.set_wide(WideRegister::AB, 0x0102);
+        let expected = vec![
+            RegisterChange {
+                reg: Register::A.into(),
+                value: 0x01,
+            },
+            RegisterChange {
+                reg: Register::B.into(),
+                value: 0x02,
+            },
+        ];
+        assert_eq!(expected, register_changes(&before, &after));
+    }
+
+    #[test]
+    fn tracer_writes_lines() {
+        let output = SharedOutput::default();
+        let mut tracer = Tracer::new(output.clone(), TraceFormat::Text);
+        tracer.record(&test_record()).expect("write trace");
+        tracer.record(&test_record()).expect("write trace");
+        assert_eq!(2, output.contents().lines().count());
+    }
+}
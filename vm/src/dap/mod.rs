@@ -0,0 +1,654 @@
+use crate::cpu::{Cpu, Error as CpuError, Register, Step, StopReason, WideRegister};
+use crate::source_map::SourceMap;
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use serde_json::{json, Value};
+use std::fmt;
+use std::fs;
+use std::io::{self, BufRead, Write};
+use std::mem;
+use std::path::Path;
+use strum::IntoEnumIterator;
+
+const THREAD_ID: u64 = 1;
+const REGISTERS_REF: u64 = 1;
+const NOT_LAUNCHED: &str = "no program launched";
+
+/// Creates the CPU for a `launch` request from the program path, with the
+/// source map of a program assembled from source
+pub type Loader =
+    Box<dyn FnMut(&Path) -> Result<(Cpu, Option<SourceMap>), Box<dyn std::error::Error>>>;
+
+/// Debug Adapter Protocol server
+///
+/// There is a single thread with a single stack frame at the program
+/// counter. Execution is synchronous: `continue` returns once the program
+/// stops, so `pause` is refused and a program which never stops blocks the
+/// server.
+pub struct DapServer<R, W> {
+    input: R,
+    output: W,
+    loader: Loader,
+    seq: u64,
+    cpu: Option<Cpu>,
+    source_map: Option<SourceMap>,
+    source_breakpoints: Vec<u16>,
+    instruction_breakpoints: Vec<u16>,
+    stop_on_entry: bool,
+    // sent after the response to the current request
+    events: Vec<(&'static str, Value)>,
+    done: bool,
+}
+
+impl<R: fmt::Debug, W: fmt::Debug> fmt::Debug for DapServer<R, W> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("DapServer")
+            .field("input", &self.input)
+            .field("output", &self.output)
+            .field("seq", &self.seq)
+            .field("cpu", &self.cpu)
+            .field("source_map", &self.source_map)
+            .field("source_breakpoints", &self.source_breakpoints)
+            .field("instruction_breakpoints", &self.instruction_breakpoints)
+            .field("stop_on_entry", &self.stop_on_entry)
+            .field("events", &self.events)
+            .field("done", &self.done)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<R: BufRead, W: Write> DapServer<R, W> {
+    pub fn new(input: R, output: W, loader: Loader) -> Self {
+        Self {
+            input,
+            output,
+            loader,
+            seq: 0,
+            cpu: None,
+            source_map: None,
+            source_breakpoints: Vec::new(),
+            instruction_breakpoints: Vec::new(),
+            stop_on_entry: false,
+            events: Vec::new(),
+            done: false,
+        }
+    }
+
+    pub fn cpu(&self) -> Option<&Cpu> {
+        self.cpu.as_ref()
+    }
+
+    /// Handle requests until the client disconnects or closes the input
+    pub fn serve(&mut self) -> io::Result<()> {
+        while !self.done {
+            let request = match self.read_message()? {
+                Some(request) => request,
+                None => break,
+            };
+            let command = request["command"].as_str().unwrap_or_default();
+            let result = self.handle(command, &request["arguments"]);
+            let mut response = json!({
+                "type": "response",
+                "request_seq": request["seq"],
+                "command": command,
+                "success": result.is_ok(),
+            });
+            match result {
+                Ok(body) => response["body"] = body,
+                Err(message) => response["message"] = json!(message),
+            }
+            self.send(response)?;
+            for (event, body) in mem::take(&mut self.events) {
+                self.send(json!({"type": "event", "event": event, "body": body}))?;
+            }
+        }
+        Ok(())
+    }
+
+    fn read_message(&mut self) -> io::Result<Option<Value>> {
+        let mut len = None;
+        loop {
+            let mut line = String::new();
+            if self.input.read_line(&mut line)? == 0 {
+                return Ok(None);
+            }
+            let line = line.trim_end();
+            if line.is_empty() {
+                break;
+            }
+            if let Some(value) = line.strip_prefix("Content-Length:") {
+                len = value.trim().parse().ok();
+            }
+        }
+        let len = len.ok_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidData, "missing Content-Length header")
+        })?;
+        let mut body = vec![0; len];
+        self.input.read_exact(&mut body)?;
+        Ok(Some(serde_json::from_slice(&body)?))
+    }
+
+    fn send(&mut self, mut message: Value) -> io::Result<()> {
+        self.seq += 1;
+        message["seq"] = json!(self.seq);
+        let body = message.to_string();
+        write!(
+            self.output,
+            "Content-Length: {}\r\n\r\n{}",
+            body.len(),
+            body
+        )?;
+        self.output.flush()
+    }
+
+    fn handle(&mut self, command: &str, args: &Value) -> Result<Value, String> {
+        match command {
+            "initialize" => Ok(json!({
+                "supportsConfigurationDoneRequest": true,
+                "supportsInstructionBreakpoints": true,
+                "supportsReadMemoryRequest": true,
+                "supportsWriteMemoryRequest": true,
+            })),
+            "launch" => self.launch(args),
+            "setBreakpoints" => self.set_breakpoints(args),
+            "setInstructionBreakpoints" => self.set_instruction_breakpoints(args),
+            "setExceptionBreakpoints" => Ok(json!({"breakpoints": []})),
+            "configurationDone" => {
+                if self.stop_on_entry {
+                    self.stopped("entry", None);
+                    Ok(Value::Null)
+                } else {
+                    self.resume()
+                }
+            }
+            "threads" => Ok(json!({"threads": [{"id": THREAD_ID, "name": "cpu"}]})),
+            "stackTrace" => self.stack_trace(),
+            "scopes" => Ok(json!({"scopes": [{
+                "name": "Registers",
+                "variablesReference": REGISTERS_REF,
+                "expensive": false,
+            }]})),
+            "variables" => self.variables(args),
+            "readMemory" => self.read_memory(args),
+            "writeMemory" => self.write_memory(args),
+            "continue" => self.resume().map(|_| json!({"allThreadsContinued": true})),
+            "next" | "stepIn" | "stepOut" => self.step(),
+            "pause" => Err("pause is not supported, execution is synchronous".to_string()),
+            "disconnect" | "terminate" => {
+                self.done = true;
+                Ok(Value::Null)
+            }
+            _ => Err(format!("unsupported request: {}", command)),
+        }
+    }
+
+    fn launch(&mut self, args: &Value) -> Result<Value, String> {
+        let program = args["program"].as_str().ok_or("missing program")?;
+        let (cpu, assembled) = (self.loader)(Path::new(program))
+            .map_err(|err| format!("failed to load {}: {}", program, err))?;
+        self.source_map = match args.get("sourceMap") {
+            None => assembled,
+            Some(Value::String(path)) => {
+                let text = fs::read_to_string(path)
+                    .map_err(|err| format!("failed to read {}: {}", path, err))?;
+                let json = serde_json::from_str(&text)
+                    .map_err(|err| format!("failed to parse {}: {}", path, err))?;
+                Some(SourceMap::from_json(&json).ok_or("invalid source map")?)
+            }
+            Some(json) => Some(SourceMap::from_json(json).ok_or("invalid source map")?),
+        };
+        self.stop_on_entry = args["stopOnEntry"].as_bool().unwrap_or(false);
+        self.source_breakpoints.clear();
+        self.instruction_breakpoints.clear();
+        self.cpu = Some(cpu);
+        self.events.push(("initialized", json!({})));
+        Ok(Value::Null)
+    }
+
+    fn set_breakpoints(&mut self, args: &Value) -> Result<Value, String> {
+        let cpu = self.cpu.as_mut().ok_or(NOT_LAUNCHED)?;
+        let path = Path::new(args["source"]["path"].as_str().unwrap_or_default());
+        let map = self.source_map.as_ref().filter(|map| map.is_source(path));
+        let lines = args["breakpoints"].as_array().map(Vec::as_slice);
+        let mut addrs = Vec::new();
+        let mut breakpoints = Vec::new();
+        for line in lines
+            .unwrap_or_default()
+            .iter()
+            .map(|bp| bp["line"].as_u64())
+        {
+            let addr = map.zip(line).and_then(|(map, line)| {
+                let line = line.try_into().ok()?;
+                map.address_for(line)
+            });
+            breakpoints.push(match addr {
+                Some(addr) => {
+                    addrs.push(addr);
+                    json!({"verified": true, "line": line, "instructionReference": reference(addr)})
+                }
+                None => json!({"verified": false, "line": line, "message": "no code at this line"}),
+            });
+        }
+        replace_breakpoints(
+            cpu,
+            &mut self.source_breakpoints,
+            addrs,
+            &self.instruction_breakpoints,
+        );
+        Ok(json!({ "breakpoints": breakpoints }))
+    }
+
+    fn set_instruction_breakpoints(&mut self, args: &Value) -> Result<Value, String> {
+        let cpu = self.cpu.as_mut().ok_or(NOT_LAUNCHED)?;
+        let requested = args["breakpoints"].as_array().map(Vec::as_slice);
+        let mut addrs = Vec::new();
+        let mut breakpoints = Vec::new();
+        for bp in requested.unwrap_or_default() {
+            let addr = bp["instructionReference"]
+                .as_str()
+                .and_then(|reference| offset_reference(reference, &bp["offset"]));
+            breakpoints.push(match addr {
+                Some(addr) => {
+                    addrs.push(addr);
+                    json!({"verified": true, "instructionReference": reference(addr)})
+                }
+                None => json!({"verified": false, "message": "invalid address"}),
+            });
+        }
+        replace_breakpoints(
+            cpu,
+            &mut self.instruction_breakpoints,
+            addrs,
+            &self.source_breakpoints,
+        );
+        Ok(json!({ "breakpoints": breakpoints }))
+    }
+
+    fn resume(&mut self) -> Result<Value, String> {
+        let cpu = self.cpu.as_mut().ok_or(NOT_LAUNCHED)?;
+        let result = cpu.run().map(Some);
+        self.stop(result);
+        Ok(Value::Null)
+    }
+
+    fn step(&mut self) -> Result<Value, String> {
+        let cpu = self.cpu.as_mut().ok_or(NOT_LAUNCHED)?;
+        let result = match cpu.step() {
+            // reported breakpoints let the instruction run on the next step
+            Ok(Step::Stopped(StopReason::Breakpoint(_))) => cpu.step(),
+            result => result,
+        };
+        self.stop(result.map(|step| match step {
+            Step::Executed => None,
+            Step::Stopped(reason) => Some(reason),
+        }));
+        Ok(Value::Null)
+    }
+
+    fn stop(&mut self, result: Result<Option<StopReason>, CpuError>) {
+        match result {
+            Ok(None) => self.stopped("step", None),
+            Ok(Some(StopReason::Breakpoint(_))) => self.stopped("breakpoint", None),
+            Ok(Some(StopReason::Watchpoint(_))) => self.stopped("data breakpoint", None),
+            Err(CpuError::Halt) => {
+                self.events.push(("exited", json!({"exitCode": 0})));
+                self.events.push(("terminated", json!({})));
+            }
+            Err(err) => self.stopped("exception", Some(err.to_string())),
+        }
+    }
+
+    fn stopped(&mut self, reason: &str, text: Option<String>) {
+        let mut body = json!({
+            "reason": reason,
+            "threadId": THREAD_ID,
+            "allThreadsStopped": true,
+        });
+        if let Some(text) = text {
+            body["text"] = json!(text);
+        }
+        self.events.push(("stopped", body));
+    }
+
+    fn stack_trace(&self) -> Result<Value, String> {
+        let cpu = self.cpu.as_ref().ok_or(NOT_LAUNCHED)?;
+        let pc = cpu.registers().get_wide(WideRegister::PC);
+        let mut frame = json!({
+            "id": 0,
+            "name": reference(pc),
+            "line": 0,
+            "column": 0,
+            "instructionPointerReference": reference(pc),
+        });
+        if let Some(ref map) = self.source_map {
+            if let Some(line) = map.line_for(pc) {
+                let source = map.source();
+                frame["line"] = json!(line);
+                frame["column"] = json!(1);
+                frame["source"] = json!({
+                    "name": source.file_name().map(|name| name.to_string_lossy()),
+                    "path": source.to_string_lossy(),
+                });
+            }
+        }
+        Ok(json!({"stackFrames": [frame], "totalFrames": 1}))
+    }
+
+    fn variables(&self, args: &Value) -> Result<Value, String> {
+        let cpu = self.cpu.as_ref().ok_or(NOT_LAUNCHED)?;
+        if args["variablesReference"].as_u64() != Some(REGISTERS_REF) {
+            return Err("unknown variables reference".to_string());
+        }
+        let registers = cpu.registers();
+        let mut variables: Vec<Value> = Register::iter()
+            .map(|reg| {
+                json!({
+                    "name": reg.as_str(),
+                    "value": format!("{:#04x}", registers.get(reg)),
+                    "variablesReference": 0,
+                })
+            })
+            .collect();
+        for reg in [WideRegister::PC, WideRegister::SP] {
+            let value = registers.get_wide(reg);
+            variables.push(json!({
+                "name": reg.as_str(),
+                "value": reference(value),
+                "variablesReference": 0,
+                "memoryReference": reference(value),
+            }));
+        }
+        variables.push(json!({
+            "name": "FLAGS",
+            "value": registers.flags().to_string(),
+            "variablesReference": 0,
+        }));
+        Ok(json!({ "variables": variables }))
+    }
+
+    fn read_memory(&self, args: &Value) -> Result<Value, String> {
+        let cpu = self.cpu.as_ref().ok_or(NOT_LAUNCHED)?;
+        let addr = args["memoryReference"]
+            .as_str()
+            .and_then(|reference| offset_reference(reference, &args["offset"]))
+            .ok_or("invalid memory reference")?;
+        let count = args["count"].as_u64().ok_or("missing count")?;
+        // stop at the first unmapped byte
+        let data: Vec<u8> = (addr..=u16::MAX)
+            .take(count as usize)
+            .map_while(|addr| cpu.memory().peek(addr))
+            .collect();
+        Ok(json!({
+            "address": reference(addr),
+            "data": BASE64.encode(&data),
+            "unreadableBytes": count - data.len() as u64,
+        }))
+    }
+
+    fn write_memory(&mut self, args: &Value) -> Result<Value, String> {
+        let cpu = self.cpu.as_mut().ok_or(NOT_LAUNCHED)?;
+        let addr = args["memoryReference"]
+            .as_str()
+            .and_then(|reference| offset_reference(reference, &args["offset"]))
+            .ok_or("invalid memory reference")?;
+        let data = BASE64
+            .decode(args["data"].as_str().unwrap_or_default())
+            .map_err(|err| format!("invalid data: {}", err))?;
+        if data.len() > (u16::MAX - addr) as usize + 1 {
+            return Err("data past end of memory".to_string());
+        }
+        let memory = cpu.memory_mut();
+        for (addr, &byte) in (addr..=u16::MAX).zip(&data) {
+            memory.poke(addr, byte).map_err(|err| err.to_string())?;
+        }
+        Ok(json!({"bytesWritten": data.len()}))
+    }
+}
+
+/// Swap the breakpoints in `current` for `new`, leaving any also in `keep`
+fn replace_breakpoints(cpu: &mut Cpu, current: &mut Vec<u16>, new: Vec<u16>, keep: &[u16]) {
+    for addr in current.drain(..) {
+        if !keep.contains(&addr) {
+            cpu.remove_breakpoint(addr);
+        }
+    }
+    for &addr in &new {
+        cpu.add_breakpoint(addr);
+    }
+    *current = new;
+}
+
+/// Memory and instruction references are addresses in hex
+fn reference(addr: u16) -> String {
+    format!("{:#06x}", addr)
+}
+
+fn offset_reference(reference: &str, offset: &Value) -> Option<u16> {
+    let addr = u16::from_str_radix(reference.strip_prefix("0x")?, 16).ok()?;
+    let offset = offset.as_i64().unwrap_or(0);
+    (addr as i64 + offset).try_into().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cpu::operation::{hlt, mov};
+    use crate::memory::{MemoryMapper, TestDevice};
+
+    fn test_cpu() -> Cpu {
+        let program = [
+            mov::lit_reg::CODE,
+            0xab,
+            Register::C.into(),
+            mov::lit_mem::CODE,
+            0xcd,
+            0x00,
+            0xf0,
+            hlt::CODE,
+        ];
+        let mut mem = TestDevice::new(0x100);
+        mem.write_slice(&program);
+        let mut mapper = MemoryMapper::new();
+        mapper
+            .add_device(Box::new(mem), 0, 0xff)
+            .expect("valid range");
+        Cpu::new(mapper).expect("valid CPU")
+    }
+
+    fn test_map() -> SourceMap {
+        let mut map = SourceMap::new("prog.asm");
+        map.insert(0, 1);
+        map.insert(3, 2);
+        map.insert(7, 3);
+        map
+    }
+
+    /// Run a session of `(command, arguments)` requests, returning all messages sent
+    fn session(requests: &[(&str, Value)]) -> Vec<Value> {
+        let mut input = Vec::new();
+        for (seq, (command, args)) in requests.iter().enumerate() {
+            let body = json!({
+                "seq": seq + 1,
+                "type": "request",
+                "command": command,
+                "arguments": args,
+            })
+            .to_string();
+            write!(input, "Content-Length: {}\r\n\r\n{}", body.len(), body).expect("write");
+        }
+        let mut output = Vec::new();
+        let loader: Loader = Box::new(|path| {
+            let assembled = path.extension().is_some_and(|ext| ext == "asm");
+            Ok((test_cpu(), assembled.then(test_map)))
+        });
+        DapServer::new(input.as_slice(), &mut output, loader)
+            .serve()
+            .expect("session");
+
+        let mut output = output.as_slice();
+        let mut messages = Vec::new();
+        while let Some(start) = output.windows(4).position(|w| w == b"\r\n\r\n") {
+            let header = std::str::from_utf8(&output[..start]).expect("utf8 header");
+            let len: usize = header["Content-Length: ".len()..].parse().expect("length");
+            let body = &output[start + 4..start + 4 + len];
+            messages.push(serde_json::from_slice(body).expect("json message"));
+            output = &output[start + 4 + len..];
+        }
+        messages
+    }
+
+    fn response(messages: &[Value], request_seq: u64) -> &Value {
+        messages
+            .iter()
+            .find(|msg| msg["type"] == "response" && msg["request_seq"] == request_seq)
+            .expect("response")
+    }
+
+    /// Events sent in reply to a request, as `event` or `event:reason`
+    fn events(messages: &[Value], request_seq: u64) -> Vec<String> {
+        messages
+            .iter()
+            .skip_while(|msg| msg["request_seq"] != request_seq)
+            .skip(1)
+            .take_while(|msg| msg["type"] == "event")
+            .map(|msg| match msg["body"]["reason"].as_str() {
+                Some(reason) => format!("{}:{}", msg["event"].as_str().unwrap(), reason),
+                None => msg["event"].as_str().unwrap().to_string(),
+            })
+            .collect()
+    }
+
+    fn launch(args: Value) -> Vec<(&'static str, Value)> {
+        vec![
+            ("initialize", json!({"adapterID": "h8bit"})),
+            ("launch", args),
+        ]
+    }
+
+    #[test]
+    fn instruction_breakpoints_and_stepping() {
+        let mut requests = launch(json!({"program": "prog.bin", "stopOnEntry": true}));
+        requests.extend([
+            (
+                "setInstructionBreakpoints",
+                json!({"breakpoints": [{"instructionReference": "0x0003"}]}),
+            ),
+            ("configurationDone", json!({})),
+            ("continue", json!({"threadId": 1})),
+            ("stackTrace", json!({"threadId": 1})),
+            ("variables", json!({"variablesReference": REGISTERS_REF})),
+            ("next", json!({"threadId": 1})),
+            ("continue", json!({"threadId": 1})),
+        ]);
+        let messages = session(&requests);
+        assert_eq!(vec!["initialized"], events(&messages, 2));
+        assert_eq!(
+            true,
+            response(&messages, 3)["body"]["breakpoints"][0]["verified"]
+        );
+        assert_eq!(vec!["stopped:entry"], events(&messages, 4));
+        assert_eq!(vec!["stopped:breakpoint"], events(&messages, 5));
+        let frame = &response(&messages, 6)["body"]["stackFrames"][0];
+        assert_eq!("0x0003", frame["instructionPointerReference"]);
+        let variables = &response(&messages, 7)["body"]["variables"];
+        assert_eq!("C", variables[2]["name"]);
+        assert_eq!("0xab", variables[2]["value"]);
+        assert_eq!("0x0003", variables[9]["value"]);
+        assert_eq!("---", variables[11]["value"]);
+        assert_eq!(vec!["stopped:step"], events(&messages, 8));
+        assert_eq!(vec!["exited", "terminated"], events(&messages, 9));
+    }
+
+    #[test]
+    fn source_breakpoints() {
+        let source_map = json!({"source": "prog.asm", "lines": [[0, 1], [3, 2], [7, 3]]});
+        let mut requests = launch(json!({"program": "prog.bin", "sourceMap": source_map}));
+        requests.extend([
+            (
+                "setBreakpoints",
+                json!({
+                    "source": {"path": "/src/prog.asm"},
+                    "breakpoints": [{"line": 2}, {"line": 5}],
+                }),
+            ),
+            ("configurationDone", json!({})),
+            ("stackTrace", json!({"threadId": 1})),
+        ]);
+        let messages = session(&requests);
+        let breakpoints = &response(&messages, 3)["body"]["breakpoints"];
+        assert_eq!(true, breakpoints[0]["verified"]);
+        assert_eq!(false, breakpoints[1]["verified"]);
+        assert_eq!(vec!["stopped:breakpoint"], events(&messages, 4));
+        let frame = &response(&messages, 5)["body"]["stackFrames"][0];
+        assert_eq!(2, frame["line"]);
+        assert_eq!("prog.asm", frame["source"]["name"]);
+    }
+
+    #[test]
+    fn assembled_source_map() {
+        let mut requests = launch(json!({"program": "prog.asm"}));
+        requests.extend([
+            (
+                "setBreakpoints",
+                json!({
+                    "source": {"path": "/src/prog.asm"},
+                    "breakpoints": [{"line": 3}],
+                }),
+            ),
+            ("configurationDone", json!({})),
+            ("stackTrace", json!({"threadId": 1})),
+        ]);
+        let messages = session(&requests);
+        let breakpoints = &response(&messages, 3)["body"]["breakpoints"];
+        assert_eq!(true, breakpoints[0]["verified"]);
+        assert_eq!(vec!["stopped:breakpoint"], events(&messages, 4));
+        let frame = &response(&messages, 5)["body"]["stackFrames"][0];
+        assert_eq!(3, frame["line"]);
+    }
+
+    #[test]
+    fn memory_view() {
+        let mut requests = launch(json!({"program": "prog.bin", "stopOnEntry": true}));
+        requests.extend([
+            (
+                "writeMemory",
+                json!({"memoryReference": "0x0080", "data": BASE64.encode([0xab, 0xcd])}),
+            ),
+            (
+                "readMemory",
+                json!({"memoryReference": "0x0080", "offset": 1, "count": 1}),
+            ),
+            (
+                "readMemory",
+                json!({"memoryReference": "0x00ff", "count": 4}),
+            ),
+        ]);
+        let messages = session(&requests);
+        assert_eq!(2, response(&messages, 3)["body"]["bytesWritten"]);
+        let read = &response(&messages, 4)["body"];
+        assert_eq!("0x0081", read["address"]);
+        assert_eq!(BASE64.encode([0xcd]), read["data"]);
+        let read = &response(&messages, 5)["body"];
+        assert_eq!(3, read["unreadableBytes"]);
+    }
+
+    #[test]
+    fn errors() {
+        let messages = session(&[
+            ("threads", json!({})),
+            ("stackTrace", json!({"threadId": 1})),
+            ("evaluate", json!({"expression": "A"})),
+            ("pause", json!({"threadId": 1})),
+        ]);
+        assert_eq!(true, response(&messages, 1)["success"]);
+        assert_eq!(false, response(&messages, 2)["success"]);
+        assert_eq!(NOT_LAUNCHED, response(&messages, 2)["message"]);
+        assert_eq!(false, response(&messages, 3)["success"]);
+        assert_eq!(false, response(&messages, 4)["success"]);
+    }
+
+    #[test]
+    fn disconnect_ends_session() {
+        let messages = session(&[("disconnect", json!({})), ("threads", json!({}))]);
+        assert_eq!(1, messages.len());
+    }
+}
@@ -0,0 +1,546 @@
+use self::packet::{from_hex, to_hex, Connection, Incoming};
+use crate::cpu::{Cpu, Error as CpuError, Flags, Register, Step, StopReason, Watch, WideRegister};
+use crate::memory::AccessKind;
+use std::io;
+use std::net::TcpListener;
+
+mod packet;
+
+/// Cycles to run between checks for an interrupt from the client
+const POLL_CYCLES: u64 = 10_000;
+
+/// Registers in GDB order: `A`..`MB`, then `PC`, `SP` and the flags
+const REGISTER_COUNT: usize = 12;
+
+const TARGET_XML: &str = r#"<?xml version="1.0"?>
+<!DOCTYPE target SYSTEM "gdb-target.dtd">
+<target version="1.0">
+  <feature name="org.h8bit.core">
+    <reg name="a" bitsize="8" regnum="0"/>
+    <reg name="b" bitsize="8"/>
+    <reg name="c" bitsize="8"/>
+    <reg name="d" bitsize="8"/>
+    <reg name="e" bitsize="8"/>
+    <reg name="f" bitsize="8"/>
+    <reg name="g" bitsize="8"/>
+    <reg name="h" bitsize="8"/>
+    <reg name="mb" bitsize="8"/>
+    <reg name="pc" bitsize="16" type="code_ptr"/>
+    <reg name="sp" bitsize="16" type="data_ptr"/>
+    <reg name="flags" bitsize="8"/>
+  </feature>
+</target>
+"#;
+
+// stop replies, signal numbers as GDB expects them
+const SIGINT: &str = "S02";
+const SIGILL: &str = "S04";
+const SIGTRAP: &str = "S05";
+const SIGFPE: &str = "S08";
+const SIGSEGV: &str = "S0b";
+const EXITED: &str = "W00";
+
+// error replies
+const EINVAL: &str = "E16";
+const EFAULT: &str = "E0e";
+
+/// GDB remote serial protocol stub
+///
+/// Serves a single client over TCP. Registers and memory are big-endian, as
+/// described by the target XML sent to the client.
+#[derive(Debug)]
+pub struct GdbStub {
+    cpu: Cpu,
+    last_stop: String,
+}
+
+enum Action {
+    Reply(String),
+    Continue,
+    Detach,
+    Kill,
+}
+
+impl GdbStub {
+    pub fn new(cpu: Cpu) -> Self {
+        Self {
+            cpu,
+            last_stop: SIGTRAP.to_string(),
+        }
+    }
+
+    pub fn cpu(&self) -> &Cpu {
+        &self.cpu
+    }
+
+    pub fn into_inner(self) -> Cpu {
+        self.cpu
+    }
+
+    /// Accept one client and serve it until it detaches or disconnects
+    pub fn serve(&mut self, listener: &TcpListener) -> io::Result<()> {
+        let (stream, _) = listener.accept()?;
+        stream.set_nodelay(true)?;
+        let mut conn = Connection::new(stream);
+        while let Some(incoming) = conn.read()? {
+            let packet = match incoming {
+                Incoming::Packet(packet) => packet,
+                // already stopped
+                Incoming::Interrupt => continue,
+            };
+            let reply = match self.handle(&packet) {
+                Action::Reply(reply) => reply,
+                Action::Continue => self.resume(&mut conn)?,
+                Action::Detach => {
+                    conn.write("OK")?;
+                    break;
+                }
+                Action::Kill => break,
+            };
+            conn.write(&reply)?;
+        }
+        Ok(())
+    }
+
+    fn handle(&mut self, packet: &str) -> Action {
+        let args = packet.get(1..).unwrap_or_default();
+        let reply = match packet.as_bytes().first() {
+            Some(b'?') => Some(self.last_stop.clone()),
+            Some(b'g') => Some(self.read_registers()),
+            Some(b'G') => self.write_registers(args),
+            Some(b'p') => self.read_register(args),
+            Some(b'P') => self.write_register(args),
+            Some(b'm') => self.read_memory(args),
+            Some(b'M') => self.write_memory(args),
+            Some(b's') => self.set_pc(args).map(|_| self.step()),
+            Some(b'c') => match self.set_pc(args) {
+                Some(()) => return Action::Continue,
+                None => None,
+            },
+            Some(b'Z') => self.set_point(args, true),
+            Some(b'z') => self.set_point(args, false),
+            Some(b'q') => Some(query(args)),
+            Some(b'H') => Some("OK".to_string()),
+            Some(b'D') => return Action::Detach,
+            Some(b'k') => return Action::Kill,
+            _ => Some(String::new()),
+        };
+        Action::Reply(reply.unwrap_or_else(|| EINVAL.to_string()))
+    }
+
+    fn step(&mut self) -> String {
+        let result = match self.cpu.step() {
+            // reported breakpoints let the instruction run on the next step
+            Ok(Step::Stopped(StopReason::Breakpoint(_))) => self.cpu.step(),
+            result => result,
+        };
+        self.stop(result.map(|step| match step {
+            Step::Executed => None,
+            Step::Stopped(reason) => Some(reason),
+        }))
+    }
+
+    fn resume(&mut self, conn: &mut Connection) -> io::Result<String> {
+        loop {
+            match self.cpu.run_for_cycles(POLL_CYCLES) {
+                Ok(None) if conn.poll_interrupt()? => {
+                    self.last_stop = SIGINT.to_string();
+                    return Ok(self.last_stop.clone());
+                }
+                Ok(None) => (),
+                result => return Ok(self.stop(result)),
+            }
+        }
+    }
+
+    fn stop(&mut self, result: Result<Option<StopReason>, CpuError>) -> String {
+        self.last_stop = match result {
+            Ok(Some(StopReason::Watchpoint(access))) => {
+                let kind = match access.kind {
+                    AccessKind::Write => "watch",
+                    _ => "rwatch",
+                };
+                format!("T05{}:{:04x};", kind, access.addr)
+            }
+            Ok(_) => SIGTRAP.to_string(),
+            Err(CpuError::Halt) => EXITED.to_string(),
+            Err(CpuError::InvalidRegister(_)) => SIGILL.to_string(),
+            Err(CpuError::DivideByZero) => SIGFPE.to_string(),
+            Err(CpuError::Device(_) | CpuError::OutOfBounds(_)) => SIGSEGV.to_string(),
+            Err(_) => SIGTRAP.to_string(),
+        };
+        self.last_stop.clone()
+    }
+
+    fn set_pc(&mut self, args: &str) -> Option<()> {
+        if !args.is_empty() {
+            let addr = parse_hex(args)?;
+            self.cpu.registers_mut().set_wide(WideRegister::PC, addr);
+        }
+        Some(())
+    }
+
+    fn register_bytes(&self, num: usize) -> Option<Vec<u8>> {
+        let registers = self.cpu.registers();
+        let bytes = match num {
+            0..=8 => vec![registers.get(Register::from_repr(num as u8 + 1)?)],
+            9 => registers.get_wide(WideRegister::PC).to_be_bytes().to_vec(),
+            10 => registers.get_wide(WideRegister::SP).to_be_bytes().to_vec(),
+            11 => vec![registers.flags().bits()],
+            _ => return None,
+        };
+        Some(bytes)
+    }
+
+    /// Set a register from its bytes, returning how many were used
+    fn set_register_bytes(&mut self, num: usize, bytes: &[u8]) -> Option<usize> {
+        let registers = self.cpu.registers_mut();
+        match (num, bytes) {
+            (0..=8, [value, ..]) => {
+                registers.set(Register::from_repr(num as u8 + 1)?, *value);
+                Some(1)
+            }
+            (9 | 10, [high, low, ..]) => {
+                let reg = match num {
+                    9 => WideRegister::PC,
+                    _ => WideRegister::SP,
+                };
+                registers.set_wide(reg, u16::from_be_bytes([*high, *low]));
+                Some(2)
+            }
+            (11, [bits, ..]) => {
+                registers.set_flags(Flags::from_bits(*bits));
+                Some(1)
+            }
+            _ => None,
+        }
+    }
+
+    fn read_registers(&self) -> String {
+        (0..REGISTER_COUNT)
+            .filter_map(|num| self.register_bytes(num))
+            .map(|bytes| to_hex(&bytes))
+            .collect()
+    }
+
+    fn write_registers(&mut self, args: &str) -> Option<String> {
+        let bytes = from_hex(args)?;
+        let mut rest = bytes.as_slice();
+        for num in 0..REGISTER_COUNT {
+            let used = self.set_register_bytes(num, rest)?;
+            rest = &rest[used..];
+        }
+        Some("OK".to_string())
+    }
+
+    fn read_register(&self, args: &str) -> Option<String> {
+        let num = usize::from_str_radix(args, 16).ok()?;
+        self.register_bytes(num).map(|bytes| to_hex(&bytes))
+    }
+
+    fn write_register(&mut self, args: &str) -> Option<String> {
+        let (num, value) = args.split_once('=')?;
+        let num = usize::from_str_radix(num, 16).ok()?;
+        let bytes = from_hex(value)?;
+        match self.set_register_bytes(num, &bytes)? {
+            used if used == bytes.len() => Some("OK".to_string()),
+            _ => None,
+        }
+    }
+
+    fn read_memory(&self, args: &str) -> Option<String> {
+        let (addr, len) = parse_range(args)?;
+        // partial reads are allowed, stop at the first unmapped byte
+        let bytes: Vec<u8> = (0..len)
+            .map_while(|offset| addr.checked_add(offset))
+            .map_while(|addr| self.cpu.memory().peek(addr))
+            .collect();
+        if bytes.is_empty() && len > 0 {
+            Some(EFAULT.to_string())
+        } else {
+            Some(to_hex(&bytes))
+        }
+    }
+
+    fn write_memory(&mut self, args: &str) -> Option<String> {
+        let (range, data) = args.split_once(':')?;
+        let (addr, len) = parse_range(range)?;
+        let bytes = from_hex(data)?;
+        if bytes.len() != len as usize {
+            return None;
+        }
+        let memory = self.cpu.memory_mut();
+        for (addr, &byte) in (addr..=u16::MAX).zip(&bytes) {
+            if memory.poke(addr, byte).is_err() {
+                return Some(EFAULT.to_string());
+            }
+        }
+        Some("OK".to_string())
+    }
+
+    /// Insert or remove a breakpoint (`0`, `1`) or watchpoint (`2`..`4`)
+    fn set_point(&mut self, args: &str, insert: bool) -> Option<String> {
+        let mut parts = args.splitn(3, ',');
+        let kind = parts.next()?;
+        let addr = parse_hex(parts.next()?)?;
+        let len = parse_hex(parts.next()?)?;
+        let watch = match kind {
+            "0" | "1" => {
+                if insert {
+                    self.cpu.add_breakpoint(addr);
+                } else {
+                    self.cpu.remove_breakpoint(addr);
+                }
+                return Some("OK".to_string());
+            }
+            "2" => Watch::Write,
+            "3" => Watch::Read,
+            "4" => Watch::ReadWrite,
+            _ => return Some(String::new()),
+        };
+        let range = addr..=addr.saturating_add(len.max(1) - 1);
+        if insert {
+            self.cpu.add_watchpoint(range, watch);
+        } else {
+            self.cpu.remove_watchpoint(range, watch);
+        }
+        Some("OK".to_string())
+    }
+}
+
+fn query(args: &str) -> String {
+    if args.starts_with("Supported") {
+        return "PacketSize=1000;qXfer:features:read+".to_string();
+    }
+    if let Some(range) = args.strip_prefix("Xfer:features:read:target.xml:") {
+        return match parse_range(range) {
+            Some((offset, len)) => xfer(TARGET_XML, offset as usize, len as usize),
+            None => EINVAL.to_string(),
+        };
+    }
+    match args {
+        "Attached" => "1".to_string(),
+        "fThreadInfo" => "m1".to_string(),
+        "sThreadInfo" => "l".to_string(),
+        _ => String::new(),
+    }
+}
+
+/// Chunk of a document, prefixed with `l` for the last chunk or `m` for more
+fn xfer(document: &str, offset: usize, len: usize) -> String {
+    let start = offset.min(document.len());
+    let end = (start + len).min(document.len());
+    let marker = if end == document.len() { 'l' } else { 'm' };
+    format!("{}{}", marker, &document[start..end])
+}
+
+fn parse_hex(hex: &str) -> Option<u16> {
+    u16::from_str_radix(hex, 16).ok()
+}
+
+/// Parse `addr,len`
+fn parse_range(args: &str) -> Option<(u16, u16)> {
+    let (addr, len) = args.split_once(',')?;
+    Some((parse_hex(addr)?, parse_hex(len)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::packet::{checksum_of, frame};
+    use super::*;
+    use crate::cpu::operation::{hlt, mov};
+    use crate::memory::{Device, MemoryMapper, TestDevice};
+    use std::io::{Read, Write};
+    use std::net::TcpStream;
+    use std::thread;
+
+    const ADDR: u8 = 0xf0;
+
+    struct Client(TcpStream);
+
+    impl Client {
+        fn byte(&mut self) -> u8 {
+            let mut byte = [0];
+            self.0.read_exact(&mut byte).expect("stub reply");
+            byte[0]
+        }
+
+        fn send(&mut self, data: &str) -> String {
+            self.0.write_all(&frame(data)).expect("send packet");
+            assert_eq!(b'+', self.byte(), "ack for {}", data);
+            assert_eq!(b'$', self.byte());
+            let mut reply = Vec::new();
+            loop {
+                match self.byte() {
+                    b'#' => break,
+                    byte => reply.push(byte),
+                }
+            }
+            let checksum = String::from_utf8(vec![self.byte(), self.byte()]).expect("hex");
+            assert_eq!(format!("{:02x}", checksum_of(&reply)), checksum);
+            let reply = String::from_utf8(reply).expect("utf8 reply");
+            self.0.write_all(b"+").expect("send ack");
+            reply
+        }
+    }
+
+    fn test_cpu() -> Cpu {
+        let program = [
+            mov::lit_reg::CODE,
+            0xab,
+            Register::C.into(),
+            mov::lit_mem::CODE,
+            0xcd,
+            0x00,
+            ADDR,
+            hlt::CODE,
+        ];
+        let mut mem = TestDevice::new(0x100);
+        mem.write_slice(&program);
+        let mut mapper = MemoryMapper::new();
+        mapper
+            .add_device(Box::new(mem), 0, 0xff)
+            .expect("valid range");
+        mapper.collect_stats(true);
+        Cpu::new(mapper).expect("valid CPU")
+    }
+
+    /// Serve a scripted client, returning the CPU once it detaches
+    fn session(script: impl FnOnce(&mut Client) + Send + 'static) -> Cpu {
+        let listener = TcpListener::bind(("127.0.0.1", 0)).expect("local port");
+        let addr = listener.local_addr().expect("bound address");
+        let client = thread::spawn(move || {
+            let mut client = Client(TcpStream::connect(addr).expect("connect to stub"));
+            script(&mut client);
+        });
+        let mut stub = GdbStub::new(test_cpu());
+        stub.serve(&listener).expect("session");
+        client.join().expect("client script");
+        stub.into_inner()
+    }
+
+    #[test]
+    fn registers() {
+        let cpu = session(|client| {
+            assert_eq!("000000000000000000000000ff00", client.send("g"));
+            assert_eq!("OK", client.send("P2=12"));
+            assert_eq!("12", client.send("p2"));
+            assert_eq!("OK", client.send("P9=0010"));
+            assert_eq!("0010", client.send("p9"));
+            assert_eq!("OK", client.send("G0102030405060708090020fff005"));
+            assert_eq!("0102030405060708090020fff005", client.send("g"));
+            assert_eq!("05", client.send("pb"));
+            assert_eq!(EINVAL, client.send("p20"));
+            assert_eq!("OK", client.send("D"));
+        });
+        assert_eq!(0x03, cpu.registers().get(Register::C));
+        assert_eq!(0xfff0, cpu.registers().get_wide(WideRegister::SP));
+        assert!(cpu.registers().flags().zero);
+    }
+
+    #[test]
+    fn memory() {
+        let cpu = session(|client| {
+            assert_eq!("10ab0315", client.send("m0,4"));
+            assert_eq!("OK", client.send("M80,2:abcd"));
+            assert_eq!("abcd", client.send("m80,2"));
+            assert_eq!("00", client.send("mff,4"));
+            assert_eq!(EFAULT, client.send("m100,1"));
+            assert_eq!(EFAULT, client.send("M100,1:00"));
+            assert_eq!("OK", client.send("D"));
+        });
+        let stats = cpu.memory().stats().expect("collecting").total();
+        assert_eq!(0, stats.reads, "debugger reads are not counted");
+        assert_eq!(0, stats.writes, "debugger writes are not counted");
+        assert_eq!(0xabcd, cpu.memory().get_wide(0x80).expect("mapped"));
+    }
+
+    #[test]
+    fn single_step() {
+        let cpu = session(|client| {
+            assert_eq!(SIGTRAP, client.send("s"));
+            assert_eq!("0003", client.send("p9"));
+            assert_eq!("ab", client.send("p2"));
+            assert_eq!(SIGTRAP, client.send("?"));
+            assert_eq!("OK", client.send("D"));
+        });
+        assert_eq!(1, cpu.instructions());
+    }
+
+    #[test]
+    fn breakpoint_and_continue() {
+        session(|client| {
+            assert_eq!("OK", client.send("Z0,3,1"));
+            assert_eq!(SIGTRAP, client.send("c"));
+            assert_eq!("0003", client.send("p9"));
+            assert_eq!("OK", client.send("z0,3,1"));
+            assert_eq!(EXITED, client.send("c"));
+            assert_eq!(EXITED, client.send("?"));
+            assert_eq!("OK", client.send("D"));
+        });
+    }
+
+    #[test]
+    fn step_over_breakpoint() {
+        session(|client| {
+            assert_eq!("OK", client.send("Z0,0,1"));
+            assert_eq!(SIGTRAP, client.send("s"));
+            assert_eq!("0003", client.send("p9"));
+            assert_eq!("OK", client.send("D"));
+        });
+    }
+
+    #[test]
+    fn watchpoint() {
+        session(|client| {
+            assert_eq!("OK", client.send("Z2,f0,1"));
+            assert_eq!("T05watch:00f0;", client.send("c"));
+            assert_eq!("0007", client.send("p9"));
+            assert_eq!("OK", client.send("D"));
+        });
+    }
+
+    #[test]
+    fn target_description() {
+        session(|client| {
+            let supported = client.send("qSupported:multiprocess+");
+            assert!(supported.contains("qXfer:features:read+"));
+            let mut xml = String::new();
+            loop {
+                let query = format!("qXfer:features:read:target.xml:{:x},80", xml.len());
+                let chunk = client.send(&query);
+                xml.push_str(&chunk[1..]);
+                if chunk.starts_with('l') {
+                    break;
+                }
+            }
+            assert_eq!(TARGET_XML, xml);
+            assert_eq!("OK", client.send("D"));
+        });
+    }
+
+    #[test]
+    fn unsupported_packet() {
+        session(|client| {
+            assert_eq!("", client.send("vMustReplyEmpty"));
+            assert_eq!("OK", client.send("D"));
+        });
+    }
+
+    #[test]
+    fn bad_checksum_is_nacked() {
+        session(|client| {
+            client.0.write_all(b"$g#00").expect("send packet");
+            assert_eq!(b'-', client.byte());
+            assert_eq!("OK", client.send("D"));
+        });
+    }
+
+    #[test]
+    fn kill_ends_session() {
+        session(|client| {
+            client.0.write_all(&frame("k")).expect("send packet");
+            assert_eq!(b'+', client.byte());
+        });
+    }
+}
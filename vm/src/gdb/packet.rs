@@ -0,0 +1,160 @@
+use std::collections::VecDeque;
+use std::io::{self, Read, Write};
+use std::net::TcpStream;
+
+/// Byte sent by the client to interrupt a running target
+pub const INTERRUPT: u8 = 0x03;
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum Incoming {
+    Packet(String),
+    Interrupt,
+}
+
+/// Framing for the remote serial protocol: `$<data>#<checksum>`
+#[derive(Debug)]
+pub struct Connection {
+    stream: TcpStream,
+    buffer: VecDeque<u8>,
+}
+
+impl Connection {
+    pub fn new(stream: TcpStream) -> Self {
+        Self {
+            stream,
+            buffer: VecDeque::new(),
+        }
+    }
+
+    /// Read and acknowledge the next packet, `None` once the client disconnects
+    pub fn read(&mut self) -> io::Result<Option<Incoming>> {
+        loop {
+            let byte = match self.read_byte()? {
+                Some(byte) => byte,
+                None => return Ok(None),
+            };
+            match byte {
+                INTERRUPT => return Ok(Some(Incoming::Interrupt)),
+                b'$' => (),
+                // acks and line noise
+                _ => continue,
+            }
+            let mut data = Vec::new();
+            loop {
+                match self.read_byte()? {
+                    Some(b'#') => break,
+                    Some(byte) => data.push(byte),
+                    None => return Ok(None),
+                }
+            }
+            let mut checksum = [0; 2];
+            for digit in &mut checksum {
+                *digit = match self.read_byte()? {
+                    Some(byte) => byte,
+                    None => return Ok(None),
+                };
+            }
+            let valid = std::str::from_utf8(&checksum)
+                .ok()
+                .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+                == Some(checksum_of(&data));
+            if !valid {
+                self.stream.write_all(b"-")?;
+                continue;
+            }
+            self.stream.write_all(b"+")?;
+            return Ok(Some(Incoming::Packet(
+                String::from_utf8_lossy(&data).into_owned(),
+            )));
+        }
+    }
+
+    pub fn write(&mut self, data: &str) -> io::Result<()> {
+        self.stream.write_all(&frame(data))?;
+        self.stream.flush()
+    }
+
+    /// Check for an interrupt without blocking, discarding anything else
+    pub fn poll_interrupt(&mut self) -> io::Result<bool> {
+        self.stream.set_nonblocking(true)?;
+        let mut buf = [0; 64];
+        let read = loop {
+            match self.stream.read(&mut buf) {
+                Ok(n) => break Ok(n),
+                Err(err) if err.kind() == io::ErrorKind::WouldBlock => break Ok(0),
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+                Err(err) => break Err(err),
+            }
+        };
+        self.stream.set_nonblocking(false)?;
+        self.buffer.extend(&buf[..read?]);
+        match self.buffer.iter().position(|&byte| byte == INTERRUPT) {
+            Some(index) => {
+                self.buffer.drain(..=index);
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+
+    fn read_byte(&mut self) -> io::Result<Option<u8>> {
+        if let Some(byte) = self.buffer.pop_front() {
+            return Ok(Some(byte));
+        }
+        let mut byte = [0];
+        loop {
+            match self.stream.read(&mut byte) {
+                Ok(0) => return Ok(None),
+                Ok(_) => return Ok(Some(byte[0])),
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+                Err(err) => return Err(err),
+            }
+        }
+    }
+}
+
+pub fn checksum_of(data: &[u8]) -> u8 {
+    data.iter().fold(0, |sum, &byte| sum.wrapping_add(byte))
+}
+
+pub fn frame(data: &str) -> Vec<u8> {
+    format!("${}#{:02x}", data, checksum_of(data.as_bytes())).into_bytes()
+}
+
+pub fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+pub fn from_hex(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn frame_packet() {
+        assert_eq!(b"$OK#9a".to_vec(), frame("OK"));
+        assert_eq!(b"$#00".to_vec(), frame(""));
+    }
+
+    #[test]
+    fn hex_round_trip() {
+        let bytes = [0x00, 0xab, 0xff];
+        assert_eq!("00abff", to_hex(&bytes));
+        assert_eq!(Some(bytes.to_vec()), from_hex("00abff"));
+    }
+
+    #[test]
+    fn from_hex_invalid() {
+        assert_eq!(None, from_hex("abc"));
+        assert_eq!(None, from_hex("zz"));
+    }
+}
@@ -0,0 +1,232 @@
+use super::{address, decode_hex, encode_hex, sum, Image, ParseError, ParseErrorKind};
+
+const DATA: u8 = 0x00;
+const END_OF_FILE: u8 = 0x01;
+const EXTENDED_SEGMENT: u8 = 0x02;
+const START_SEGMENT: u8 = 0x03;
+const EXTENDED_LINEAR: u8 = 0x04;
+const START_LINEAR: u8 = 0x05;
+
+/// Parse an Intel HEX file
+///
+/// Extended address records are accepted as long as every address fits in
+/// 16 bits.
+pub fn parse_ihex(text: &str) -> Result<Image, ParseError> {
+    let mut image = Image::default();
+    let mut base: u32 = 0;
+    let mut ended = false;
+    let mut last_line = 0;
+    for (index, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        last_line = index + 1;
+        let err = |kind| ParseError {
+            line: index + 1,
+            kind,
+        };
+        if ended {
+            return Err(err(ParseErrorKind::AfterEnd));
+        }
+        let (kind, offset, data) = parse_record(line).map_err(err)?;
+        match (kind, data.as_slice()) {
+            (DATA, data) => {
+                let addr = base
+                    .checked_add(offset as u32)
+                    .ok_or(ParseErrorKind::Address(u32::MAX))
+                    .and_then(|addr| address(addr, data.len()))
+                    .map_err(err)?;
+                image.push(addr, data);
+            }
+            (END_OF_FILE, []) => ended = true,
+            (EXTENDED_SEGMENT, [high, low]) => {
+                base = (u16::from_be_bytes([*high, *low]) as u32) << 4
+            }
+            (EXTENDED_LINEAR, [high, low]) => {
+                base = (u16::from_be_bytes([*high, *low]) as u32) << 16
+            }
+            (START_SEGMENT, [cs_high, cs_low, ip_high, ip_low]) => {
+                let cs = u16::from_be_bytes([*cs_high, *cs_low]) as u32;
+                let ip = u16::from_be_bytes([*ip_high, *ip_low]) as u32;
+                image.entry = Some(address((cs << 4) + ip, 0).map_err(err)?);
+            }
+            (START_LINEAR, [a, b, c, d]) => {
+                let entry = u32::from_be_bytes([*a, *b, *c, *d]);
+                image.entry = Some(address(entry, 0).map_err(err)?);
+            }
+            (END_OF_FILE, _) => return Err(err(ParseErrorKind::Malformed("end of file"))),
+            (EXTENDED_SEGMENT | EXTENDED_LINEAR, _) => {
+                return Err(err(ParseErrorKind::Malformed("extended address")))
+            }
+            (START_SEGMENT | START_LINEAR, _) => {
+                return Err(err(ParseErrorKind::Malformed("start address")))
+            }
+            (kind, _) => return Err(err(ParseErrorKind::RecordType(kind))),
+        }
+    }
+    if !ended {
+        return Err(ParseError {
+            line: last_line,
+            kind: ParseErrorKind::MissingEnd,
+        });
+    }
+    Ok(image)
+}
+
+/// Write an image as Intel HEX, 16 data bytes per record
+pub fn write_ihex(image: &Image) -> String {
+    let mut out = String::new();
+    for (addr, data) in image.records() {
+        out.push_str(&record(DATA, addr, data));
+    }
+    if let Some(entry) = image.entry {
+        out.push_str(&record(START_LINEAR, 0, &(entry as u32).to_be_bytes()));
+    }
+    out.push_str(&record(END_OF_FILE, 0, &[]));
+    out
+}
+
+/// Parse `:LLAAAATT[DD..]CC` into record type, address and data
+fn parse_record(line: &str) -> Result<(u8, u16, Vec<u8>), ParseErrorKind> {
+    let hex = line
+        .strip_prefix(':')
+        .ok_or(ParseErrorKind::StartCode(':'))?;
+    let bytes = decode_hex(hex).ok_or(ParseErrorKind::InvalidHex)?;
+    if bytes.len() < 5 {
+        return Err(ParseErrorKind::TooShort);
+    }
+    let count = bytes[0] as usize;
+    if bytes.len() != count + 5 {
+        return Err(ParseErrorKind::Length {
+            expected: count,
+            actual: bytes.len() - 5,
+        });
+    }
+    let (body, checksum) = bytes.split_at(bytes.len() - 1);
+    let expected = sum(body).wrapping_neg();
+    if checksum[0] != expected {
+        return Err(ParseErrorKind::Checksum {
+            expected,
+            found: checksum[0],
+        });
+    }
+    let offset = u16::from_be_bytes([body[1], body[2]]);
+    Ok((body[3], offset, body[4..].to_vec()))
+}
+
+fn record(kind: u8, addr: u16, data: &[u8]) -> String {
+    let mut bytes = vec![data.len() as u8];
+    bytes.extend_from_slice(&addr.to_be_bytes());
+    bytes.push(kind);
+    bytes.extend_from_slice(data);
+    bytes.push(sum(&bytes).wrapping_neg());
+    format!(":{}\n", encode_hex(&bytes))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::image::Block;
+
+    fn kind_of(text: &str) -> ParseErrorKind {
+        parse_ihex(text).expect_err("invalid file").kind
+    }
+
+    #[test]
+    fn parse_data() {
+        let image = parse_ihex(":0300300002337A1E\n:00000001FF\n").expect("valid file");
+        let expected = vec![Block {
+            addr: 0x30,
+            data: vec![0x02, 0x33, 0x7a],
+        }];
+        assert_eq!(expected, image.blocks);
+        assert_eq!(None, image.entry);
+    }
+
+    #[test]
+    fn parse_extended_address() {
+        let text = ":020000020100FB\n:01000000AB54\n:00000001FF\n";
+        let image = parse_ihex(text).expect("valid file");
+        assert_eq!(0x1000, image.blocks[0].addr);
+
+        let text = ":020000040001F9\n:01000000AB54\n:00000001FF\n";
+        let err = parse_ihex(text).expect_err("above 16 bits");
+        assert_eq!(
+            ParseError {
+                line: 2,
+                kind: ParseErrorKind::Address(0x10000)
+            },
+            err
+        );
+    }
+
+    #[test]
+    fn write_round_trip() {
+        let mut image = Image::default();
+        image.push(0xfff0, &(0..0x10).collect::<Vec<u8>>());
+        image.push(0x0100, &(0..0x14).collect::<Vec<u8>>());
+        image.entry = Some(0x0100);
+        let text = write_ihex(&image);
+        assert_eq!(5, text.lines().count());
+        assert!(text.ends_with(":00000001FF\n"));
+        assert_eq!(image, parse_ihex(&text).expect("valid file"));
+    }
+
+    #[test]
+    fn error_line_numbers() {
+        let text = ":0300300002337A1E\n\n:0300300002337A1F\n:00000001FF\n";
+        let err = parse_ihex(text).expect_err("bad checksum");
+        assert_eq!(3, err.line);
+        assert_eq!(
+            ParseErrorKind::Checksum {
+                expected: 0x1e,
+                found: 0x1f
+            },
+            err.kind
+        );
+    }
+
+    #[test]
+    fn malformed_records() {
+        assert_eq!(ParseErrorKind::StartCode(':'), kind_of("0300300002337A1E"));
+        assert_eq!(ParseErrorKind::InvalidHex, kind_of(":03003000023G7A1E"));
+        assert_eq!(ParseErrorKind::TooShort, kind_of(":0000"));
+        assert_eq!(
+            ParseErrorKind::Length {
+                expected: 4,
+                actual: 3
+            },
+            kind_of(":0400300002337A1D")
+        );
+        assert_eq!(ParseErrorKind::RecordType(6), kind_of(":00000006FA"));
+        assert_eq!(
+            ParseErrorKind::Malformed("end of file"),
+            kind_of(":01000001AB53")
+        );
+        assert_eq!(
+            ParseErrorKind::Address(u32::MAX),
+            kind_of(":02000004FFFFFC\n:02FFFF000102FD\n")
+        );
+    }
+
+    #[test]
+    fn end_of_file() {
+        let err = parse_ihex(":0300300002337A1E\n").expect_err("no end");
+        assert_eq!(
+            ParseError {
+                line: 1,
+                kind: ParseErrorKind::MissingEnd
+            },
+            err
+        );
+        let err = parse_ihex(":00000001FF\n:0300300002337A1E\n").expect_err("after end");
+        assert_eq!(
+            ParseError {
+                line: 2,
+                kind: ParseErrorKind::AfterEnd
+            },
+            err
+        );
+    }
+}
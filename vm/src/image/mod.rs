@@ -0,0 +1,207 @@
+use crate::memory::{Device, DeviceError};
+use std::ops::RangeInclusive;
+
+pub use ihex::{parse_ihex, write_ihex};
+pub use srec::{parse_srec, write_srec};
+
+mod ihex;
+mod srec;
+
+/// Data bytes per record when writing
+const RECORD_SIZE: usize = 16;
+
+/// Memory contents read from or written to a ROM image file
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Image {
+    pub blocks: Vec<Block>,
+    /// Start address, if the file has one
+    pub entry: Option<u16>,
+}
+
+/// Contiguous bytes starting at `addr`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Block {
+    pub addr: u16,
+    pub data: Vec<u8>,
+}
+
+#[derive(Debug, thiserror::Error, PartialEq, Eq)]
+#[error("line {line}: {kind}")]
+pub struct ParseError {
+    pub line: usize,
+    pub kind: ParseErrorKind,
+}
+
+#[derive(Debug, thiserror::Error, PartialEq, Eq)]
+pub enum ParseErrorKind {
+    #[error("record must start with '{0}'")]
+    StartCode(char),
+    #[error("invalid hex digits")]
+    InvalidHex,
+    #[error("record too short")]
+    TooShort,
+    #[error("byte count {expected} does not match {actual} bytes in record")]
+    Length { expected: usize, actual: usize },
+    #[error("checksum {found:#04x} should be {expected:#04x}")]
+    Checksum { expected: u8, found: u8 },
+    #[error("unknown record type {0}")]
+    RecordType(u8),
+    #[error("malformed {0} record")]
+    Malformed(&'static str),
+    #[error("address {0:#x} out of range")]
+    Address(u32),
+    #[error("record count {expected} does not match {actual} data records")]
+    RecordCount { expected: u32, actual: u32 },
+    #[error("record after end of file")]
+    AfterEnd,
+    #[error("missing end of file record")]
+    MissingEnd,
+}
+
+impl Image {
+    /// Read `range` from a device as a single block
+    pub fn from_device<D: Device + ?Sized>(
+        device: &D,
+        range: RangeInclusive<u16>,
+    ) -> Result<Self, DeviceError> {
+        let addr = *range.start();
+        let data = range
+            .map(|addr| device.get(addr))
+            .collect::<Result<_, _>>()?;
+        Ok(Self {
+            blocks: vec![Block { addr, data }],
+            entry: None,
+        })
+    }
+
+    /// Write every block into a device such as a [`MemoryMapper`] or [`DynMem`]
+    ///
+    /// [`MemoryMapper`]: crate::memory::MemoryMapper
+    /// [`DynMem`]: crate::memory::DynMem
+    pub fn load_into<D: Device + ?Sized>(&self, device: &mut D) -> Result<(), DeviceError> {
+        for block in &self.blocks {
+            for (addr, &byte) in (block.addr..=u16::MAX).zip(&block.data) {
+                device.set(addr, byte)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Add data, extending the last block if it is contiguous
+    fn push(&mut self, addr: u16, data: &[u8]) {
+        match self.blocks.last_mut() {
+            Some(last) if last.addr as usize + last.data.len() == addr as usize => {
+                last.data.extend_from_slice(data)
+            }
+            _ => self.blocks.push(Block {
+                addr,
+                data: data.to_vec(),
+            }),
+        }
+    }
+
+    /// Records of at most [`RECORD_SIZE`] bytes as `(address, data)`
+    fn records(&self) -> impl Iterator<Item = (u16, &[u8])> {
+        self.blocks.iter().flat_map(|block| {
+            block
+                .data
+                .chunks(RECORD_SIZE)
+                .enumerate()
+                .map(move |(i, chunk)| (block.addr + (i * RECORD_SIZE) as u16, chunk))
+        })
+    }
+}
+
+/// Check `len` bytes from `addr` fit in the 16 bit address space
+fn address(addr: u32, len: usize) -> Result<u16, ParseErrorKind> {
+    let last = addr
+        .checked_add(len.saturating_sub(1) as u32)
+        .ok_or(ParseErrorKind::Address(u32::MAX))?;
+    match u16::try_from(last) {
+        Ok(_) => Ok(addr as u16),
+        Err(_) => Err(ParseErrorKind::Address(last)),
+    }
+}
+
+fn decode_hex(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+fn encode_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02X}", byte)).collect()
+}
+
+fn sum(bytes: &[u8]) -> u8 {
+    bytes.iter().fold(0, |sum, &byte| sum.wrapping_add(byte))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memory::{DynMem, MemoryMapper};
+
+    #[test]
+    fn push_merges_contiguous() {
+        let mut image = Image::default();
+        image.push(0x10, &[1, 2]);
+        image.push(0x12, &[3]);
+        image.push(0x20, &[4]);
+        let expected = vec![
+            Block {
+                addr: 0x10,
+                data: vec![1, 2, 3],
+            },
+            Block {
+                addr: 0x20,
+                data: vec![4],
+            },
+        ];
+        assert_eq!(expected, image.blocks);
+    }
+
+    #[test]
+    fn load_into_mapper_and_back() {
+        let mut image = Image::default();
+        image.push(0x0e, &[0xab, 0xcd, 0xef]);
+        let mut mapper = MemoryMapper::new();
+        mapper
+            .add_device(Box::new(DynMem::new(0x10)), 0, 0x0f)
+            .expect("valid range");
+        mapper
+            .add_device(Box::new(DynMem::new(0x10)), 0x10, 0x1f)
+            .expect("valid range");
+        image.load_into(&mut mapper).expect("mapped");
+        let actual = Image::from_device(&mapper, 0x0e..=0x10).expect("mapped");
+        assert_eq!(image, actual);
+    }
+
+    #[test]
+    fn load_into_out_of_bounds() {
+        let mut image = Image::default();
+        image.push(0x0f, &[1, 2]);
+        let mut mem = DynMem::new(0x10);
+        let err = image.load_into(&mut mem).expect_err("too small");
+        assert!(matches!(err, DeviceError::OutOfBounds(0x10)));
+    }
+
+    #[test]
+    fn records_split_blocks() {
+        let mut image = Image::default();
+        image.push(0xffe0, &[0; 0x20]);
+        let addrs: Vec<u16> = image.records().map(|(addr, _)| addr).collect();
+        assert_eq!(vec![0xffe0, 0xfff0], addrs);
+    }
+
+    #[test]
+    fn address_range() {
+        assert_eq!(Ok(0xfff0), address(0xfff0, 0x10));
+        assert_eq!(Err(ParseErrorKind::Address(0x10000)), address(0xfff1, 0x10));
+        assert_eq!(Ok(0xffff), address(0xffff, 0));
+    }
+}
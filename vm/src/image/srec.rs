@@ -0,0 +1,229 @@
+use super::{address, decode_hex, encode_hex, sum, Image, ParseError, ParseErrorKind};
+
+/// Parse a Motorola S-record file
+///
+/// `S0` headers are ignored and `S5`/`S6` record counts are checked. Every
+/// address must fit in 16 bits.
+pub fn parse_srec(text: &str) -> Result<Image, ParseError> {
+    let mut image = Image::default();
+    let mut data_records = 0;
+    let mut ended = false;
+    let mut last_line = 0;
+    for (index, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        last_line = index + 1;
+        let err = |kind| ParseError {
+            line: index + 1,
+            kind,
+        };
+        if ended {
+            return Err(err(ParseErrorKind::AfterEnd));
+        }
+        let (kind, addr, data) = parse_record(line).map_err(err)?;
+        match kind {
+            0 => (),
+            1..=3 => {
+                image.push(address(addr, data.len()).map_err(err)?, &data);
+                data_records += 1;
+            }
+            5 | 6 if addr != data_records => {
+                return Err(err(ParseErrorKind::RecordCount {
+                    expected: addr,
+                    actual: data_records,
+                }))
+            }
+            5 | 6 => (),
+            _ => {
+                image.entry = Some(address(addr, 0).map_err(err)?);
+                ended = true;
+            }
+        }
+    }
+    if !ended {
+        return Err(ParseError {
+            line: last_line,
+            kind: ParseErrorKind::MissingEnd,
+        });
+    }
+    Ok(image)
+}
+
+/// Write an image as `S1` records with an `S5` count and `S9` terminator
+pub fn write_srec(image: &Image) -> String {
+    let mut out = record(0, 0, &[]);
+    let mut count: u16 = 0;
+    for (addr, data) in image.records() {
+        out.push_str(&record(1, addr, data));
+        count = count.wrapping_add(1);
+    }
+    out.push_str(&record(5, count, &[]));
+    out.push_str(&record(9, image.entry.unwrap_or(0), &[]));
+    out
+}
+
+/// Parse `S<type><count><address><data><checksum>`
+fn parse_record(line: &str) -> Result<(u8, u32, Vec<u8>), ParseErrorKind> {
+    let rest = line
+        .strip_prefix('S')
+        .ok_or(ParseErrorKind::StartCode('S'))?;
+    let kind = rest
+        .get(..1)
+        .and_then(|kind| kind.parse().ok())
+        .ok_or(ParseErrorKind::InvalidHex)?;
+    let bytes = decode_hex(&rest[1..]).ok_or(ParseErrorKind::InvalidHex)?;
+    let addr_len = match kind {
+        0 | 1 | 5 | 9 => 2,
+        2 | 6 | 8 => 3,
+        3 | 7 => 4,
+        kind => return Err(ParseErrorKind::RecordType(kind)),
+    };
+    if bytes.len() < addr_len + 2 {
+        return Err(ParseErrorKind::TooShort);
+    }
+    let count = bytes[0] as usize;
+    if bytes.len() != count + 1 {
+        return Err(ParseErrorKind::Length {
+            expected: count,
+            actual: bytes.len() - 1,
+        });
+    }
+    let (body, checksum) = bytes.split_at(bytes.len() - 1);
+    let expected = !sum(body);
+    if checksum[0] != expected {
+        return Err(ParseErrorKind::Checksum {
+            expected,
+            found: checksum[0],
+        });
+    }
+    let addr = body[1..=addr_len]
+        .iter()
+        .fold(0, |addr, &byte| addr << 8 | byte as u32);
+    let data = body[addr_len + 1..].to_vec();
+    if matches!(kind, 5..=9) && !data.is_empty() {
+        return Err(ParseErrorKind::Malformed("count or termination"));
+    }
+    Ok((kind, addr, data))
+}
+
+fn record(kind: u8, addr: u16, data: &[u8]) -> String {
+    let mut bytes = vec![(data.len() + 3) as u8];
+    bytes.extend_from_slice(&addr.to_be_bytes());
+    bytes.extend_from_slice(data);
+    bytes.push(!sum(&bytes));
+    format!("S{}{}\n", kind, encode_hex(&bytes))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EXAMPLE: &str = "\
+S00F000068656C6C6F202020202000003C
+S11F00007C0802A6900100049421FFF07C6C1B787C8C23783C6000003863000026
+S11F001C4BFFFFE5398000007D83637880010014382100107C0803A64E800020E9
+S111003848656C6C6F20776F726C642E0A0042
+S5030003F9
+S9030000FC
+";
+
+    fn kind_of(text: &str) -> ParseErrorKind {
+        parse_srec(text).expect_err("invalid file").kind
+    }
+
+    #[test]
+    fn parse_example() {
+        let image = parse_srec(EXAMPLE).expect("valid file");
+        assert_eq!(1, image.blocks.len());
+        assert_eq!(0, image.blocks[0].addr);
+        assert_eq!(0x46, image.blocks[0].data.len());
+        assert_eq!(b"Hello world.\n\0", &image.blocks[0].data[0x38..]);
+        assert_eq!(Some(0), image.entry);
+    }
+
+    #[test]
+    fn write_round_trip() {
+        let mut image = Image::default();
+        image.push(0x0100, &(0..0x24).collect::<Vec<u8>>());
+        image.entry = Some(0x0100);
+        let text = write_srec(&image);
+        assert!(text.starts_with("S0030000FC\n"));
+        assert!(text.ends_with("S9030100FB\n"));
+        assert_eq!(image, parse_srec(&text).expect("valid file"));
+    }
+
+    #[test]
+    fn wide_addresses() {
+        let text = "S2050000F0AB5F\nS804000000FB\n";
+        let image = parse_srec(text).expect("valid file");
+        assert_eq!(0xf0, image.blocks[0].addr);
+
+        let err = parse_srec("S2050100F0AB5E\nS804000000FB\n").expect_err("above 16 bits");
+        assert_eq!(
+            ParseError {
+                line: 1,
+                kind: ParseErrorKind::Address(0x100f0)
+            },
+            err
+        );
+    }
+
+    #[test]
+    fn record_count_mismatch() {
+        let text = "S1040000AB50\nS5030002FA\nS9030000FC\n";
+        let err = parse_srec(text).expect_err("wrong count");
+        assert_eq!(
+            ParseError {
+                line: 2,
+                kind: ParseErrorKind::RecordCount {
+                    expected: 2,
+                    actual: 1
+                }
+            },
+            err
+        );
+    }
+
+    #[test]
+    fn malformed_records() {
+        assert_eq!(ParseErrorKind::StartCode('S'), kind_of(":0300300002337A1E"));
+        assert_eq!(ParseErrorKind::RecordType(4), kind_of("S4030000FC"));
+        assert_eq!(ParseErrorKind::InvalidHex, kind_of("SX030000FC"));
+        assert_eq!(ParseErrorKind::TooShort, kind_of("S10200FD"));
+        assert_eq!(
+            ParseErrorKind::Length {
+                expected: 4,
+                actual: 3
+            },
+            kind_of("S1040000FC")
+        );
+        assert_eq!(
+            ParseErrorKind::Checksum {
+                expected: 0xfc,
+                found: 0xfd
+            },
+            kind_of("S9030000FD")
+        );
+        assert_eq!(
+            ParseErrorKind::Address(u32::MAX),
+            kind_of("S307FFFFFFFF0102F9")
+        );
+    }
+
+    #[test]
+    fn end_of_file() {
+        let err = parse_srec("S1040000AB50\n").expect_err("no end");
+        assert_eq!(
+            ParseError {
+                line: 1,
+                kind: ParseErrorKind::MissingEnd
+            },
+            err
+        );
+        let err = parse_srec("S9030000FC\nS1040000AB50\n").expect_err("after end");
+        assert_eq!(2, err.line);
+        assert_eq!(ParseErrorKind::AfterEnd, err.kind);
+    }
+}
@@ -1,3 +1,11 @@
 pub mod cpu;
+pub mod dap;
+pub mod gdb;
+pub mod image;
+pub mod link;
 pub mod memory;
+pub mod object;
+pub mod source_map;
 pub mod util;
+
+pub use h8bit_macros::h8asm;
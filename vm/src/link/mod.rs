@@ -0,0 +1,430 @@
+use crate::object::{Object, ObjectError, Section, Symbol};
+use crate::util::high_and_low_value;
+use std::collections::HashMap;
+
+pub use script::{MapRegion, MemoryMap, Placement, ScriptError};
+
+mod script;
+
+/// Combines relocatable objects into a single executable [`Object`]
+#[derive(Debug, Default)]
+pub struct Linker {
+    modules: Vec<Module>,
+}
+
+#[derive(Debug)]
+struct Module {
+    name: String,
+    object: Object,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum LinkError {
+    #[error("{module}: {source}")]
+    Object {
+        module: String,
+        #[source]
+        source: ObjectError,
+    },
+    #[error("{module}: section {section} has no placement in the memory map")]
+    Unplaced { module: String, section: String },
+    #[error("unknown region {0}")]
+    UnknownRegion(String),
+    #[error("{module}: section {section} does not fit in region {region}")]
+    RegionFull {
+        module: String,
+        section: String,
+        region: String,
+    },
+    #[error("{}", join_lines(.0))]
+    Symbols(Vec<SymbolError>),
+    #[error("entry symbol {0} is not a global symbol")]
+    Entry(String),
+}
+
+#[derive(Debug, thiserror::Error, PartialEq, Eq)]
+pub enum SymbolError {
+    #[error("duplicate symbol {name}: defined in {first} and {second}")]
+    Duplicate {
+        name: String,
+        first: String,
+        second: String,
+    },
+    #[error("{module}: undefined symbol {name}")]
+    Undefined { name: String, module: String },
+}
+
+/// Output section index and offset of each input section, by module
+type Placements = Vec<Vec<(usize, u16)>>;
+
+impl Linker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add an object, `name` is used in diagnostics
+    pub fn add(&mut self, name: impl Into<String>, object: Object) {
+        self.modules.push(Module {
+            name: name.into(),
+            object,
+        });
+    }
+
+    /// Lay out every section and resolve symbols into a single executable
+    ///
+    /// Relocations are applied, so the output has none. All defined symbols
+    /// are kept for debuggers.
+    pub fn link(&self, map: &MemoryMap) -> Result<Object, LinkError> {
+        for module in &self.modules {
+            module
+                .object
+                .validate()
+                .map_err(|source| LinkError::Object {
+                    module: module.name.clone(),
+                    source,
+                })?;
+        }
+        let (mut sections, placed) = self.layout(map)?;
+        let address = |module: usize, symbol: &Symbol| {
+            let (index, offset) = placed[module][symbol.section? as usize];
+            Some(
+                sections[index]
+                    .addr
+                    .wrapping_add(offset)
+                    .wrapping_add(symbol.offset),
+            )
+        };
+
+        let mut errors = Vec::new();
+        let mut globals: HashMap<&str, (usize, u16)> = HashMap::new();
+        for (index, module) in self.modules.iter().enumerate() {
+            for symbol in module.object.symbols.iter().filter(|sym| sym.global) {
+                let Some(addr) = address(index, symbol) else {
+                    continue;
+                };
+                match globals.get(symbol.name.as_str()) {
+                    Some(&(first, _)) => errors.push(SymbolError::Duplicate {
+                        name: symbol.name.clone(),
+                        first: self.modules[first].name.clone(),
+                        second: module.name.clone(),
+                    }),
+                    None => {
+                        globals.insert(&symbol.name, (index, addr));
+                    }
+                }
+            }
+        }
+        let mut resolved = Vec::new();
+        for (index, module) in self.modules.iter().enumerate() {
+            let addrs: Vec<u16> = module
+                .object
+                .symbols
+                .iter()
+                .map(|symbol| {
+                    address(index, symbol)
+                        .or_else(|| globals.get(symbol.name.as_str()).map(|&(_, addr)| addr))
+                        .unwrap_or_else(|| {
+                            errors.push(SymbolError::Undefined {
+                                name: symbol.name.clone(),
+                                module: module.name.clone(),
+                            });
+                            0
+                        })
+                })
+                .collect();
+            resolved.push(addrs);
+        }
+        if !errors.is_empty() {
+            return Err(LinkError::Symbols(errors));
+        }
+
+        let mut symbols = Vec::new();
+        for (index, module) in self.modules.iter().enumerate() {
+            for reloc in &module.object.relocations {
+                let addr = resolved[index][reloc.symbol as usize].wrapping_add_signed(reloc.addend);
+                let (section, offset) = placed[index][reloc.section as usize];
+                let at = (offset + reloc.offset) as usize;
+                let (high, low) = high_and_low_value(addr);
+                sections[section].data[at] = high;
+                sections[section].data[at + 1] = low;
+            }
+            for symbol in &module.object.symbols {
+                if let Some(section) = symbol.section {
+                    let (section, offset) = placed[index][section as usize];
+                    symbols.push(Symbol {
+                        name: symbol.name.clone(),
+                        section: Some(section as u16),
+                        offset: offset + symbol.offset,
+                        global: symbol.global,
+                    });
+                }
+            }
+        }
+        let entry = match map.entry {
+            Some(ref name) => match globals.get(name.as_str()) {
+                Some(&(_, addr)) => Some(addr),
+                None => return Err(LinkError::Entry(name.clone())),
+            },
+            None => None,
+        };
+        Ok(Object {
+            sections,
+            symbols,
+            relocations: Vec::new(),
+            entry,
+        })
+    }
+
+    /// Merge input sections of the same name, packed into their regions
+    fn layout(&self, map: &MemoryMap) -> Result<(Vec<Section>, Placements), LinkError> {
+        for module in &self.modules {
+            for section in &module.object.sections {
+                if !map.placements.iter().any(|p| p.section == section.name) {
+                    return Err(LinkError::Unplaced {
+                        module: module.name.clone(),
+                        section: section.name.clone(),
+                    });
+                }
+            }
+        }
+        let mut cursors: HashMap<&str, u32> = map
+            .regions
+            .iter()
+            .map(|region| (region.name.as_str(), region.start as u32))
+            .collect();
+        let mut sections = Vec::new();
+        let mut placed: Placements = self
+            .modules
+            .iter()
+            .map(|module| vec![(0, 0); module.object.sections.len()])
+            .collect();
+        for placement in &map.placements {
+            let region = map
+                .region(&placement.region)
+                .ok_or_else(|| LinkError::UnknownRegion(placement.region.clone()))?;
+            let start = cursors[region.name.as_str()];
+            let mut data = Vec::new();
+            let mut kind = None;
+            for (module_index, module) in self.modules.iter().enumerate() {
+                let inputs = module.object.sections.iter().enumerate();
+                for (index, section) in inputs.filter(|(_, s)| s.name == placement.section) {
+                    let end = start + (data.len() + section.data.len()) as u32;
+                    if end > region.end as u32 + 1 {
+                        return Err(LinkError::RegionFull {
+                            module: module.name.clone(),
+                            section: section.name.clone(),
+                            region: region.name.clone(),
+                        });
+                    }
+                    placed[module_index][index] = (sections.len(), data.len() as u16);
+                    data.extend_from_slice(&section.data);
+                    kind.get_or_insert(section.kind);
+                }
+            }
+            if let Some(kind) = kind {
+                cursors.insert(&region.name, start + data.len() as u32);
+                let name = placement.section.clone();
+                sections.push(Section::new(name, kind, start as u16, data));
+            }
+        }
+        Ok((sections, placed))
+    }
+}
+
+fn join_lines(errors: &[SymbolError]) -> String {
+    errors
+        .iter()
+        .map(ToString::to_string)
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cpu::{operation::mov, Cpu, Error as CpuError, WideRegister};
+    use crate::memory::{Device, MemoryMapper, TestDevice};
+    use crate::object::{Relocation, SectionKind};
+
+    const SCRIPT: &str = "
+        region rom 0x0000 0x003f
+        region ram 0x0040 0x00ff
+        place code rom
+        place data ram
+        entry start
+    ";
+
+    fn symbol(name: &str, section: Option<u16>, offset: u16, global: bool) -> Symbol {
+        Symbol {
+            name: name.to_string(),
+            section,
+            offset,
+            global,
+        }
+    }
+
+    fn reloc(offset: u16, symbol: u16) -> Relocation {
+        Relocation {
+            section: 0,
+            offset,
+            symbol,
+            addend: 0,
+        }
+    }
+
+    /// Stores 0xab in `counter` from the library, then halts
+    fn main_object() -> Object {
+        let code = vec![mov::lit_mem::CODE, 0xab, 0, 0, 0xff];
+        Object {
+            sections: vec![Section::new("code", SectionKind::Code, 0, code)],
+            symbols: vec![
+                symbol("start", Some(0), 0, true),
+                symbol("counter", None, 0, false),
+            ],
+            relocations: vec![reloc(2, 1)],
+            entry: None,
+        }
+    }
+
+    /// Sets `CD` to the address of its own data
+    fn lib_object() -> Object {
+        let code = vec![mov::lit_reg_wide::CODE, 0, 0, WideRegister::CD.into()];
+        Object {
+            sections: vec![
+                Section::new("data", SectionKind::Data, 0, vec![0; 4]),
+                Section::new("code", SectionKind::Code, 0, code),
+            ],
+            symbols: vec![
+                symbol("counter", Some(0), 2, true),
+                symbol("table", Some(0), 0, false),
+            ],
+            relocations: vec![Relocation {
+                section: 1,
+                offset: 1,
+                symbol: 1,
+                addend: 0,
+            }],
+            entry: None,
+        }
+    }
+
+    fn link(objects: Vec<(&str, Object)>) -> Result<Object, LinkError> {
+        let mut linker = Linker::new();
+        for (name, object) in objects {
+            linker.add(name, object);
+        }
+        linker.link(&SCRIPT.parse().expect("valid script"))
+    }
+
+    fn symbol_errors(result: Result<Object, LinkError>) -> Vec<SymbolError> {
+        match result.expect_err("symbol errors") {
+            LinkError::Symbols(errors) => errors,
+            err => panic!("unexpected error: {}", err),
+        }
+    }
+
+    #[test]
+    fn link_layout() {
+        let object = link(vec![("main", main_object()), ("lib", lib_object())]).expect("links");
+        assert_eq!(2, object.sections.len());
+        let code = &object.sections[0];
+        assert_eq!((0, 9), (code.addr, code.data.len()));
+        assert_eq!([0x00, 0x42], code.data[2..4]);
+        assert_eq!([0x00, 0x40], code.data[6..8]);
+        assert_eq!(0x40, object.sections[1].addr);
+        assert_eq!(Some(0), object.entry);
+        assert_eq!(Some(0x42), object.symbol_address("counter"));
+        assert_eq!(Some(0x40), object.symbol_address("table"));
+        assert!(object.relocations.is_empty());
+    }
+
+    #[test]
+    fn link_and_run() {
+        let object = link(vec![("main", main_object()), ("lib", lib_object())]).expect("links");
+        let mut mapper = MemoryMapper::new();
+        mapper
+            .add_device(Box::new(TestDevice::new(0x100)), 0, 0xff)
+            .expect("valid range");
+        let mut cpu = Cpu::new(mapper).expect("valid CPU");
+        cpu.load_object(&object).expect("loads");
+        let err = cpu.run().expect_err("halts");
+        assert!(matches!(err, CpuError::Halt));
+        assert_eq!(0xab, cpu.memory().get(0x42).expect("mapped"));
+    }
+
+    #[test]
+    fn undefined_symbols() {
+        let errors = symbol_errors(link(vec![("main", main_object())]));
+        let expected = vec![SymbolError::Undefined {
+            name: "counter".to_string(),
+            module: "main".to_string(),
+        }];
+        assert_eq!(expected, errors);
+    }
+
+    #[test]
+    fn duplicate_symbols_reported_together() {
+        let mut other = lib_object();
+        other.symbols[0].name = "start".to_string();
+        let errors = symbol_errors(link(vec![
+            ("main", main_object()),
+            ("lib", other.clone()),
+            ("other", other),
+        ]));
+        let messages: Vec<String> = errors.iter().map(ToString::to_string).collect();
+        assert_eq!(
+            vec![
+                "duplicate symbol start: defined in main and lib",
+                "duplicate symbol start: defined in main and other",
+                "main: undefined symbol counter",
+            ],
+            messages
+        );
+    }
+
+    #[test]
+    fn local_symbols_do_not_resolve_imports() {
+        let mut lib = lib_object();
+        lib.symbols[0].global = false;
+        let errors = symbol_errors(link(vec![("main", main_object()), ("lib", lib)]));
+        assert_eq!(1, errors.len());
+    }
+
+    #[test]
+    fn unplaced_section() {
+        let mut lib = lib_object();
+        lib.sections[0].name = "bss".to_string();
+        let err = link(vec![("main", main_object()), ("lib", lib)]).expect_err("unplaced");
+        assert_eq!(
+            "lib: section bss has no placement in the memory map",
+            err.to_string()
+        );
+    }
+
+    #[test]
+    fn region_full() {
+        let mut lib = lib_object();
+        lib.sections[0].data = vec![0; 0xc1];
+        let err = link(vec![("main", main_object()), ("lib", lib)]).expect_err("too big");
+        assert_eq!(
+            "lib: section data does not fit in region ram",
+            err.to_string()
+        );
+    }
+
+    #[test]
+    fn missing_entry() {
+        let mut main = main_object();
+        main.symbols[0].global = false;
+        let err = link(vec![("main", main), ("lib", lib_object())]).expect_err("no entry");
+        assert!(matches!(err, LinkError::Entry(name) if name == "start"));
+    }
+
+    #[test]
+    fn invalid_object() {
+        let mut main = main_object();
+        main.relocations[0].offset = 4;
+        let err = link(vec![("main", main)]).expect_err("invalid");
+        assert_eq!("main: invalid relocation 0", err.to_string());
+    }
+}
@@ -0,0 +1,187 @@
+use std::str::FromStr;
+
+/// Linker memory map script
+///
+/// One directive per line, `#` starts a comment:
+///
+/// ```text
+/// region rom 0x0000 0x00ff   # name, first and last address
+/// region ram 0x0100 0xfffd
+/// place code rom             # sections named `code` go in `rom`
+/// place data ram
+/// entry start                # symbol for the entry point
+/// ```
+///
+/// Sections are laid out in `place` order, packed from the start of each
+/// region.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MemoryMap {
+    pub regions: Vec<MapRegion>,
+    pub placements: Vec<Placement>,
+    pub entry: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MapRegion {
+    pub name: String,
+    pub start: u16,
+    pub end: u16,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Placement {
+    pub section: String,
+    pub region: String,
+}
+
+#[derive(Debug, thiserror::Error, PartialEq, Eq)]
+#[error("line {line}: {message}")]
+pub struct ScriptError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl MemoryMap {
+    pub fn region(&self, name: &str) -> Option<&MapRegion> {
+        self.regions.iter().find(|region| region.name == name)
+    }
+
+    fn add_region(&mut self, args: &[&str]) -> Result<(), String> {
+        let [name, start, end] = args else {
+            return Err("expected `region <name> <start> <end>`".to_string());
+        };
+        let start = parse_addr(start)?;
+        let end = parse_addr(end)?;
+        if start > end {
+            return Err(format!("region {} ends before it starts", name));
+        }
+        if self.region(name).is_some() {
+            return Err(format!("duplicate region {}", name));
+        }
+        if let Some(other) = self
+            .regions
+            .iter()
+            .find(|other| start <= other.end && other.start <= end)
+        {
+            return Err(format!("region {} overlaps {}", name, other.name));
+        }
+        self.regions.push(MapRegion {
+            name: name.to_string(),
+            start,
+            end,
+        });
+        Ok(())
+    }
+
+    fn add_placement(&mut self, args: &[&str]) -> Result<(), String> {
+        let [section, region] = args else {
+            return Err("expected `place <section> <region>`".to_string());
+        };
+        if self.region(region).is_none() {
+            return Err(format!("unknown region {}", region));
+        }
+        if self.placements.iter().any(|p| p.section == *section) {
+            return Err(format!("section {} is already placed", section));
+        }
+        self.placements.push(Placement {
+            section: section.to_string(),
+            region: region.to_string(),
+        });
+        Ok(())
+    }
+
+    fn set_entry(&mut self, args: &[&str]) -> Result<(), String> {
+        let [symbol] = args else {
+            return Err("expected `entry <symbol>`".to_string());
+        };
+        if self.entry.is_some() {
+            return Err("entry is already set".to_string());
+        }
+        self.entry = Some(symbol.to_string());
+        Ok(())
+    }
+}
+
+impl FromStr for MemoryMap {
+    type Err = ScriptError;
+
+    fn from_str(script: &str) -> Result<Self, Self::Err> {
+        let mut map = Self::default();
+        for (index, line) in script.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or_default();
+            let words: Vec<&str> = line.split_whitespace().collect();
+            let result = match words.split_first() {
+                None => Ok(()),
+                Some((&"region", args)) => map.add_region(args),
+                Some((&"place", args)) => map.add_placement(args),
+                Some((&"entry", args)) => map.set_entry(args),
+                Some((directive, _)) => Err(format!("unknown directive {}", directive)),
+            };
+            result.map_err(|message| ScriptError {
+                line: index + 1,
+                message,
+            })?;
+        }
+        Ok(map)
+    }
+}
+
+fn parse_addr(text: &str) -> Result<u16, String> {
+    let parsed = match text.strip_prefix("0x") {
+        Some(hex) => u16::from_str_radix(hex, 16),
+        None => text.parse(),
+    };
+    parsed.map_err(|_| format!("invalid address {}", text))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn error(script: &str) -> ScriptError {
+        script.parse::<MemoryMap>().expect_err("invalid script")
+    }
+
+    #[test]
+    fn parse_script() {
+        let script = "
+            # layout
+            region rom 0x0000 0x00ff
+            region ram 256 0xfffd
+            place code rom   # all code
+            place data ram
+            entry start
+        ";
+        let map: MemoryMap = script.parse().expect("valid script");
+        assert_eq!(2, map.regions.len());
+        assert_eq!(0x100, map.region("ram").expect("ram").start);
+        assert_eq!("rom", map.placements[0].region);
+        assert_eq!(Some("start".to_string()), map.entry);
+    }
+
+    #[test]
+    fn error_lines() {
+        let err = error("region rom 0 0xff\n\nplace code ram");
+        assert_eq!(3, err.line);
+        assert_eq!("unknown region ram", err.message);
+    }
+
+    #[test]
+    fn invalid_directives() {
+        assert_eq!("unknown directive load", error("load code").message);
+        assert_eq!("invalid address 0xg", error("region a 0xg 0xff").message);
+        assert_eq!(
+            "region a ends before it starts",
+            error("region a 0xff 0").message
+        );
+        assert_eq!(
+            "region b overlaps a",
+            error("region a 0 0xff\nregion b 0xff 0x1ff").message
+        );
+        assert_eq!(
+            "section code is already placed",
+            error("region a 0 0xff\nplace code a\nplace code a").message
+        );
+        assert_eq!("expected `entry <symbol>`", error("entry").message);
+    }
+}
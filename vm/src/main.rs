@@ -1,51 +1,148 @@
+use h8bit_asm::assemble_file;
 use h8bit_vm::{
-    cpu::Cpu,
-    cpu::{operation::*, Register, WideRegister},
-    memory::{DynMem, MemoryMapper, RamArray},
-    util::high_and_low_value,
+    cpu::{Cpu, WideRegister},
+    dap::DapServer,
+    gdb::GdbStub,
+    h8asm,
+    image::{parse_ihex, parse_srec},
+    memory::{DeviceKind, DumpFormat, DynMem, MemoryMapper, RamArray},
+    object::Object,
+    source_map::SourceMap,
 };
+use std::error::Error;
+use std::net::TcpListener;
+use std::path::Path;
+use std::{env, fs, io, process};
+
+const GDB_PORT: u16 = 1234;
+const ROM_MAX: usize = 0xfffd;
 
 fn main() {
+    let mut args = env::args().skip(1);
+    match args.next().as_deref() {
+        None => run(create_cpu(BOOT_ROM)),
+        Some("run") => match args.next() {
+            Some(path) => run(load_or_exit(&path)),
+            None => exit_with("usage: h8bit-vm run <program>"),
+        },
+        Some("gdbstub") => {
+            let port = match args.next().map(|port| port.parse()) {
+                None => GDB_PORT,
+                Some(Ok(port)) => port,
+                Some(Err(err)) => exit_with(&format!("invalid port: {}", err)),
+            };
+            let cpu = match args.next() {
+                Some(path) => load_or_exit(&path),
+                None => create_cpu(BOOT_ROM),
+            };
+            if let Err(err) = gdbstub(cpu, port) {
+                exit_with(&format!("gdbstub: {}", err));
+            }
+        }
+        Some("dap") => {
+            if let Err(err) = dap() {
+                exit_with(&format!("dap: {}", err));
+            }
+        }
+        Some(mode) => exit_with(&format!("unknown mode: {}", mode)),
+    }
+}
+
+/// Create a CPU with `rom` mapped over RAM from address 0
+fn create_cpu(rom: &[u8]) -> Cpu {
     // create memory
     let mut mem_map = MemoryMapper::new();
     let ram = Box::new(RamArray::new());
-    mem_map.add_device(ram, 0, 0xfffd);
+    mem_map
+        .add_named_device(ram, 0, 0xfffd, "ram", DeviceKind::Ram)
+        .expect("valid range");
 
     // load boot rom
-    let mut boot_mem = Box::new(DynMem::new(0xff + 1));
-    boot_mem.replace(&boot_rom(), 0);
-    mem_map.add_device(boot_mem, 0, 0xff);
+    let size = rom.len().max(0xff + 1);
+    let mut boot_mem = Box::new(DynMem::new(size));
+    boot_mem.replace(rom, 0);
+    let end = (size - 1) as u16;
+    mem_map
+        .add_named_device(boot_mem, 0, end, "boot_rom", DeviceKind::Rom)
+        .expect("valid range");
+
+    Cpu::new(mem_map).expect("valid CPU")
+}
 
-    // create cpu
-    let mut cpu = Cpu::new(mem_map).expect("valid CPU");
+fn run(mut cpu: Cpu) {
+    let format = DumpFormat::default();
+    print!("{}", cpu.memory());
     println!("{}", cpu);
-    cpu.display_memory_at(0);
+    print!("{}", cpu.memory().dump(0..=0x0f, &format));
 
-    // run
-    cpu.run();
+    match cpu.run() {
+        Ok(reason) => println!("stopped: {:?}", reason),
+        Err(err) => println!("{}", err),
+    }
     println!("{}", cpu);
-    cpu.display_memory_at(0x01f0);
-}
-
-fn boot_rom() -> Vec<u8> {
-    let addr = 0x01f0;
-    let (addr_high, addr_low) = high_and_low_value(addr);
-    [
-        vec![
-            mov::lit_reg_wide::CODE,
-            addr_high,
-            addr_low,
-            WideRegister::CD.into(),
-        ],
-        vec![mov::lit_mem::CODE, 0xab, addr_high, addr_low],
-        vec![
-            mov::reg_ptr_reg::CODE,
-            WideRegister::CD.into(),
-            Register::A.into(),
-        ],
-        vec![hlt::CODE],
-    ]
-    .into_iter()
-    .flatten()
-    .collect()
+    print!("{}", cpu.memory().dump(0x01f0..=0x01ff, &format));
 }
+
+fn gdbstub(cpu: Cpu, port: u16) -> io::Result<()> {
+    let listener = TcpListener::bind(("127.0.0.1", port))?;
+    println!("waiting for gdb on {}", listener.local_addr()?);
+    GdbStub::new(cpu).serve(&listener)
+}
+
+/// Serve the Debug Adapter Protocol on stdio
+fn dap() -> io::Result<()> {
+    DapServer::new(io::stdin().lock(), io::stdout(), Box::new(load_program)).serve()
+}
+
+/// Load objects, Intel HEX or S-record files by extension, assemble `.asm`
+/// sources into ROM with their source map, and load anything else as a raw
+/// ROM
+fn load_program(path: &Path) -> Result<(Cpu, Option<SourceMap>), Box<dyn Error>> {
+    let image = match path.extension().and_then(|ext| ext.to_str()) {
+        Some("asm" | "s") => {
+            let program = assemble_file(path)?;
+            let source_map = SourceMap::from_program(path, &program);
+            return Ok((rom_cpu(&program.bytes)?, Some(source_map)));
+        }
+        Some("hex" | "ihex") => parse_ihex(&fs::read_to_string(path)?)?,
+        Some("srec" | "s19" | "mot") => parse_srec(&fs::read_to_string(path)?)?,
+        Some("h8o") => {
+            let object = Object::from_bytes(&fs::read(path)?)?;
+            let mut cpu = create_cpu(&[]);
+            cpu.load_object(&object)?;
+            return Ok((cpu, None));
+        }
+        _ => return Ok((rom_cpu(&fs::read(path)?)?, None)),
+    };
+    let mut cpu = create_cpu(&[]);
+    image.load_into(cpu.memory_mut())?;
+    if let Some(entry) = image.entry {
+        cpu.registers_mut().set_wide(WideRegister::PC, entry);
+    }
+    Ok((cpu, None))
+}
+
+fn rom_cpu(rom: &[u8]) -> Result<Cpu, Box<dyn Error>> {
+    if rom.len() > ROM_MAX {
+        return Err(format!("ROM larger than {} bytes", ROM_MAX).into());
+    }
+    Ok(create_cpu(rom))
+}
+
+fn load_or_exit(path: &str) -> Cpu {
+    load_program(Path::new(path))
+        .map(|(cpu, _)| cpu)
+        .unwrap_or_else(|err| exit_with(&format!("failed to load {}: {}", path, err)))
+}
+
+fn exit_with(message: &str) -> ! {
+    eprintln!("{}", message);
+    process::exit(1);
+}
+
+const BOOT_ROM: &[u8] = &h8asm! {
+    mov 0x01f0, cd;
+    mov 0xab, [0x01f0];
+    mov [cd], a;
+    hlt
+};
@@ -0,0 +1,109 @@
+use super::{Access, Device, DeviceError, MemoryMapper};
+
+/// Memory as seen by a [`Cpu`](crate::cpu::Cpu)
+///
+/// [`MemoryMapper`] maps devices at runtime. An embedder with a fixed layout
+/// can implement `Bus` itself, so accesses are statically dispatched.
+/// Watchpoints, tracing writes, rewind and the decode cache only see
+/// accesses on buses which record them, see [`Bus::observe`].
+pub trait Bus: Device {
+    /// Lowest mapped address, the initial program counter
+    fn start(&self) -> Option<u16>;
+
+    /// Highest mapped address, the initial stack pointer
+    fn end(&self) -> Option<u16>;
+
+    /// Get a byte as an instruction fetch
+    fn fetch(&self, addr: u16) -> Result<u8, DeviceError> {
+        self.get(addr)
+    }
+
+    /// Get a wide value as an instruction fetch
+    fn fetch_wide(&self, addr: u16) -> Result<u16, DeviceError> {
+        self.get_wide(addr)
+    }
+
+    /// Record every byte access until observing is turned off
+    fn observe(&mut self, _observing: bool) {}
+
+    /// Whether accesses are being recorded
+    fn is_observing(&self) -> bool {
+        false
+    }
+
+    /// Take all accesses recorded since the last call
+    fn take_accesses(&self) -> Vec<Access> {
+        Vec::new()
+    }
+
+    /// Whether the byte at `addr` can also be reached at other addresses
+    fn is_aliased(&self, _addr: u16) -> bool {
+        false
+    }
+
+    /// Count `len` bytes from `addr` as fetched without reading them
+    ///
+    /// Called for instructions taken from the decode cache, so access
+    /// counts match running without it.
+    fn count_fetch(&self, _addr: u16, _len: u16) {}
+
+    /// Set a byte for a debugger
+    ///
+    /// Buses which record accesses should not record this write.
+    fn poke(&mut self, addr: u16, value: u8) -> Result<(), DeviceError> {
+        self.set(addr, value)
+    }
+
+    /// Put back a byte overwritten by an instruction, used by rewind
+    ///
+    /// Buses which record accesses should not record this write.
+    fn undo_write(&mut self, addr: u16, previous: u8) -> Result<(), DeviceError> {
+        self.set(addr, previous)
+    }
+}
+
+impl Bus for MemoryMapper {
+    fn start(&self) -> Option<u16> {
+        MemoryMapper::start(self)
+    }
+
+    fn end(&self) -> Option<u16> {
+        MemoryMapper::end(self)
+    }
+
+    fn fetch(&self, addr: u16) -> Result<u8, DeviceError> {
+        MemoryMapper::fetch(self, addr)
+    }
+
+    fn fetch_wide(&self, addr: u16) -> Result<u16, DeviceError> {
+        MemoryMapper::fetch_wide(self, addr)
+    }
+
+    fn observe(&mut self, observing: bool) {
+        MemoryMapper::observe(self, observing)
+    }
+
+    fn is_observing(&self) -> bool {
+        MemoryMapper::is_observing(self)
+    }
+
+    fn take_accesses(&self) -> Vec<Access> {
+        MemoryMapper::take_accesses(self)
+    }
+
+    fn is_aliased(&self, addr: u16) -> bool {
+        MemoryMapper::is_aliased(self, addr)
+    }
+
+    fn count_fetch(&self, addr: u16, len: u16) {
+        MemoryMapper::count_fetch(self, addr, len)
+    }
+
+    fn poke(&mut self, addr: u16, value: u8) -> Result<(), DeviceError> {
+        MemoryMapper::poke(self, addr, value)
+    }
+
+    fn undo_write(&mut self, addr: u16, previous: u8) -> Result<(), DeviceError> {
+        MemoryMapper::undo_write(self, addr, previous)
+    }
+}
@@ -1,3 +1,6 @@
+use super::DeviceKind;
+use std::any::Any;
+
 pub mod ram;
 
 #[derive(Debug, thiserror::Error)]
@@ -5,18 +8,76 @@ pub mod ram;
 pub enum Error {
     #[error("invalid device address: {0:#06x}")]
     OutOfBounds(u16),
+    #[error("invalid device state")]
+    InvalidState,
     #[error("unkown error")]
     Other,
+    #[error("{device} at {addr:#06x}: {source}")]
+    Fault {
+        device: String,
+        addr: u16,
+        source: Box<Error>,
+    },
 }
 
-pub trait Device {
+/// Memory mapped into a [`MemoryMapper`](crate::memory::MemoryMapper)
+pub trait Device: Any {
     fn set(&mut self, addr: u16, data: u8) -> Result<(), Error>;
     fn get(&self, addr: u16) -> Result<u8, Error>;
     fn set_wide(&mut self, addr: u16, data: u16) -> Result<(), Error>;
     fn get_wide(&self, addr: u16) -> Result<u16, Error>;
+
+    /// Name shown in memory dumps, the type name by default
+    fn name(&self) -> &str {
+        let path = std::any::type_name::<Self>();
+        let path = path.split('<').next().unwrap_or(path);
+        path.rsplit("::").next().unwrap_or(path)
+    }
+
+    /// What the device is used as when mapped with
+    /// [`MemoryMapper::add_device`](crate::memory::MemoryMapper::add_device),
+    /// RAM by default
+    fn kind(&self) -> DeviceKind {
+        DeviceKind::Ram
+    }
+
+    /// Leave the device's contents out of snapshots
+    ///
+    /// For peripherals with nothing worth saving. Their region is still part
+    /// of the snapshot layout.
+    fn skip_snapshot(&self) -> bool {
+        false
+    }
+
+    /// Contents to save in a snapshot
+    ///
+    /// Devices which return `None`, the default, cannot be snapshotted unless
+    /// they [skip snapshots](Device::skip_snapshot).
+    fn export_state(&self) -> Option<Vec<u8>> {
+        None
+    }
+
+    /// Restore contents previously returned by [`Device::export_state`]
+    fn import_state(&mut self, _state: &[u8]) -> Result<(), Error> {
+        Err(Error::InvalidState)
+    }
 }
 
 macro_rules! device_impl {
+    (@state $prop:tt) => {
+        fn export_state(&self) -> Option<Vec<u8>> {
+            Some(self.$prop.to_vec())
+        }
+
+        fn import_state(&mut self, state: &[u8]) -> Result<(), crate::memory::DeviceError> {
+            if state.len() != self.$prop.len() {
+                return Err(crate::memory::DeviceError::InvalidState);
+            }
+            self.$prop.copy_from_slice(state);
+            Ok(())
+        }
+    };
+
     (@wide) => {
         fn set_wide(&mut self, addr: u16, data: u16) -> Result<(), crate::memory::DeviceError> {
             let (high, low) = crate::util::high_and_low_value(data);
@@ -50,6 +111,7 @@ macro_rules! device_impl {
             }
 
             device_impl!(@wide);
+            device_impl!(@state $prop);
         }
     };
 
@@ -72,6 +134,18 @@ macro_rules! device_impl {
             }
 
             device_impl!(@wide);
+
+            fn export_state(&self) -> Option<Vec<u8>> {
+                Some(self.to_vec())
+            }
+
+            fn import_state(&mut self, state: &[u8]) -> Result<(), crate::memory::DeviceError> {
+                if state.len() != self.len() {
+                    return Err(crate::memory::DeviceError::InvalidState);
+                }
+                self.copy_from_slice(state);
+                Ok(())
+            }
         }
     };
 }
@@ -123,6 +197,18 @@ pub mod tests {
                     assert_eq!(low, actual_low);
                 }
 
+                #[test]
+                fn [<$name _state_round_trip>]() {
+                    let mut dev = $constructor();
+                    if let Some(state) = dev.export_state() {
+                        let (addr, value) = (1, 1);
+                        dev.set(addr, value).expect("setting valid address");
+                        dev.import_state(&state).expect("importing own state");
+                        let actual = dev.get(addr).expect("getting valid address");
+                        assert_eq!(0, actual);
+                    }
+                }
+
                 #[test]
                 fn [<$name _set_byte_get_wide>]() {
                     let mut dev = $constructor();
@@ -1,4 +1,7 @@
-use super::device_impl;
+use super::{device_impl, Device};
+use crate::memory::DeviceError;
+
+const PAGE_SIZE: usize = 256;
 
 #[derive(Debug)]
 pub struct RamArray([u8; 256 * 256]);
@@ -39,10 +42,89 @@ impl Default for DynMem {
     }
 }
 
+/// 64 KiB of RAM, allocated a page at a time on first write
+///
+/// Unwritten addresses read as zero.
+#[derive(Debug)]
+pub struct SparseRam(Vec<Option<Box<[u8; PAGE_SIZE]>>>);
+
+impl SparseRam {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Number of allocated pages
+    pub fn pages(&self) -> usize {
+        self.0.iter().filter(|page| page.is_some()).count()
+    }
+}
+
+impl Default for SparseRam {
+    fn default() -> Self {
+        Self(vec![None; 0xffff / PAGE_SIZE + 1])
+    }
+}
+
+impl Device for SparseRam {
+    fn set(&mut self, addr: u16, data: u8) -> Result<(), DeviceError> {
+        let (page, offset) = page_and_offset(addr);
+        match &mut self.0[page] {
+            Some(page) => page[offset] = data,
+            None if data == 0 => {}
+            None => {
+                let mut new = Box::new([0; PAGE_SIZE]);
+                new[offset] = data;
+                self.0[page] = Some(new);
+            }
+        }
+        Ok(())
+    }
+
+    fn get(&self, addr: u16) -> Result<u8, DeviceError> {
+        let (page, offset) = page_and_offset(addr);
+        Ok(self.0[page].as_ref().map_or(0, |page| page[offset]))
+    }
+
+    device_impl!(@wide);
+
+    fn export_state(&self) -> Option<Vec<u8>> {
+        let mut state = Vec::with_capacity(0xffff + 1);
+        for page in &self.0 {
+            match page {
+                Some(page) => state.extend_from_slice(&page[..]),
+                None => state.resize(state.len() + PAGE_SIZE, 0),
+            }
+        }
+        Some(state)
+    }
+
+    /// Only pages with a non-zero byte are allocated
+    fn import_state(&mut self, state: &[u8]) -> Result<(), DeviceError> {
+        if state.len() != self.0.len() * PAGE_SIZE {
+            return Err(DeviceError::InvalidState);
+        }
+        for (page, data) in self.0.iter_mut().zip(state.chunks_exact(PAGE_SIZE)) {
+            *page = if data.iter().any(|&byte| byte != 0) {
+                let mut new = Box::new([0; PAGE_SIZE]);
+                new.copy_from_slice(data);
+                Some(new)
+            } else {
+                None
+            };
+        }
+        Ok(())
+    }
+}
+
+fn page_and_offset(addr: u16) -> (usize, usize) {
+    let addr = addr as usize;
+    (addr / PAGE_SIZE, addr % PAGE_SIZE)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::memory::{device::tests::device_tests, Device, DeviceError};
+    use crate::memory::device::tests::device_tests;
 
     #[test]
     fn ram_array_new_and_default_is_zeroed() {
@@ -93,5 +175,40 @@ mod tests {
         }
     }
 
+    #[test]
+    fn dyn_mem_import_state_wrong_size() {
+        let mut ram = DynMem::new(8);
+        let err = ram.import_state(&[0; 4]).expect_err("wrong size");
+        assert!(matches!(err, DeviceError::InvalidState));
+    }
+
     device_tests!(dyn_mem, || DynMem::new(8));
+
+    #[test]
+    fn sparse_ram_allocates_on_write() {
+        let mut ram = SparseRam::new();
+        assert_eq!(0, ram.get(0xffff).expect("valid address"));
+        ram.set(0x1234, 0).expect("valid address");
+        assert_eq!(0, ram.pages());
+        ram.set(0x1234, 0xab).expect("valid address");
+        ram.set(0x12ff, 0xcd).expect("valid address");
+        assert_eq!(1, ram.pages());
+        ram.set_wide(0x12ff, 0x0102).expect("valid address");
+        assert_eq!(2, ram.pages());
+        assert_eq!(0xab, ram.get(0x1234).expect("valid address"));
+    }
+
+    #[test]
+    fn sparse_ram_import_state() {
+        let mut state = vec![0; 0xffff + 1];
+        state[0x0300] = 1;
+        let mut ram = SparseRam::new();
+        ram.import_state(&state).expect("full state");
+        assert_eq!(1, ram.pages());
+        assert_eq!(Some(state), ram.export_state());
+        let err = ram.import_state(&[0; 4]).expect_err("wrong size");
+        assert!(matches!(err, DeviceError::InvalidState));
+    }
+
+    device_tests!(sparse_ram, SparseRam::new);
 }
@@ -0,0 +1,167 @@
+use super::MemoryMapper;
+use std::fmt::Write as _;
+use std::io;
+use std::ops::RangeInclusive;
+
+/// Layout of [`MemoryMapper::dump`] rows
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DumpFormat {
+    /// Bytes per row
+    pub width: u16,
+    /// Show printable bytes as characters after the hex
+    pub ascii: bool,
+    /// Name the devices each row reads from
+    pub devices: bool,
+}
+
+impl Default for DumpFormat {
+    fn default() -> Self {
+        Self {
+            width: 16,
+            ascii: true,
+            devices: true,
+        }
+    }
+}
+
+impl MemoryMapper {
+    /// Hexdump of `range`, unmapped addresses are shown as `--`
+    ///
+    /// Reading for a dump is never recorded as an access.
+    pub fn dump(&self, range: RangeInclusive<u16>, format: &DumpFormat) -> String {
+        let mut out = Vec::new();
+        self.write_dump(&mut out, range, format)
+            .expect("writing to a vector");
+        String::from_utf8(out).expect("dump is ASCII")
+    }
+
+    pub fn write_dump<W: io::Write + ?Sized>(
+        &self,
+        out: &mut W,
+        range: RangeInclusive<u16>,
+        format: &DumpFormat,
+    ) -> io::Result<()> {
+        let width = format.width.max(1) as u32;
+        let end = *range.end() as u32;
+        let mut row = *range.start() as u32;
+        while row <= end {
+            let mut line = format!("{:#06x}:", row);
+            let mut ascii = String::new();
+            let mut names = Vec::new();
+            for addr in row..row + width {
+                if addr > end {
+                    line.push_str("   ");
+                    continue;
+                }
+                let addr = addr as u16;
+                match self.peek(addr) {
+                    Some(byte) => {
+                        write!(line, " {:02x}", byte).expect("writing to a string");
+                        ascii.push(printable(byte));
+                    }
+                    None => {
+                        line.push_str(" --");
+                        ascii.push(' ');
+                    }
+                }
+                if let Some(name) = self.device_name(addr) {
+                    if !names.contains(&name) {
+                        names.push(name);
+                    }
+                }
+            }
+            if format.ascii {
+                write!(line, "  |{}|", ascii).expect("writing to a string");
+            }
+            if format.devices && !names.is_empty() {
+                write!(line, "  {}", names.join(", ")).expect("writing to a string");
+            }
+            writeln!(out, "{}", line.trim_end())?;
+            row += width;
+        }
+        Ok(())
+    }
+}
+
+fn printable(byte: u8) -> char {
+    if byte.is_ascii_graphic() || byte == b' ' {
+        byte as char
+    } else {
+        '.'
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memory::{Device, DynMem, TestDevice};
+
+    fn test_mapper() -> MemoryMapper {
+        let mut mapper = MemoryMapper::new();
+        let mut rom = DynMem::new(4);
+        rom.replace(b"h8\x00\xff", 0);
+        mapper.add_device(Box::new(rom), 0, 3).expect("valid range");
+        mapper
+            .add_device(Box::new(TestDevice::new(4)), 8, 11)
+            .expect("valid range");
+        mapper
+    }
+
+    #[test]
+    fn dump_rows() {
+        let format = DumpFormat {
+            width: 8,
+            ..DumpFormat::default()
+        };
+        let expected = "\
+0x0000: 68 38 00 ff -- -- -- --  |h8..    |  DynMem
+0x0008: 00 00 00 00 -- --        |....  |  TestDevice
+";
+        assert_eq!(expected, test_mapper().dump(0..=13, &format));
+    }
+
+    #[test]
+    fn dump_without_columns() {
+        let format = DumpFormat {
+            width: 4,
+            ascii: false,
+            devices: false,
+        };
+        let expected = "0x0002: 00 ff -- --\n0x0006: -- -- 00 00\n";
+        assert_eq!(expected, test_mapper().dump(2..=9, &format));
+    }
+
+    #[test]
+    fn dump_end_of_memory() {
+        let mut mapper = MemoryMapper::new();
+        mapper
+            .add_device(Box::new(TestDevice::new(2)), 0xfffe, 0xffff)
+            .expect("valid range");
+        let format = DumpFormat::default();
+        let dump = mapper.dump(0xfff8..=0xffff, &format);
+        assert_eq!(
+            "0xfff8: -- -- -- -- -- -- 00 00                          |      ..|  TestDevice\n",
+            dump
+        );
+    }
+
+    #[test]
+    fn dump_rows_name_every_device() {
+        let format = DumpFormat {
+            ascii: false,
+            ..DumpFormat::default()
+        };
+        let dump = test_mapper().dump(0..=15, &format);
+        assert!(dump.ends_with("  DynMem, TestDevice\n"));
+    }
+
+    #[test]
+    fn dump_is_not_recorded() {
+        let mut mapper = test_mapper();
+        mapper.observe(true);
+        mapper.dump(0..=3, &DumpFormat::default());
+        assert!(mapper.take_accesses().is_empty());
+        mapper.get(0).expect("mapped");
+        assert_eq!(1, mapper.take_accesses().len());
+    }
+}
@@ -1,11 +1,100 @@
 use super::device::Device;
-use crate::memory::DeviceError;
+use super::stats::AccessStats;
+use crate::{
+    memory::DeviceError,
+    util::{high_and_low_value, wide_value},
+};
+use std::any::Any;
+use std::cell::{Ref, RefCell};
+use std::fmt;
+use std::iter;
+use std::marker::PhantomData;
+use strum_macros::FromRepr;
 
 #[derive(Default, Debug)]
 pub struct MemoryMapper {
     regions: Vec<Region>,
-    start: u16,
-    end: u16,
+    next_id: u64,
+    observing: bool,
+    accesses: RefCell<Vec<Access>>,
+    stats: RefCell<Option<AccessStats>>,
+}
+
+/// Typed reference to a device mapped by a [`MemoryMapper`]
+///
+/// A handle goes stale when its device is removed or replaced. Devices
+/// mapped with [`MemoryMapper::add_boxed_device`] have a
+/// `DeviceHandle<dyn Device>`, which can't be used to get the device back
+/// typed.
+pub struct DeviceHandle<D: ?Sized> {
+    id: u64,
+    device: PhantomData<fn() -> D>,
+}
+
+#[derive(Debug, thiserror::Error, PartialEq, Eq)]
+pub enum MapError {
+    #[error("device is no longer mapped")]
+    Stale,
+    #[error("device does not fit at {0:#06x}")]
+    Bounds(u16),
+    #[error("range {0:#06x}..={1:#06x} ends before it starts")]
+    Inverted(u16, u16),
+    #[error("device is not of the handle's type")]
+    WrongType,
+    #[error("devices cannot save their state: {}", .0.join(", "))]
+    NoState(Vec<String>),
+}
+
+/// A single byte access made through a [`MemoryMapper`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Access {
+    pub kind: AccessKind,
+    pub addr: u16,
+    pub value: u8,
+    /// Value replaced by a write, the same as `value` for other accesses
+    pub previous: u8,
+}
+
+/// Saved contents of a mapped device
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RegionState {
+    pub name: String,
+    pub kind: DeviceKind,
+    pub start: u16,
+    pub end: u16,
+    pub mask: u16,
+    pub mirrors: Vec<(u16, u16)>,
+    /// `None` if the device skips snapshots
+    pub state: Option<Vec<u8>>,
+}
+
+/// What a mapped device is used as, for listings
+#[derive(Debug, Clone, Copy, PartialEq, Eq, FromRepr)]
+#[repr(u8)]
+pub enum DeviceKind {
+    Ram,
+    Rom,
+    /// Memory mapped peripheral
+    Io,
+}
+
+/// Layout of a mapped device, from [`MemoryMapper::regions`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RegionInfo<'a> {
+    pub name: &'a str,
+    pub kind: DeviceKind,
+    pub start: u16,
+    pub end: u16,
+    /// Added with [`MemoryMapper::add_mirror`]
+    pub mirror: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AccessKind {
+    /// Instruction fetch by the CPU
+    Fetch,
+    Read,
+    Write,
 }
 
 impl MemoryMapper {
@@ -13,94 +102,628 @@ impl MemoryMapper {
         Self::default()
     }
 
-    pub fn add_device(&mut self, device: Box<dyn Device>, start: u16, end: u16) {
-        match self.start() {
-            // map starts at new device start
-            Some(x) if start < x => self.start = start,
-            None => self.start = start,
-            _ => (),
+    /// Map a device named by [`Device::name`] as its [`Device::kind`]
+    pub fn add_device<D: Device>(
+        &mut self,
+        device: Box<D>,
+        start: u16,
+        end: u16,
+    ) -> Result<DeviceHandle<D>, MapError> {
+        let name = device.name().to_string();
+        let kind = device.kind();
+        self.add_named_device(device, start, end, name, kind)
+    }
+
+    /// Map a device at `start..=end`, later mappings take priority where
+    /// they overlap
+    pub fn add_named_device<D: Device>(
+        &mut self,
+        device: Box<D>,
+        start: u16,
+        end: u16,
+        name: impl Into<String>,
+        kind: DeviceKind,
+    ) -> Result<DeviceHandle<D>, MapError> {
+        let id = self.insert(device, start, end, name.into(), kind)?;
+        Ok(DeviceHandle::new(id))
+    }
+
+    /// Map a device chosen at runtime, named by [`Device::name`] as its
+    /// [`Device::kind`]
+    pub fn add_boxed_device(
+        &mut self,
+        device: Box<dyn Device>,
+        start: u16,
+        end: u16,
+    ) -> Result<DeviceHandle<dyn Device>, MapError> {
+        let name = device.name().to_string();
+        let kind = device.kind();
+        let id = self.insert(device, start, end, name, kind)?;
+        Ok(DeviceHandle::new(id))
+    }
+
+    /// Unmap a device mapped with [`MemoryMapper::add_boxed_device`]
+    pub fn remove_boxed_device(
+        &mut self,
+        handle: DeviceHandle<dyn Device>,
+    ) -> Result<Box<dyn Device>, MapError> {
+        let index = self.index_of(handle.id).ok_or(MapError::Stale)?;
+        Ok(self.regions.remove(index).device)
+    }
+
+    /// Unmap a device and give it back
+    pub fn remove_device<D: Device>(
+        &mut self,
+        handle: DeviceHandle<D>,
+    ) -> Result<Box<D>, MapError> {
+        let index = self.index_of(handle.id).ok_or(MapError::Stale)?;
+        let device: &dyn Any = self.regions[index].device.as_ref();
+        if !device.is::<D>() {
+            return Err(MapError::WrongType);
         }
-        match self.end() {
-            // map ends at new device start
-            Some(x) if end > x => self.end = end,
-            None => self.end = end,
-            _ => (),
+        let device: Box<dyn Any> = self.regions.remove(index).device;
+        Ok(device.downcast().expect("type checked"))
+    }
+
+    /// Swap in a new device at the same addresses, keeping its name and kind
+    pub fn replace_device<D: Device + ?Sized, N: Device>(
+        &mut self,
+        handle: DeviceHandle<D>,
+        device: Box<N>,
+    ) -> Result<DeviceHandle<N>, MapError> {
+        let index = self.index_of(handle.id).ok_or(MapError::Stale)?;
+        let id = self.next_id();
+        let region = &mut self.regions[index];
+        region.device = device;
+        region.id = id;
+        Ok(DeviceHandle::new(id))
+    }
+
+    /// Move a device to start at `start`, keeping its size
+    ///
+    /// Overlaps are allowed, as with [`MemoryMapper::add_named_device`]. The
+    /// device keeps the priority it was mapped with, so it still hides
+    /// devices mapped before it and is hidden by devices mapped after it.
+    /// Mirrors stay where they are.
+    pub fn remap<D: Device + ?Sized>(
+        &mut self,
+        handle: DeviceHandle<D>,
+        start: u16,
+    ) -> Result<DeviceHandle<D>, MapError> {
+        let index = self.index_of(handle.id).ok_or(MapError::Stale)?;
+        let region = &mut self.regions[index];
+        region.end = start
+            .checked_add(region.end - region.start)
+            .ok_or(MapError::Bounds(start))?;
+        region.start = start;
+        Ok(handle)
+    }
+
+    /// Also map a device at `start..=end`, sharing its contents
+    pub fn add_mirror<D: Device + ?Sized>(
+        &mut self,
+        handle: DeviceHandle<D>,
+        start: u16,
+        end: u16,
+    ) -> Result<(), MapError> {
+        let index = self.index_of(handle.id).ok_or(MapError::Stale)?;
+        if start > end {
+            return Err(MapError::Inverted(start, end));
         }
-        // insert is expensive but saves on reversing vector when finding regions
-        self.regions.insert(0, Region { device, start, end });
+        self.regions[index].mirrors.push((start, end));
+        Ok(())
+    }
+
+    /// Mask offsets into a device, so it repeats across its ranges
+    ///
+    /// A 2 KiB device mapped over 8 KiB with mask `0x07ff` appears four
+    /// times. Offsets are relative to the start of each range.
+    pub fn set_mask<D: Device + ?Sized>(
+        &mut self,
+        handle: DeviceHandle<D>,
+        mask: u16,
+    ) -> Result<(), MapError> {
+        let index = self.index_of(handle.id).ok_or(MapError::Stale)?;
+        self.regions[index].mask = mask;
+        Ok(())
+    }
+
+    /// Get a mapped device to inspect it from the host
+    pub fn device<D: Device>(&self, handle: DeviceHandle<D>) -> Option<&D> {
+        let index = self.index_of(handle.id)?;
+        let device: &dyn Any = self.regions[index].device.as_ref();
+        device.downcast_ref()
+    }
+
+    pub fn device_mut<D: Device>(&mut self, handle: DeviceHandle<D>) -> Option<&mut D> {
+        let index = self.index_of(handle.id)?;
+        let device: &mut dyn Any = self.regions[index].device.as_mut();
+        device.downcast_mut()
     }
 
     pub fn start(&self) -> Option<u16> {
-        match self.regions.len() {
-            0 => None,
-            _ => Some(self.start),
-        }
+        let ranges = self.regions.iter().flat_map(Region::ranges);
+        ranges.map(|(start, _)| start).min()
     }
 
     pub fn end(&self) -> Option<u16> {
-        match self.regions.len() {
-            0 => None,
-            _ => Some(self.end),
+        let ranges = self.regions.iter().flat_map(Region::ranges);
+        ranges.map(|(_, end)| end).max()
+    }
+
+    /// Export the layout and contents of every mapped device
+    ///
+    /// Devices which [skip snapshots](Device::skip_snapshot) are exported
+    /// without contents. Fails listing the other devices which do not
+    /// implement [`Device::export_state`].
+    pub fn export_regions(&self) -> Result<Vec<RegionState>, MapError> {
+        let mut states = Vec::with_capacity(self.regions.len());
+        let mut unsaved = Vec::new();
+        for region in &self.regions {
+            let state = if region.device.skip_snapshot() {
+                None
+            } else {
+                match region.device.export_state() {
+                    Some(state) => Some(state),
+                    None => {
+                        unsaved.push(region.name.clone());
+                        continue;
+                    }
+                }
+            };
+            states.push(RegionState {
+                name: region.name.clone(),
+                kind: region.kind,
+                start: region.start,
+                end: region.end,
+                mask: region.mask,
+                mirrors: region.mirrors.clone(),
+                state,
+            });
+        }
+        if !unsaved.is_empty() {
+            return Err(MapError::NoState(unsaved));
+        }
+        Ok(states)
+    }
+
+    /// Restore contents from [`MemoryMapper::export_regions`]
+    ///
+    /// The mapper must have the same layout as when the state was exported.
+    /// Regions exported without contents are left as they are.
+    pub fn import_regions(&mut self, states: &[RegionState]) -> Result<(), DeviceError> {
+        if !self.has_layout(states) {
+            return Err(DeviceError::InvalidState);
+        }
+        for (region, region_state) in self.regions.iter_mut().zip(states) {
+            if let Some(state) = &region_state.state {
+                region.device.import_state(state)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Check regions are mapped exactly as in `states`
+    ///
+    /// Names, kinds, address ranges, mirrors and masks must all match.
+    pub fn has_layout(&self, states: &[RegionState]) -> bool {
+        self.regions.len() == states.len()
+            && self.regions.iter().zip(states).all(|(region, state)| {
+                region.name == state.name
+                    && region.kind == state.kind
+                    && region.start == state.start
+                    && region.end == state.end
+                    && region.mask == state.mask
+                    && region.mirrors == state.mirrors
+            })
+    }
+
+    /// Record every byte access until observing is turned off
+    pub fn observe(&mut self, observing: bool) {
+        self.observing = observing;
+        if !observing {
+            self.accesses.get_mut().clear();
+        }
+    }
+
+    pub fn is_observing(&self) -> bool {
+        self.observing
+    }
+
+    /// Take all accesses recorded since the last call
+    pub fn take_accesses(&self) -> Vec<Access> {
+        self.accesses.take()
+    }
+
+    /// Count accesses to every address until collecting is turned off
+    ///
+    /// Accesses made from the host count too, dumps do not.
+    pub fn collect_stats(&mut self, collecting: bool) {
+        let stats = self.stats.get_mut();
+        if !collecting {
+            *stats = None;
+        } else if stats.is_none() {
+            *stats = Some(AccessStats::new());
+        }
+    }
+
+    /// Counts so far, `None` unless collecting
+    pub fn stats(&self) -> Option<Ref<'_, AccessStats>> {
+        Ref::filter_map(self.stats.borrow(), Option::as_ref).ok()
+    }
+
+    /// Stop collecting and return the counts
+    pub fn take_stats(&mut self) -> Option<AccessStats> {
+        self.stats.get_mut().take()
+    }
+
+    /// Get a byte as an instruction fetch
+    pub fn fetch(&self, addr: u16) -> Result<u8, DeviceError> {
+        self.read(addr, AccessKind::Fetch)
+    }
+
+    /// Count `len` bytes from `addr` as fetched in the stats only
+    ///
+    /// Nothing is read, and no accesses are recorded for observers.
+    pub fn count_fetch(&self, addr: u16, len: u16) {
+        if let Some(stats) = self.stats.borrow_mut().as_mut() {
+            for offset in 0..len {
+                stats.record(AccessKind::Fetch, addr.wrapping_add(offset));
+            }
         }
     }
 
-    fn find_region(&self, addr: u16) -> Option<&Region> {
+    /// Get a wide value as an instruction fetch
+    pub fn fetch_wide(&self, addr: u16) -> Result<u16, DeviceError> {
+        self.read_wide(addr, AccessKind::Fetch)
+    }
+
+    /// Mapped devices ordered by start address
+    ///
+    /// Where regions overlap, the one listed first takes priority.
+    pub fn regions(&self) -> Vec<RegionInfo<'_>> {
+        let mut regions: Vec<_> = self.regions.iter().flat_map(Region::infos).collect();
+        regions.sort_by_key(|region| region.start);
+        regions
+    }
+
+    /// Region that handles `addr`
+    pub fn region_at(&self, addr: u16) -> Option<RegionInfo<'_>> {
+        let (region, _) = self.find_region(addr)?;
+        region
+            .infos()
+            .find(|info| (info.start..=info.end).contains(&addr))
+    }
+
+    /// Name of the device mapped at `addr`
+    pub fn device_name(&self, addr: u16) -> Option<&str> {
+        let (region, _) = self.find_region(addr)?;
+        Some(&region.name)
+    }
+
+    /// Whether the byte at `addr` can also be reached at other addresses
+    pub fn is_aliased(&self, addr: u16) -> bool {
+        self.find_region(addr)
+            .is_some_and(|(region, _)| !region.mirrors.is_empty() || region.mask != u16::MAX)
+    }
+
+    /// Get a byte without recording an access
+    pub fn peek(&self, addr: u16) -> Option<u8> {
+        let (region, offset) = self.find_region(addr)?;
+        region.device.get(offset).ok()
+    }
+
+    /// Set a byte without recording an access, for debuggers
+    pub fn poke(&mut self, addr: u16, value: u8) -> Result<(), DeviceError> {
+        let (region, offset) = self
+            .find_region_mut(addr)
+            .ok_or(DeviceError::OutOfBounds(addr))?;
+        region
+            .device
+            .set(offset, value)
+            .map_err(|err| region.fault(addr, err))
+    }
+
+    /// Put back a byte without recording an access
+    ///
+    /// IO devices are skipped, replaying a write could have side effects.
+    pub fn undo_write(&mut self, addr: u16, previous: u8) -> Result<(), DeviceError> {
+        if self.find_region(addr).map(|(region, _)| region.kind) == Some(DeviceKind::Io) {
+            return Ok(());
+        }
+        self.poke(addr, previous)
+    }
+
+    fn read(&self, addr: u16, kind: AccessKind) -> Result<u8, DeviceError> {
+        if let Some((region, offset)) = self.find_region(addr) {
+            let value = region.device.get(offset);
+            let value = value.map_err(|err| region.fault(addr, err))?;
+            self.record(kind, addr, value, value);
+            Ok(value)
+        } else {
+            Err(DeviceError::OutOfBounds(addr))
+        }
+    }
+
+    fn read_wide(&self, addr: u16, kind: AccessKind) -> Result<u16, DeviceError> {
+        if let Some((region, offset)) = self.find_region(addr) {
+            let value = region.get_wide(offset);
+            let value = value.map_err(|err| region.fault(addr, err))?;
+            self.record_wide(kind, addr, value, value);
+            Ok(value)
+        } else {
+            Err(DeviceError::OutOfBounds(addr))
+        }
+    }
+
+    fn record(&self, kind: AccessKind, addr: u16, value: u8, previous: u8) {
+        if let Some(stats) = self.stats.borrow_mut().as_mut() {
+            stats.record(kind, addr);
+        }
+        if self.observing {
+            self.accesses.borrow_mut().push(Access {
+                kind,
+                addr,
+                value,
+                previous,
+            });
+        }
+    }
+
+    fn record_wide(&self, kind: AccessKind, addr: u16, value: u16, previous: u16) {
+        let (high, low) = high_and_low_value(value);
+        let (prev_high, prev_low) = high_and_low_value(previous);
+        self.record(kind, addr, high, prev_high);
+        self.record(kind, addr.wrapping_add(1), low, prev_low);
+    }
+
+    /// Map a device with the highest priority, returning its id
+    fn insert(
+        &mut self,
+        device: Box<dyn Device>,
+        start: u16,
+        end: u16,
+        name: String,
+        kind: DeviceKind,
+    ) -> Result<u64, MapError> {
+        if start > end {
+            return Err(MapError::Inverted(start, end));
+        }
+        let id = self.next_id();
+        // insert is expensive but saves on reversing vector when finding regions
+        self.regions.insert(
+            0,
+            Region {
+                device,
+                id,
+                name,
+                kind,
+                start,
+                end,
+                mask: u16::MAX,
+                mirrors: Vec::new(),
+            },
+        );
+        Ok(id)
+    }
+
+    fn next_id(&mut self) -> u64 {
+        let id = self.next_id;
+        self.next_id += 1;
+        id
+    }
+
+    fn index_of(&self, id: u64) -> Option<usize> {
+        self.regions.iter().position(|region| region.id == id)
+    }
+
+    /// Region handling `addr` and the device offset
+    fn find_region(&self, addr: u16) -> Option<(&Region, u16)> {
         self.regions
             .iter()
-            .find(|region| is_addr_in_region(addr, region))
+            .find_map(|region| Some((region, region.offset(addr)?)))
     }
 
-    fn find_region_mut(&mut self, addr: u16) -> Option<&mut Region> {
+    fn find_region_mut(&mut self, addr: u16) -> Option<(&mut Region, u16)> {
         self.regions
             .iter_mut()
-            .find(|region| is_addr_in_region(addr, region))
+            .find_map(|region| region.offset(addr).map(|offset| (region, offset)))
     }
 }
 
 impl Device for MemoryMapper {
     fn set(&mut self, addr: u16, data: u8) -> Result<(), DeviceError> {
-        if let Some(region) = self.find_region_mut(addr) {
-            region.device.set(addr - region.start, data)
+        let observing = self.observing;
+        if let Some((region, offset)) = self.find_region_mut(addr) {
+            let previous = if observing {
+                region.device.get(offset).unwrap_or(data)
+            } else {
+                data
+            };
+            region
+                .device
+                .set(offset, data)
+                .map_err(|err| region.fault(addr, err))?;
+            self.record(AccessKind::Write, addr, data, previous);
+            Ok(())
         } else {
             Err(DeviceError::OutOfBounds(addr))
         }
     }
 
     fn get(&self, addr: u16) -> Result<u8, DeviceError> {
-        if let Some(region) = self.find_region(addr) {
-            region.device.get(addr - region.start)
-        } else {
-            Err(DeviceError::OutOfBounds(addr))
-        }
+        self.read(addr, AccessKind::Read)
     }
 
     fn set_wide(&mut self, addr: u16, data: u16) -> Result<(), DeviceError> {
-        if let Some(region) = self.find_region_mut(addr) {
-            region.device.set_wide(addr - region.start, data)
+        let observing = self.observing;
+        if let Some((region, offset)) = self.find_region_mut(addr) {
+            let previous = if observing {
+                region.get_wide(offset).unwrap_or(data)
+            } else {
+                data
+            };
+            region
+                .set_wide(offset, data)
+                .map_err(|err| region.fault(addr, err))?;
+            self.record_wide(AccessKind::Write, addr, data, previous);
+            Ok(())
         } else {
             Err(DeviceError::OutOfBounds(addr))
         }
     }
 
     fn get_wide(&self, addr: u16) -> Result<u16, DeviceError> {
-        if let Some(region) = self.find_region(addr) {
-            region.device.get_wide(addr - region.start)
-        } else {
-            Err(DeviceError::OutOfBounds(addr))
+        self.read_wide(addr, AccessKind::Read)
+    }
+}
+
+impl fmt::Display for MemoryMapper {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let regions = self.regions();
+        let width = regions.iter().map(|region| region.name.len()).max();
+        for region in regions {
+            write!(
+                f,
+                "{:#06x}-{:#06x} {:width$} {}",
+                region.start,
+                region.end,
+                region.name,
+                region.kind,
+                width = width.unwrap_or_default()
+            )?;
+            if region.mirror {
+                write!(f, " (mirror)")?;
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for DeviceKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let kind = match self {
+            DeviceKind::Ram => "RAM",
+            DeviceKind::Rom => "ROM",
+            DeviceKind::Io => "IO",
+        };
+        write!(f, "{}", kind)
+    }
+}
+
+impl<D: ?Sized> DeviceHandle<D> {
+    fn new(id: u64) -> Self {
+        Self {
+            id,
+            device: PhantomData,
         }
     }
 }
 
-#[derive(custom_debug::Debug)]
+impl<D: ?Sized> Clone for DeviceHandle<D> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<D: ?Sized> Copy for DeviceHandle<D> {}
+
+impl<D: ?Sized> PartialEq for DeviceHandle<D> {
+    fn eq(&self, other: &Self) -> bool {
+        self.id == other.id
+    }
+}
+
+impl<D: ?Sized> Eq for DeviceHandle<D> {}
+
+impl<D: ?Sized> fmt::Debug for DeviceHandle<D> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("DeviceHandle").field(&self.id).finish()
+    }
+}
+
 struct Region {
-    #[debug(skip)]
     device: Box<dyn Device>,
+    id: u64,
+    name: String,
+    kind: DeviceKind,
     start: u16,
     end: u16,
+    /// Applied to offsets from the start of a range
+    mask: u16,
+    mirrors: Vec<(u16, u16)>,
 }
 
-fn is_addr_in_region(addr: u16, region: &Region) -> bool {
-    addr >= region.start && addr <= region.end
+impl fmt::Debug for Region {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Region")
+            .field("id", &self.id)
+            .field("name", &self.name)
+            .field("kind", &self.kind)
+            .field("start", &self.start)
+            .field("end", &self.end)
+            .field("mask", &self.mask)
+            .field("mirrors", &self.mirrors)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Region {
+    /// Mapped ranges, the original range first
+    fn ranges(&self) -> impl Iterator<Item = (u16, u16)> + '_ {
+        iter::once((self.start, self.end)).chain(self.mirrors.iter().copied())
+    }
+
+    fn infos(&self) -> impl Iterator<Item = RegionInfo<'_>> {
+        self.ranges()
+            .enumerate()
+            .map(|(index, (start, end))| RegionInfo {
+                name: &self.name,
+                kind: self.kind,
+                start,
+                end,
+                mirror: index > 0,
+            })
+    }
+
+    fn offset(&self, addr: u16) -> Option<u16> {
+        let (start, _) = self
+            .ranges()
+            .find(|&(start, end)| (start..=end).contains(&addr))?;
+        Some((addr - start) & self.mask)
+    }
+
+    /// Masked devices are accessed a byte at a time, so wide values wrap
+    fn get_wide(&self, offset: u16) -> Result<u16, DeviceError> {
+        if self.mask == u16::MAX {
+            return self.device.get_wide(offset);
+        }
+        let high = self.device.get(offset)?;
+        let low = self.device.get(offset.wrapping_add(1) & self.mask)?;
+        Ok(wide_value(high, low))
+    }
+
+    fn set_wide(&mut self, offset: u16, data: u16) -> Result<(), DeviceError> {
+        if self.mask == u16::MAX {
+            return self.device.set_wide(offset, data);
+        }
+        let (high, low) = high_and_low_value(data);
+        self.device.set(offset, high)?;
+        self.device.set(offset.wrapping_add(1) & self.mask, low)
+    }
+
+    /// Name the device in errors, out of bounds uses the mapped address
+    fn fault(&self, addr: u16, err: DeviceError) -> DeviceError {
+        match err {
+            DeviceError::OutOfBounds(_) => DeviceError::OutOfBounds(addr),
+            err => DeviceError::Fault {
+                device: self.name.clone(),
+                addr,
+                source: Box::new(err),
+            },
+        }
+    }
 }
 
 #[cfg(test)]
@@ -177,6 +800,387 @@ mod tests {
         assert_eq!(value, actual);
     }
 
+    #[test]
+    fn mapper_not_observing_records_nothing() {
+        let mut mapper = test_mapper_with_device_at(0);
+        mapper.set(1, 1).expect("valid address");
+        mapper.get(1).expect("valid address");
+        assert!(mapper.take_accesses().is_empty());
+    }
+
+    #[test]
+    fn mapper_observing_records_accesses() {
+        let mut mapper = test_mapper_with_device_at(0);
+        mapper.observe(true);
+        mapper.set(1, 0xab).expect("valid address");
+        mapper.get(1).expect("valid address");
+        mapper.fetch(2).expect("valid address");
+        let expected = [
+            Access {
+                kind: AccessKind::Write,
+                addr: 1,
+                value: 0xab,
+                previous: 0,
+            },
+            Access {
+                kind: AccessKind::Read,
+                addr: 1,
+                value: 0xab,
+                previous: 0xab,
+            },
+            Access {
+                kind: AccessKind::Fetch,
+                addr: 2,
+                value: 0,
+                previous: 0,
+            },
+        ];
+        assert_eq!(expected.as_slice(), mapper.take_accesses());
+        assert!(mapper.take_accesses().is_empty());
+    }
+
+    #[test]
+    fn mapper_observing_records_wide_as_bytes() {
+        let mut mapper = test_mapper_with_device_at(0);
+        mapper.observe(true);
+        mapper.set_wide(1, 0x0102).expect("valid address");
+        mapper.set_wide(1, 0x0304).expect("valid address");
+        let actual: Vec<_> = mapper
+            .take_accesses()
+            .into_iter()
+            .map(|access| (access.addr, access.value, access.previous))
+            .collect();
+        let expected = vec![(1, 0x01, 0), (2, 0x02, 0), (1, 0x03, 0x01), (2, 0x04, 0x02)];
+        assert_eq!(expected, actual);
+    }
+
+    #[test]
+    fn mapper_observing_skips_failed_accesses() {
+        let mut mapper = test_mapper_with_device_at(0);
+        mapper.observe(true);
+        mapper
+            .get(TEST_DEVICE_SIZE)
+            .expect_err("out of bounds error");
+        assert!(mapper.take_accesses().is_empty());
+    }
+
+    #[test]
+    fn mapper_peek_poke_not_recorded() {
+        let mut mapper = test_mapper_with_device_at(0);
+        mapper.observe(true);
+        mapper.collect_stats(true);
+        mapper.poke(1, 0xab).expect("valid address");
+        assert_eq!(Some(0xab), mapper.peek(1));
+        mapper
+            .poke(TEST_DEVICE_SIZE, 0)
+            .expect_err("out of bounds error");
+        assert!(mapper.take_accesses().is_empty());
+        let stats = mapper.stats().expect("collecting").total();
+        assert_eq!((0, 0), (stats.reads, stats.writes));
+    }
+
+    #[test]
+    fn mapper_export_import_regions() {
+        let mut mapper = test_mapper_with_set_device_at(0, 1, 0xab);
+        let states = mapper.export_regions().expect("devices have state");
+        assert_eq!(1, states.len());
+        assert_eq!((0, TEST_DEVICE_SIZE - 1), (states[0].start, states[0].end));
+        mapper.set(1, 0).expect("valid address");
+        mapper.import_regions(&states).expect("same layout");
+        assert_eq!(0xab, mapper.get(1).expect("valid address"));
+    }
+
+    #[test]
+    fn mapper_import_regions_wrong_layout() {
+        let mut mapper = test_mapper_with_device_at(1);
+        let states = test_mapper_with_device_at(0)
+            .export_regions()
+            .expect("devices have state");
+        let err = mapper
+            .import_regions(&states)
+            .expect_err("different layout");
+        assert!(matches!(err, DeviceError::InvalidState));
+    }
+
+    #[test]
+    fn mapper_layout_includes_mirrors_and_masks() {
+        let mut mapper = test_mapper_with_device_at(0);
+        let states = mapper.export_regions().expect("devices have state");
+        let device = mapper
+            .add_device(Box::new(TestDevice::new(0x10)), 0x8000, 0x800f)
+            .expect("valid range");
+        let with_device = mapper.export_regions().expect("devices have state");
+        mapper.add_mirror(device, 0x9000, 0x900f).expect("mapped");
+        assert!(!mapper.has_layout(&states));
+        assert!(!mapper.has_layout(&with_device));
+        let with_mirror = mapper.export_regions().expect("devices have state");
+        mapper.set_mask(device, 0x07).expect("mapped");
+        assert!(!mapper.has_layout(&with_mirror));
+    }
+
+    #[test]
+    fn mapper_export_regions_lists_devices_without_state() {
+        let mut mapper = test_mapper_with_device_at(0);
+        mapper
+            .add_named_device(
+                Box::new(FaultyDevice),
+                0x8000,
+                0x80ff,
+                "uart",
+                DeviceKind::Io,
+            )
+            .expect("valid range");
+        let err = mapper.export_regions().expect_err("no state");
+        assert_eq!(MapError::NoState(vec!["uart".to_string()]), err);
+    }
+
+    #[test]
+    fn mapper_device_name() {
+        let mapper = test_mapper_with_device_at(1);
+        assert_eq!(Some("TestDevice"), mapper.device_name(1));
+        assert_eq!(None, mapper.device_name(0));
+    }
+
+    #[test]
+    fn mapper_regions_listing() {
+        let mut mapper = test_mapper_with_device_at(0x10);
+        mapper
+            .add_named_device(test_device(4), 0, 3, "boot_rom", DeviceKind::Rom)
+            .expect("valid range");
+        let regions = mapper.regions();
+        assert_eq!("boot_rom", regions[0].name);
+        assert_eq!(DeviceKind::Rom, regions[0].kind);
+        assert_eq!(Some(regions[1]), mapper.region_at(0x11));
+        let expected = "\
+0x0000-0x0003 boot_rom   ROM
+0x0010-0x0017 TestDevice RAM
+";
+        assert_eq!(expected, mapper.to_string());
+    }
+
+    #[test]
+    fn mapper_add_device_uses_kind() {
+        let mut mapper = test_mapper_with_device_at(0x10);
+        mapper
+            .add_device(Box::new(FaultyDevice), 0, 3)
+            .expect("valid range");
+        let regions = mapper.regions();
+        assert_eq!(DeviceKind::Io, regions[0].kind);
+        assert_eq!(DeviceKind::Ram, regions[1].kind);
+    }
+
+    #[test]
+    fn mapper_errors_name_device() {
+        let mut mapper = MemoryMapper::new();
+        mapper
+            .add_named_device(Box::new(FaultyDevice), 4, 8, "uart", DeviceKind::Io)
+            .expect("valid range");
+        let err = mapper.set(5, 1).expect_err("device error");
+        assert_eq!("uart at 0x0005: unkown error", err.to_string());
+        let err = mapper.get(6).expect_err("device error");
+        assert_device_error_is_out_of_bounds(err, 6);
+    }
+
+    #[test]
+    fn mapper_typed_device_access() {
+        let mut mapper = MemoryMapper::new();
+        let handle = mapper
+            .add_device(test_device(4), 0, 3)
+            .expect("valid range");
+        mapper.set(1, 0xab).expect("valid address");
+        let device = mapper.device(handle).expect("mapped");
+        assert_eq!(0xab, device.get(1).expect("valid address"));
+        let device = mapper.device_mut(handle).expect("mapped");
+        device.set(2, 0xcd).expect("valid address");
+        assert_eq!(0xcd, mapper.get(2).expect("valid address"));
+    }
+
+    #[test]
+    fn mapper_remove_device() {
+        let mut mapper = test_mapper_with_device_at(0x10);
+        let rom = mapper
+            .add_named_device(test_device(4), 0x10, 0x13, "rom", DeviceKind::Rom)
+            .expect("valid range");
+        let device = mapper.remove_device(rom).expect("mapped");
+        assert_eq!(4, device.size());
+        assert_eq!(Some("TestDevice"), mapper.device_name(0x10));
+        assert_eq!(Err(MapError::Stale), mapper.remove_device(rom).map(|_| ()));
+        assert!(mapper.device(rom).is_none());
+    }
+
+    #[test]
+    fn mapper_remove_device_wrong_type() {
+        let mut other = MemoryMapper::new();
+        let handle = other.add_device(test_device(4), 0, 3).expect("valid range");
+        let mut mapper = MemoryMapper::new();
+        mapper
+            .add_device(Box::new(FaultyDevice), 0, 3)
+            .expect("valid range");
+        let err = mapper.remove_device(handle).map(|_| ());
+        assert_eq!(Err(MapError::WrongType), err);
+        assert!(mapper.region_at(0).is_some());
+    }
+
+    #[test]
+    fn mapper_rejects_inverted_ranges() {
+        let mut mapper = MemoryMapper::new();
+        let err = mapper.add_device(test_device(4), 3, 0).map(|_| ());
+        assert_eq!(Err(MapError::Inverted(3, 0)), err);
+        assert!(mapper.regions().is_empty());
+        let handle = mapper
+            .add_device(test_device(4), 0, 3)
+            .expect("valid range");
+        let err = mapper.add_mirror(handle, 0x13, 0x10);
+        assert_eq!(Err(MapError::Inverted(0x13, 0x10)), err);
+    }
+
+    #[test]
+    fn mapper_remove_updates_bounds() {
+        let mut mapper = test_mapper_with_device_at(0x10);
+        let low = mapper
+            .add_device(test_device(4), 0, 3)
+            .expect("valid range");
+        assert_eq!(Some(0), mapper.start());
+        mapper.remove_device(low).expect("mapped");
+        assert_eq!(Some(0x10), mapper.start());
+    }
+
+    #[test]
+    fn mapper_replace_device() {
+        let mut mapper = MemoryMapper::new();
+        let old = mapper
+            .add_named_device(test_device(4), 0, 3, "rom", DeviceKind::Rom)
+            .expect("valid range");
+        let new = mapper
+            .replace_device(old, Box::new(FaultyDevice))
+            .expect("mapped");
+        assert!(mapper.device(old).is_none());
+        assert!(mapper.device(new).is_some());
+        let region = mapper.region_at(0).expect("mapped");
+        assert_eq!(
+            ("rom", DeviceKind::Rom, 3),
+            (region.name, region.kind, region.end)
+        );
+    }
+
+    #[test]
+    fn mapper_remap_device() {
+        let mut mapper = MemoryMapper::new();
+        let handle = mapper
+            .add_device(test_device(4), 0, 3)
+            .expect("valid range");
+        mapper.set(0, 0xab).expect("valid address");
+        let handle = mapper.remap(handle, 0x100).expect("fits");
+        assert_eq!(0xab, mapper.get(0x100).expect("valid address"));
+        assert!(mapper.get(0).is_err());
+        assert_eq!((Some(0x100), Some(0x103)), (mapper.start(), mapper.end()));
+        assert_eq!(Err(MapError::Bounds(0xfffe)), mapper.remap(handle, 0xfffe));
+    }
+
+    #[test]
+    fn mapper_remap_keeps_priority() {
+        let mut mapper = MemoryMapper::new();
+        let low = mapper
+            .add_device(test_device(4), 0, 3)
+            .expect("valid range");
+        let high = mapper
+            .add_device(test_device(4), 0x10, 0x13)
+            .expect("valid range");
+        mapper.set(0x10, 0xab).expect("valid address");
+        mapper.remap(high, 0x02).expect("fits");
+        assert_eq!(0xab, mapper.get(0x02).expect("valid address"));
+        mapper.remap(high, 0x10).expect("fits");
+        mapper.remap(low, 0x12).expect("fits");
+        mapper.set(0x12, 0xcd).expect("valid address");
+        let high = mapper.remove_device(high).expect("mapped");
+        assert_eq!(0xcd, high.get(2).expect("valid address"));
+    }
+
+    #[test]
+    fn mapper_boxed_device() {
+        let mut mapper = MemoryMapper::new();
+        let device: Box<dyn Device> = Box::new(FaultyDevice);
+        let handle = mapper.add_boxed_device(device, 0, 3).expect("valid range");
+        assert_eq!(DeviceKind::Io, mapper.regions()[0].kind);
+        assert_eq!(Some("FaultyDevice"), mapper.device_name(0));
+        let handle = mapper.remap(handle, 0x10).expect("fits");
+        let device = mapper.remove_boxed_device(handle).expect("mapped");
+        assert_eq!("FaultyDevice", device.name());
+        assert_eq!(None, mapper.start());
+    }
+
+    #[test]
+    fn mapper_mirror_shares_device() {
+        let mut mapper = MemoryMapper::new();
+        let ram = mapper
+            .add_device(test_device(4), 0, 3)
+            .expect("valid range");
+        mapper.add_mirror(ram, 0x10, 0x13).expect("mapped");
+        mapper.add_mirror(ram, 0x20, 0x21).expect("mapped");
+        mapper.set(0x11, 0xab).expect("valid address");
+        assert_eq!(0xab, mapper.get(1).expect("valid address"));
+        assert_eq!(0xab, mapper.get(0x21).expect("valid address"));
+        assert_eq!(Some(0x21), mapper.end());
+        let mirror = mapper.region_at(0x12).expect("mapped");
+        assert!(mirror.mirror);
+        assert!(mapper.is_aliased(0));
+        assert_eq!((0x10, 0x13), (mirror.start, mirror.end));
+        assert!(mapper
+            .to_string()
+            .contains("0x0010-0x0013 TestDevice RAM (mirror)"));
+    }
+
+    #[test]
+    fn mapper_mask_repeats_device() {
+        let mut mapper = MemoryMapper::new();
+        let ram = mapper
+            .add_device(test_device(4), 0, 0x0f)
+            .expect("valid range");
+        assert!(!mapper.is_aliased(0));
+        mapper.set_mask(ram, 0x03).expect("mapped");
+        mapper.set(0x0e, 0xab).expect("valid address");
+        assert_eq!(0xab, mapper.get(0x02).expect("valid address"));
+        assert_eq!(0xab, mapper.get(0x06).expect("valid address"));
+        mapper.set_wide(0x07, 0x1234).expect("valid address");
+        assert_eq!(0x34, mapper.get(0x00).expect("wraps in device"));
+        assert_eq!(0x1234, mapper.get_wide(0x0b).expect("valid address"));
+    }
+
+    #[test]
+    fn mapper_mirror_stale_handle() {
+        let mut mapper = MemoryMapper::new();
+        let ram = mapper
+            .add_device(test_device(4), 0, 3)
+            .expect("valid range");
+        mapper.remove_device(ram).expect("mapped");
+        assert_eq!(Err(MapError::Stale), mapper.add_mirror(ram, 4, 7));
+        assert_eq!(Err(MapError::Stale), mapper.set_mask(ram, 3));
+    }
+
+    struct FaultyDevice;
+
+    impl Device for FaultyDevice {
+        fn set(&mut self, _addr: u16, _data: u8) -> Result<(), DeviceError> {
+            Err(DeviceError::Other)
+        }
+
+        fn get(&self, addr: u16) -> Result<u8, DeviceError> {
+            Err(DeviceError::OutOfBounds(addr))
+        }
+
+        fn set_wide(&mut self, _addr: u16, _data: u16) -> Result<(), DeviceError> {
+            Err(DeviceError::Other)
+        }
+
+        fn get_wide(&self, addr: u16) -> Result<u16, DeviceError> {
+            Err(DeviceError::OutOfBounds(addr))
+        }
+
+        fn kind(&self) -> DeviceKind {
+            DeviceKind::Io
+        }
+    }
+
     device_tests!(mapper_simple, || test_mapper_with_device_at(0));
     device_tests!(mapper_offset, || test_mapper_with_device_at(1));
 
@@ -188,7 +1192,9 @@ mod tests {
         let mut mapper = MemoryMapper::new();
         let mut device = test_device(TEST_DEVICE_SIZE);
         device.set(set_addr, set_val).unwrap();
-        mapper.add_device(device, offset, TEST_DEVICE_SIZE - 1 + offset);
+        mapper
+            .add_device(device, offset, TEST_DEVICE_SIZE - 1 + offset)
+            .expect("valid range");
         mapper
     }
 
@@ -1,9 +1,17 @@
+mod bus;
 mod device;
+mod dump;
 mod mapper;
+mod stats;
 
 #[cfg(test)]
 pub use device::tests::TestDevice;
 
+pub use bus::Bus;
 pub use device::ram::*;
 pub use device::{Device, Error as DeviceError};
-pub use mapper::MemoryMapper;
+pub use dump::DumpFormat;
+pub use mapper::{
+    Access, AccessKind, DeviceHandle, DeviceKind, MapError, MemoryMapper, RegionInfo, RegionState,
+};
+pub use stats::{AccessCounts, AccessStats, Granularity};
@@ -0,0 +1,257 @@
+use super::AccessKind;
+use std::fmt;
+use std::io;
+use std::ops::RangeInclusive;
+
+const PAGE_SIZE: usize = 256;
+
+/// Per address access counts, see [`MemoryMapper::collect_stats`]
+///
+/// Instructions taken from the CPU's decode cache are counted as fetched,
+/// although they are not read again.
+///
+/// [`MemoryMapper::collect_stats`]: super::MemoryMapper::collect_stats
+#[derive(Clone, PartialEq, Eq)]
+pub struct AccessStats {
+    counts: Vec<AccessCounts>,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct AccessCounts {
+    pub reads: u64,
+    pub writes: u64,
+    /// Instruction fetches by the CPU
+    pub fetches: u64,
+}
+
+/// Whether exports have an entry per address or per 256 byte page
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Granularity {
+    Address,
+    Page,
+}
+
+impl AccessCounts {
+    pub fn total(&self) -> u64 {
+        self.reads + self.writes + self.fetches
+    }
+
+    fn add(&mut self, other: &AccessCounts) {
+        self.reads += other.reads;
+        self.writes += other.writes;
+        self.fetches += other.fetches;
+    }
+}
+
+impl AccessStats {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub(super) fn record(&mut self, kind: AccessKind, addr: u16) {
+        let counts = &mut self.counts[addr as usize];
+        match kind {
+            AccessKind::Fetch => counts.fetches += 1,
+            AccessKind::Read => counts.reads += 1,
+            AccessKind::Write => counts.writes += 1,
+        }
+    }
+
+    pub fn at(&self, addr: u16) -> AccessCounts {
+        self.counts[addr as usize]
+    }
+
+    /// Counts summed over the 256 bytes starting at `page * 256`
+    pub fn page(&self, page: u8) -> AccessCounts {
+        let start = page as usize * PAGE_SIZE;
+        sum(&self.counts[start..start + PAGE_SIZE])
+    }
+
+    pub fn total(&self) -> AccessCounts {
+        sum(&self.counts)
+    }
+
+    /// Addresses within `range` accessed at least once
+    pub fn touched(&self, range: RangeInclusive<u16>) -> Vec<u16> {
+        range.filter(|&addr| self.at(addr).total() > 0).collect()
+    }
+
+    /// Up to `count` of the most accessed addresses, busiest first
+    pub fn hottest(&self, count: usize) -> Vec<(u16, AccessCounts)> {
+        let mut hot: Vec<_> = self
+            .entries(Granularity::Address)
+            .filter(|(_, counts)| counts.total() > 0)
+            .collect();
+        hot.sort_by_key(|(addr, counts)| (std::cmp::Reverse(counts.total()), *addr));
+        hot.truncate(count);
+        hot
+    }
+
+    /// CSV with a row for every address or page that was accessed
+    pub fn write_csv<W: io::Write + ?Sized>(
+        &self,
+        out: &mut W,
+        granularity: Granularity,
+    ) -> io::Result<()> {
+        writeln!(out, "addr,reads,writes,fetches")?;
+        for (addr, counts) in self.entries(granularity) {
+            if counts.total() > 0 {
+                writeln!(
+                    out,
+                    "{:#06x},{},{},{}",
+                    addr, counts.reads, counts.writes, counts.fetches
+                )?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Binary PPM heatmap, one pixel per address or page
+    ///
+    /// Rows are 256 addresses or 16 pages wide. Writes are red, fetches
+    /// green and reads blue, each scaled to the busiest entry.
+    pub fn write_ppm<W: io::Write + ?Sized>(
+        &self,
+        out: &mut W,
+        granularity: Granularity,
+    ) -> io::Result<()> {
+        let entries: Vec<_> = self
+            .entries(granularity)
+            .map(|(_, counts)| counts)
+            .collect();
+        let width = match granularity {
+            Granularity::Address => 256,
+            Granularity::Page => 16,
+        };
+        let max = |count: fn(&AccessCounts) -> u64| entries.iter().map(count).max().unwrap_or(0);
+        let max = AccessCounts {
+            reads: max(|counts| counts.reads),
+            writes: max(|counts| counts.writes),
+            fetches: max(|counts| counts.fetches),
+        };
+        write!(out, "P6\n{} {}\n255\n", width, entries.len() / width)?;
+        let mut pixels = Vec::with_capacity(entries.len() * 3);
+        for counts in &entries {
+            pixels.push(scale(counts.writes, max.writes));
+            pixels.push(scale(counts.fetches, max.fetches));
+            pixels.push(scale(counts.reads, max.reads));
+        }
+        out.write_all(&pixels)
+    }
+
+    /// Start address and counts of every address or page
+    fn entries(&self, granularity: Granularity) -> impl Iterator<Item = (u16, AccessCounts)> + '_ {
+        let size = match granularity {
+            Granularity::Address => 1,
+            Granularity::Page => PAGE_SIZE,
+        };
+        self.counts
+            .chunks(size)
+            .enumerate()
+            .map(move |(index, chunk)| ((index * size) as u16, sum(chunk)))
+    }
+}
+
+impl Default for AccessStats {
+    fn default() -> Self {
+        Self {
+            counts: vec![AccessCounts::default(); 0xffff + 1],
+        }
+    }
+}
+
+impl fmt::Debug for AccessStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AccessStats")
+            .field("total", &self.total())
+            .finish_non_exhaustive()
+    }
+}
+
+fn sum(counts: &[AccessCounts]) -> AccessCounts {
+    let mut total = AccessCounts::default();
+    for counts in counts {
+        total.add(counts);
+    }
+    total
+}
+
+fn scale(count: u64, max: u64) -> u8 {
+    (count * 255).checked_div(max).unwrap_or(0) as u8
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memory::{Device, MemoryMapper, TestDevice};
+
+    fn test_stats() -> AccessStats {
+        let mut stats = AccessStats::new();
+        stats.record(AccessKind::Fetch, 0x0000);
+        stats.record(AccessKind::Fetch, 0x0000);
+        stats.record(AccessKind::Read, 0x0001);
+        stats.record(AccessKind::Write, 0x0102);
+        stats
+    }
+
+    #[test]
+    fn stats_counts() {
+        let stats = test_stats();
+        let expected = AccessCounts {
+            reads: 1,
+            writes: 0,
+            fetches: 2,
+        };
+        assert_eq!(expected, stats.page(0));
+        assert_eq!(2, stats.at(0).fetches);
+        assert_eq!(4, stats.total().total());
+        assert_eq!(vec![0x0000, 0x0001], stats.touched(0..=0xff));
+        let hottest: Vec<_> = stats.hottest(2).into_iter().map(|(addr, _)| addr).collect();
+        assert_eq!(vec![0x0000, 0x0001], hottest);
+    }
+
+    #[test]
+    fn stats_csv() {
+        let stats = test_stats();
+        let mut out = Vec::new();
+        stats
+            .write_csv(&mut out, Granularity::Page)
+            .expect("writing");
+        let expected = "addr,reads,writes,fetches\n0x0000,1,0,2\n0x0100,0,1,0\n";
+        assert_eq!(expected, String::from_utf8(out).expect("utf8"));
+    }
+
+    #[test]
+    fn stats_ppm() {
+        let stats = test_stats();
+        let mut out = Vec::new();
+        stats
+            .write_ppm(&mut out, Granularity::Page)
+            .expect("writing");
+        let header = b"P6\n16 16\n255\n";
+        assert!(out.starts_with(header));
+        let pixels = &out[header.len()..];
+        assert_eq!(16 * 16 * 3, pixels.len());
+        assert_eq!([0, 255, 255, 255, 0, 0], pixels[..6]);
+    }
+
+    #[test]
+    fn mapper_collects_stats() {
+        let mut mapper = MemoryMapper::new();
+        mapper
+            .add_device(Box::new(TestDevice::new(4)), 0, 3)
+            .expect("valid range");
+        mapper.get(0).expect("mapped");
+        assert!(mapper.stats().is_none());
+        mapper.collect_stats(true);
+        mapper.fetch_wide(0).expect("mapped");
+        mapper.set(2, 1).expect("mapped");
+        mapper.dump(0..=3, &Default::default());
+        let stats = mapper.stats().expect("collecting");
+        assert_eq!(1, stats.at(1).fetches);
+        assert_eq!(3, stats.total().total());
+        drop(stats);
+        assert!(mapper.take_stats().is_some());
+        assert!(mapper.stats().is_none());
+    }
+}
@@ -0,0 +1,178 @@
+use super::{Object, ObjectError, Relocation, Section, SectionKind, Symbol, UNDEFINED};
+use crate::util::bytes::{push_wide, Reader};
+
+const MAGIC: &[u8; 4] = b"H8OB";
+
+/// Current version of the object binary format
+pub const OBJECT_VERSION: u8 = 1;
+
+impl Object {
+    /// Fails if a section or symbol name is longer than 255 bytes
+    pub fn to_bytes(&self) -> Result<Vec<u8>, ObjectError> {
+        let mut bytes = MAGIC.to_vec();
+        bytes.push(OBJECT_VERSION);
+        match self.entry {
+            Some(entry) => {
+                bytes.push(1);
+                push_wide(&mut bytes, entry);
+            }
+            None => bytes.push(0),
+        }
+        push_wide(&mut bytes, self.sections.len() as u16);
+        for section in &self.sections {
+            push_name(&mut bytes, &section.name)?;
+            bytes.push(section.kind as u8);
+            push_wide(&mut bytes, section.addr);
+            bytes.extend_from_slice(&(section.data.len() as u32).to_be_bytes());
+            bytes.extend_from_slice(&section.data);
+        }
+        push_wide(&mut bytes, self.symbols.len() as u16);
+        for symbol in &self.symbols {
+            push_name(&mut bytes, &symbol.name)?;
+            push_wide(&mut bytes, symbol.section.unwrap_or(UNDEFINED));
+            push_wide(&mut bytes, symbol.offset);
+            bytes.push(symbol.global as u8);
+        }
+        push_wide(&mut bytes, self.relocations.len() as u16);
+        for reloc in &self.relocations {
+            push_wide(&mut bytes, reloc.section);
+            push_wide(&mut bytes, reloc.offset);
+            push_wide(&mut bytes, reloc.symbol);
+            bytes.extend_from_slice(&reloc.addend.to_be_bytes());
+        }
+        Ok(bytes)
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, ObjectError> {
+        let mut reader = Reader::new(bytes);
+        if reader.take(MAGIC.len())? != MAGIC {
+            return Err(ObjectError::BadMagic);
+        }
+        match reader.byte()? {
+            OBJECT_VERSION => (),
+            version => return Err(ObjectError::Version(version)),
+        }
+        let entry = match reader.byte()? {
+            0 => None,
+            _ => Some(reader.wide()?),
+        };
+        let mut sections = Vec::new();
+        for _ in 0..reader.wide()? {
+            let name = read_name(&mut reader)?;
+            let kind = reader.byte()?;
+            let kind = SectionKind::from_repr(kind).ok_or(ObjectError::SectionKind(kind))?;
+            let addr = reader.wide()?;
+            let len = u32::from_be_bytes(reader.array()?);
+            let data = reader.take(len as usize)?.to_vec();
+            sections.push(Section {
+                name,
+                kind,
+                addr,
+                data,
+            });
+        }
+        let mut symbols = Vec::new();
+        for _ in 0..reader.wide()? {
+            let name = read_name(&mut reader)?;
+            let section = match reader.wide()? {
+                UNDEFINED => None,
+                index => Some(index),
+            };
+            symbols.push(Symbol {
+                name,
+                section,
+                offset: reader.wide()?,
+                global: reader.byte()? != 0,
+            });
+        }
+        let mut relocations = Vec::new();
+        for _ in 0..reader.wide()? {
+            relocations.push(Relocation {
+                section: reader.wide()?,
+                offset: reader.wide()?,
+                symbol: reader.wide()?,
+                addend: i16::from_be_bytes(reader.array()?),
+            });
+        }
+        let object = Self {
+            sections,
+            symbols,
+            relocations,
+            entry,
+        };
+        object.validate()?;
+        Ok(object)
+    }
+}
+
+/// Names are limited to 255 bytes by their 1 byte length
+fn push_name(bytes: &mut Vec<u8>, name: &str) -> Result<(), ObjectError> {
+    let len = u8::try_from(name.len()).map_err(|_| ObjectError::NameTooLong(name.to_string()))?;
+    bytes.push(len);
+    bytes.extend_from_slice(name.as_bytes());
+    Ok(())
+}
+
+fn read_name(reader: &mut Reader) -> Result<String, ObjectError> {
+    let len = reader.byte()?;
+    let bytes = reader.take(len as usize)?;
+    String::from_utf8(bytes.to_vec()).map_err(|_| ObjectError::InvalidName)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::object::tests::test_object;
+
+    #[test]
+    fn bytes_round_trip() {
+        let mut object = test_object();
+        object.symbols[1].section = None;
+        object.relocations[0].addend = -2;
+        let actual =
+            Object::from_bytes(&object.to_bytes().expect("short names")).expect("valid object");
+        assert_eq!(object, actual);
+    }
+
+    #[test]
+    fn from_bytes_bad_magic() {
+        let err = Object::from_bytes(b"H8SS\x01").expect_err("bad magic");
+        assert!(matches!(err, ObjectError::BadMagic));
+    }
+
+    #[test]
+    fn from_bytes_unsupported_version() {
+        let mut bytes = test_object().to_bytes().expect("short names");
+        bytes[MAGIC.len()] = OBJECT_VERSION + 1;
+        let err = Object::from_bytes(&bytes).expect_err("bad version");
+        assert!(matches!(err, ObjectError::Version(v) if v == OBJECT_VERSION + 1));
+    }
+
+    #[test]
+    fn from_bytes_truncated() {
+        let bytes = test_object().to_bytes().expect("short names");
+        let err = Object::from_bytes(&bytes[..bytes.len() - 1]).expect_err("truncated");
+        assert!(matches!(err, ObjectError::Truncated));
+    }
+
+    #[test]
+    fn from_bytes_validates() {
+        let mut object = test_object();
+        object.relocations[0].symbol = 5;
+        let err = Object::from_bytes(&object.to_bytes().expect("short names"))
+            .expect_err("bad symbol index");
+        assert!(matches!(err, ObjectError::Relocation(0)));
+    }
+
+    #[test]
+    fn long_names_rejected() {
+        let mut object = test_object();
+        object.symbols[0].name = "a".repeat(255);
+        let actual = Object::from_bytes(&object.to_bytes().expect("255 bytes fit"));
+        assert_eq!(object, actual.expect("valid object"));
+
+        object.symbols[0].name = "é".repeat(128);
+        let err = object.to_bytes().expect_err("256 bytes");
+        assert!(matches!(err, ObjectError::NameTooLong(name) if name == "é".repeat(128)));
+    }
+}
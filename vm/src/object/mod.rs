@@ -0,0 +1,377 @@
+use crate::cpu::{Cpu, WideRegister};
+use crate::memory::{Bus, Device, DeviceError};
+use crate::util::{bytes::Truncated, high_and_low_value};
+
+pub use format::OBJECT_VERSION;
+
+mod format;
+
+/// Section index marking an undefined symbol in the binary format
+const UNDEFINED: u16 = u16::MAX;
+
+/// Assembled or linked program
+///
+/// Relocatable objects reference each other through global symbols. An
+/// executable has every symbol defined, and may still carry relocations which
+/// are applied when it is loaded.
+///
+/// # Binary format
+///
+/// All values are big-endian, names are a 1 byte length then UTF-8.
+///
+/// | Field       | Size                                              |
+/// |-------------|---------------------------------------------------|
+/// | magic       | 4 bytes, `H8OB`                                   |
+/// | version     | 1 byte                                            |
+/// | entry       | 1 byte has entry flag, then 2 byte address if set |
+/// | sections    | 2 byte count, then for each section:              |
+/// |             | name, 1 byte kind, 2 byte address,                |
+/// |             | 4 byte length then contents                       |
+/// | symbols     | 2 byte count, then for each symbol:               |
+/// |             | name, 2 byte section (`0xffff` if undefined),     |
+/// |             | 2 byte offset, 1 byte global flag                 |
+/// | relocations | 2 byte count, then for each relocation:           |
+/// |             | 2 byte section, 2 byte offset, 2 byte symbol,     |
+/// |             | 2 byte signed addend                              |
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Object {
+    pub sections: Vec<Section>,
+    pub symbols: Vec<Symbol>,
+    pub relocations: Vec<Relocation>,
+    /// Initial program counter, [`MemoryMapper::start`] if `None`
+    ///
+    /// [`MemoryMapper::start`]: crate::memory::MemoryMapper::start
+    pub entry: Option<u16>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Section {
+    pub name: String,
+    pub kind: SectionKind,
+    /// Load address
+    pub addr: u16,
+    pub data: Vec<u8>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum SectionKind {
+    Code = 0,
+    Data = 1,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Symbol {
+    pub name: String,
+    /// Index of the defining section, `None` if defined in another object
+    pub section: Option<u16>,
+    /// Offset from the start of the section
+    pub offset: u16,
+    /// Visible to other objects when linking
+    pub global: bool,
+}
+
+/// 16 bit big-endian address to patch with a symbol's address
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Relocation {
+    pub section: u16,
+    pub offset: u16,
+    pub symbol: u16,
+    pub addend: i16,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum ObjectError {
+    #[error("not an h8bit object")]
+    BadMagic,
+    #[error("unsupported object version: {0}")]
+    Version(u8),
+    #[error("object ends unexpectedly")]
+    Truncated,
+    #[error("invalid name")]
+    InvalidName,
+    #[error("name is longer than 255 bytes: {0}")]
+    NameTooLong(String),
+    #[error("invalid section kind: {0}")]
+    SectionKind(u8),
+    #[error("section {0} extends past the end of memory")]
+    SectionBounds(String),
+    #[error("symbol {0} refers to a missing section")]
+    SymbolSection(String),
+    #[error("invalid relocation {0}")]
+    Relocation(usize),
+    #[error("undefined symbol: {0}")]
+    Undefined(String),
+    #[error("section {section} loads at unmapped address {addr:#06x}")]
+    Unmapped { section: String, addr: u16 },
+    #[error("device error: {0}")]
+    Device(#[from] DeviceError),
+}
+
+impl From<Truncated> for ObjectError {
+    fn from(_: Truncated) -> Self {
+        Self::Truncated
+    }
+}
+
+impl SectionKind {
+    fn from_repr(repr: u8) -> Option<Self> {
+        match repr {
+            0 => Some(Self::Code),
+            1 => Some(Self::Data),
+            _ => None,
+        }
+    }
+}
+
+impl Section {
+    pub fn new(name: impl Into<String>, kind: SectionKind, addr: u16, data: Vec<u8>) -> Self {
+        Self {
+            name: name.into(),
+            kind,
+            addr,
+            data,
+        }
+    }
+
+    /// Last address the section occupies, `None` if empty
+    pub fn end(&self) -> Option<u16> {
+        let len = self.data.len().checked_sub(1)?;
+        Some(self.addr.saturating_add(len as u16))
+    }
+
+    pub fn contains(&self, addr: u16) -> bool {
+        self.end()
+            .is_some_and(|end| (self.addr..=end).contains(&addr))
+    }
+}
+
+impl Object {
+    /// Absolute address of a defined symbol
+    pub fn symbol_address(&self, name: &str) -> Option<u16> {
+        let symbol = self.symbols.iter().find(|sym| sym.name == name)?;
+        self.address_of(symbol)
+    }
+
+    /// Section containing `addr`
+    pub fn section_at(&self, addr: u16) -> Option<&Section> {
+        self.sections.iter().find(|section| section.contains(addr))
+    }
+
+    /// Check that sections fit in memory and every index is in range
+    pub fn validate(&self) -> Result<(), ObjectError> {
+        for section in &self.sections {
+            if section.addr as usize + section.data.len() > u16::MAX as usize + 1 {
+                return Err(ObjectError::SectionBounds(section.name.clone()));
+            }
+        }
+        for symbol in &self.symbols {
+            if let Some(index) = symbol.section {
+                if index as usize >= self.sections.len() {
+                    return Err(ObjectError::SymbolSection(symbol.name.clone()));
+                }
+            }
+        }
+        for (index, reloc) in self.relocations.iter().enumerate() {
+            let fits = self
+                .sections
+                .get(reloc.section as usize)
+                .is_some_and(|section| reloc.offset as usize + 2 <= section.data.len());
+            if !fits || reloc.symbol as usize >= self.symbols.len() {
+                return Err(ObjectError::Relocation(index));
+            }
+        }
+        Ok(())
+    }
+
+    /// Sections with every relocation applied
+    pub fn relocated_sections(&self) -> Result<Vec<Section>, ObjectError> {
+        self.validate()?;
+        let mut sections = self.sections.clone();
+        for reloc in &self.relocations {
+            let symbol = &self.symbols[reloc.symbol as usize];
+            let addr = self
+                .address_of(symbol)
+                .ok_or_else(|| ObjectError::Undefined(symbol.name.clone()))?
+                .wrapping_add_signed(reloc.addend);
+            let (high, low) = high_and_low_value(addr);
+            let offset = reloc.offset as usize;
+            let data = &mut sections[reloc.section as usize].data;
+            data[offset] = high;
+            data[offset + 1] = low;
+        }
+        Ok(sections)
+    }
+
+    /// Copy every section to its load address
+    ///
+    /// Sections are written through `device`, so memory must already be
+    /// mapped wherever they load. Nothing is mapped for them.
+    pub fn load_into<D: Device + ?Sized>(&self, device: &mut D) -> Result<(), ObjectError> {
+        for section in self.relocated_sections()? {
+            for (addr, &byte) in (section.addr..=u16::MAX).zip(&section.data) {
+                device.set(addr, byte).map_err(|err| match err {
+                    DeviceError::OutOfBounds(addr) => ObjectError::Unmapped {
+                        section: section.name.clone(),
+                        addr,
+                    },
+                    err => err.into(),
+                })?;
+            }
+        }
+        Ok(())
+    }
+
+    fn address_of(&self, symbol: &Symbol) -> Option<u16> {
+        let section = self.sections.get(symbol.section? as usize)?;
+        Some(section.addr.wrapping_add(symbol.offset))
+    }
+}
+
+impl<B: Bus> Cpu<B> {
+    /// Load an object's sections and jump to its entry point
+    ///
+    /// Every section must load into mapped memory, see [`Object::load_into`].
+    pub fn load_object(&mut self, object: &Object) -> Result<(), ObjectError> {
+        object.load_into(self.memory_mut())?;
+        if let Some(entry) = object.entry {
+            self.registers_mut().set_wide(WideRegister::PC, entry);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use crate::cpu::{operation::mov, Error, Register};
+    use crate::memory::{MemoryMapper, TestDevice};
+
+    pub fn test_object() -> Object {
+        // MOV_LIT_MEM 0xab, [value]; HLT
+        let code = vec![mov::lit_mem::CODE, 0xab, 0x00, 0x00, 0xff];
+        Object {
+            sections: vec![
+                Section::new("code", SectionKind::Code, 0x10, code),
+                Section::new("data", SectionKind::Data, 0x80, vec![0; 2]),
+            ],
+            symbols: vec![
+                Symbol {
+                    name: "start".to_string(),
+                    section: Some(0),
+                    offset: 0,
+                    global: true,
+                },
+                Symbol {
+                    name: "value".to_string(),
+                    section: Some(1),
+                    offset: 1,
+                    global: false,
+                },
+            ],
+            relocations: vec![Relocation {
+                section: 0,
+                offset: 2,
+                symbol: 1,
+                addend: 0,
+            }],
+            entry: Some(0x10),
+        }
+    }
+
+    fn test_cpu() -> Cpu {
+        let mut mapper = MemoryMapper::new();
+        mapper
+            .add_device(Box::new(TestDevice::new(0x100)), 0, 0xff)
+            .expect("valid range");
+        Cpu::new(mapper).expect("valid CPU")
+    }
+
+    #[test]
+    fn relocations_applied() {
+        let sections = test_object().relocated_sections().expect("valid object");
+        assert_eq!(
+            vec![mov::lit_mem::CODE, 0xab, 0x00, 0x81, 0xff],
+            sections[0].data
+        );
+    }
+
+    #[test]
+    fn relocation_addend() {
+        let mut object = test_object();
+        object.relocations[0].addend = -1;
+        let sections = object.relocated_sections().expect("valid object");
+        assert_eq!([0x00, 0x80], sections[0].data[2..4]);
+    }
+
+    #[test]
+    fn load_object_sets_entry() {
+        let mut cpu = test_cpu();
+        cpu.load_object(&test_object()).expect("valid object");
+        assert_eq!(0x10, cpu.registers().get_wide(WideRegister::PC));
+        let err = cpu.run().expect_err("halts");
+        assert!(matches!(err, Error::Halt));
+        assert_eq!(0xab, cpu.memory().get(0x81).expect("mapped"));
+        assert_eq!(0, cpu.registers().get(Register::A));
+    }
+
+    #[test]
+    fn load_object_without_entry() {
+        let mut cpu = test_cpu();
+        let object = Object {
+            entry: None,
+            ..test_object()
+        };
+        cpu.load_object(&object).expect("valid object");
+        assert_eq!(0, cpu.registers().get_wide(WideRegister::PC));
+    }
+
+    #[test]
+    fn load_undefined_symbol() {
+        let mut object = test_object();
+        object.symbols[1].section = None;
+        let err = test_cpu().load_object(&object).expect_err("undefined");
+        assert!(matches!(err, ObjectError::Undefined(name) if name == "value"));
+    }
+
+    #[test]
+    fn load_unmapped_section() {
+        let mut object = test_object();
+        object.sections[1].addr = 0x100;
+        let err = test_cpu().load_object(&object).expect_err("unmapped");
+        assert!(matches!(
+            err,
+            ObjectError::Unmapped { section, addr: 0x100 } if section == "data"
+        ));
+    }
+
+    #[test]
+    fn validate_indices() {
+        let mut object = test_object();
+        object.symbols[0].section = Some(2);
+        assert!(matches!(
+            object.validate(),
+            Err(ObjectError::SymbolSection(_))
+        ));
+
+        let mut object = test_object();
+        object.relocations[0].offset = 4;
+        assert!(matches!(object.validate(), Err(ObjectError::Relocation(0))));
+
+        let mut object = test_object();
+        object.sections[0].addr = 0xfffe;
+        assert!(matches!(
+            object.validate(),
+            Err(ObjectError::SectionBounds(_))
+        ));
+    }
+
+    #[test]
+    fn symbols_and_sections() {
+        let object = test_object();
+        assert_eq!(Some(0x81), object.symbol_address("value"));
+        assert_eq!(None, object.symbol_address("missing"));
+        assert_eq!("code", object.section_at(0x14).expect("in code").name);
+        assert!(object.section_at(0x15).is_none());
+    }
+}
@@ -0,0 +1,151 @@
+use h8bit_asm::Program;
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::iter;
+use std::path::{Path, PathBuf};
+
+/// Mapping from instruction addresses to lines of an assembly source file
+///
+/// Read from JSON of the form
+/// `{"source": "prog.asm", "lines": [[address, line], ...]}`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SourceMap {
+    source: PathBuf,
+    lines: BTreeMap<u16, u32>,
+}
+
+impl SourceMap {
+    pub fn new(source: impl Into<PathBuf>) -> Self {
+        Self {
+            source: source.into(),
+            lines: BTreeMap::new(),
+        }
+    }
+
+    pub fn from_json(json: &Value) -> Option<Self> {
+        let mut map = Self::new(json.get("source")?.as_str()?);
+        for entry in json.get("lines")?.as_array()? {
+            let addr = entry.get(0)?.as_u64()?.try_into().ok()?;
+            let line = entry.get(1)?.as_u64()?.try_into().ok()?;
+            map.insert(addr, line);
+        }
+        Some(map)
+    }
+
+    /// Lines of `program` in `source`, the file it was assembled from
+    ///
+    /// Statements from included files take the line of the `.include` in
+    /// `source`.
+    pub fn from_program(source: impl Into<PathBuf>, program: &Program) -> Self {
+        let mut map = Self::new(source);
+        for (addr, location) in &program.lines {
+            let line = iter::once(&location.at)
+                .chain(location.via.iter().map(|(_, position)| position))
+                .find(|position| position.file == map.source)
+                .and_then(|position| u32::try_from(position.line).ok());
+            if let Some(line) = line {
+                map.insert(*addr, line);
+            }
+        }
+        map
+    }
+
+    pub fn insert(&mut self, addr: u16, line: u32) {
+        self.lines.insert(addr, line);
+    }
+
+    pub fn source(&self) -> &Path {
+        &self.source
+    }
+
+    /// Whether `path` refers to this map's source file
+    pub fn is_source(&self, path: &Path) -> bool {
+        path.ends_with(&self.source)
+    }
+
+    /// Instruction addresses in order, with their lines
+    pub fn lines(&self) -> impl Iterator<Item = (u16, u32)> + '_ {
+        self.lines.iter().map(|(&addr, &line)| (addr, line))
+    }
+
+    /// Line of the instruction containing `addr`
+    pub fn line_for(&self, addr: u16) -> Option<u32> {
+        self.lines.range(..=addr).next_back().map(|(_, &line)| line)
+    }
+
+    /// First instruction on `line`
+    pub fn address_for(&self, line: u32) -> Option<u16> {
+        self.lines
+            .iter()
+            .find(|(_, &l)| l == line)
+            .map(|(&addr, _)| addr)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use h8bit_asm::assemble::{Location, Position, Via};
+    use serde_json::json;
+
+    #[test]
+    fn from_json() {
+        let map = SourceMap::from_json(&json!({
+            "source": "prog.asm",
+            "lines": [[0, 1], [3, 2], [7, 4]],
+        }))
+        .expect("valid map");
+        assert_eq!(Some(1), map.line_for(0));
+        assert_eq!(Some(1), map.line_for(2));
+        assert_eq!(Some(2), map.line_for(3));
+        assert_eq!(Some(7), map.address_for(4));
+        assert_eq!(None, map.address_for(3));
+        assert!(map.is_source(Path::new("/home/user/prog.asm")));
+    }
+
+    #[test]
+    fn from_program() {
+        let position = |file: &str, line| Position {
+            file: file.into(),
+            line,
+        };
+        let program = Program {
+            bytes: Vec::new(),
+            lines: vec![
+                (
+                    0,
+                    Location {
+                        at: position("prog.asm", 1),
+                        via: Vec::new(),
+                    },
+                ),
+                (
+                    2,
+                    Location {
+                        at: position("lib.asm", 4),
+                        via: vec![(Via::Include, position("prog.asm", 2))],
+                    },
+                ),
+                (
+                    5,
+                    Location {
+                        at: position("prog.asm", 6),
+                        via: vec![(Via::Macro("m".to_string()), position("prog.asm", 9))],
+                    },
+                ),
+            ],
+        };
+        let map = SourceMap::from_program("prog.asm", &program);
+        assert_eq!(
+            vec![(0, 1), (2, 2), (5, 6)],
+            map.lines().collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn from_json_invalid() {
+        assert_eq!(None, SourceMap::from_json(&json!({"source": "prog.asm"})));
+        let json = json!({"source": "prog.asm", "lines": [[0x10000, 1]]});
+        assert_eq!(None, SourceMap::from_json(&json));
+    }
+}
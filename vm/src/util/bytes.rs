@@ -0,0 +1,72 @@
+use super::{high_and_low_value, wide_value};
+
+/// Input ended before a value could be read
+#[derive(Debug, thiserror::Error)]
+#[error("input ends unexpectedly")]
+pub struct Truncated;
+
+/// Append a big-endian wide value
+pub fn push_wide(bytes: &mut Vec<u8>, value: u16) {
+    let (high, low) = high_and_low_value(value);
+    bytes.push(high);
+    bytes.push(low);
+}
+
+/// Reads big-endian values from the front of a byte slice
+#[derive(Debug)]
+pub struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+    pub fn new(bytes: &'a [u8]) -> Self {
+        Self(bytes)
+    }
+
+    pub fn take(&mut self, len: usize) -> Result<&'a [u8], Truncated> {
+        if len > self.0.len() {
+            return Err(Truncated);
+        }
+        let (taken, rest) = self.0.split_at(len);
+        self.0 = rest;
+        Ok(taken)
+    }
+
+    pub fn array<const N: usize>(&mut self) -> Result<[u8; N], Truncated> {
+        let mut array = [0; N];
+        array.copy_from_slice(self.take(N)?);
+        Ok(array)
+    }
+
+    pub fn byte(&mut self) -> Result<u8, Truncated> {
+        Ok(self.take(1)?[0])
+    }
+
+    pub fn wide(&mut self) -> Result<u16, Truncated> {
+        let [high, low] = self.array()?;
+        Ok(wide_value(high, low))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reader_values() {
+        let mut bytes = vec![0x01];
+        push_wide(&mut bytes, 0x0203);
+        bytes.extend_from_slice(&[0x04, 0x05, 0x06]);
+        let mut reader = Reader::new(&bytes);
+        assert_eq!(0x01, reader.byte().expect("byte"));
+        assert_eq!(0x0203, reader.wide().expect("wide"));
+        assert_eq!([0x04, 0x05], reader.array().expect("array"));
+        assert_eq!(&[0x06], reader.take(1).expect("slice"));
+    }
+
+    #[test]
+    fn reader_truncated() {
+        let mut reader = Reader::new(&[0x01]);
+        assert!(reader.wide().is_err());
+        assert_eq!(0x01, reader.byte().expect("failed reads take nothing"));
+        assert!(reader.byte().is_err());
+    }
+}
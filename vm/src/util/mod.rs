@@ -1,3 +1,5 @@
+pub mod bytes;
+
 /// Get upper and lower byte of a [`u16`](std::primitive::u16)
 pub fn high_and_low_value(value: u16) -> (u8, u8) {
     let high_val = ((value & 0xff00) >> 8) as u8;